@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS "recruitments_start_at_idx";
+DROP INDEX IF EXISTS "recruitments_status_id_idx";
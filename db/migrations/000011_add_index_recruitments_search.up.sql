@@ -0,0 +1,2 @@
+CREATE INDEX "recruitments_status_id_idx" ON "recruitments"("status", "id" DESC);
+CREATE INDEX "recruitments_start_at_idx" ON "recruitments"("start_at");
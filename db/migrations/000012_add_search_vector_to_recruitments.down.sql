@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS "recruitments_search_vector_idx";
+ALTER TABLE "recruitments" DROP COLUMN IF EXISTS "search_vector";
+DROP FUNCTION IF EXISTS search_ngram_query(TEXT);
+DROP FUNCTION IF EXISTS search_ngram_vector(TEXT, TEXT);
+DROP FUNCTION IF EXISTS search_quote(TEXT);
+DROP FUNCTION IF EXISTS search_normalize(TEXT);
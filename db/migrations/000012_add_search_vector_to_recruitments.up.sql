@@ -0,0 +1,51 @@
+-- 全角半角(NFKC)、カタカナとひらがな、大文字と小文字の違いを吸収する
+CREATE OR REPLACE FUNCTION search_normalize(body TEXT) RETURNS TEXT AS $$
+  SELECT lower(translate(
+    normalize(COALESCE(body, ''), NFKC),
+    'ァアィイゥウェエォオカガキギクグケゲコゴサザシジスズセゼソゾタダチヂッツヅテデトドナニヌネノハバパヒビピフブプヘベペホボポマミムメモャヤュユョヨラリルレロヮワヰヱヲンヴヵヶ',
+    'ぁあぃいぅうぇえぉおかがきぎくぐけげこごさざしじすずせぜそぞただちぢっつづてでとどなにぬねのはばぱひびぴふぶぷへべぺほぼぽまみむめもゃやゅゆょよらりるれろゎわゐゑをんゔゕゖ'
+  ))
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
+
+-- tsvector/tsqueryのリテラルとしてクォートする
+CREATE OR REPLACE FUNCTION search_quote(token TEXT) RETURNS TEXT AS $$
+  SELECT '''' || replace(replace(token, '\', '\\'), '''', '''''') || ''''
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
+
+-- 正規化した文字列をbigramに分割してtsvectorにする
+-- 位置は文字の位置なので隣り合うbigramは <-> で検索できる 単語の最後の1文字はunigramにする
+CREATE OR REPLACE FUNCTION search_ngram_vector(body TEXT, weight TEXT) RETURNS TSVECTOR AS $$
+  SELECT COALESCE(
+    string_agg(
+      search_quote(regexp_replace(substr(s, i, 2), '\s+$', '')) || ':' || LEAST(i, 16383) || weight,
+      ' '
+    )::tsvector,
+    ''::tsvector
+  )
+  FROM search_normalize(body) AS s,
+    generate_series(1, char_length(s)) AS i
+  WHERE substr(s, i, 1) !~ '\s'
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
+
+-- キーワードを空白で区切り 単語内のbigramは <-> 単語同士は & でつなぐ
+-- 1文字の単語は前方一致にする
+CREATE OR REPLACE FUNCTION search_ngram_query(keyword TEXT) RETURNS TSQUERY AS $$
+  SELECT string_agg('(' || w.phrase || ')', ' & ')::tsquery
+  FROM (
+    SELECT
+      CASE
+        WHEN char_length(word) = 1 THEN search_quote(word) || ':*'
+        ELSE (
+          SELECT string_agg(search_quote(substr(word, i, 2)), ' <-> ' ORDER BY i)
+          FROM generate_series(1, char_length(word) - 1) AS i
+        )
+      END AS phrase
+    FROM regexp_split_to_table(search_normalize(keyword), '\s+') AS word
+    WHERE word <> ''
+  ) AS w
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
+
+ALTER TABLE "recruitments" ADD COLUMN "search_vector" TSVECTOR GENERATED ALWAYS AS (
+  search_ngram_vector("title", 'A') || search_ngram_vector("detail", 'B')
+) STORED;
+CREATE INDEX "recruitments_search_vector_idx" ON "recruitments" USING GIN ("search_vector");
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS "recruitments_venue_earth_idx";
+DROP EXTENSION IF EXISTS earthdistance;
+DROP EXTENSION IF EXISTS cube;
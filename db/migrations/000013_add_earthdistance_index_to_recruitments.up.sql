@@ -0,0 +1,5 @@
+CREATE EXTENSION IF NOT EXISTS cube;
+CREATE EXTENSION IF NOT EXISTS earthdistance;
+
+CREATE INDEX "recruitments_venue_earth_idx" ON "recruitments" USING GIST (ll_to_earth("venue_lat", "venue_lng"))
+  WHERE "venue_lat" IS NOT NULL AND "venue_lng" IS NOT NULL;
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS "recruitments_closing_at_idx";
+ALTER TABLE "recruitments" DROP COLUMN IF EXISTS "closed_reason";
+ALTER TABLE "recruitments" DROP COLUMN IF EXISTS "closed_at";
+DROP TYPE IF EXISTS recruitment_closed_reason;
@@ -0,0 +1,6 @@
+CREATE TYPE recruitment_closed_reason AS ENUM ('expired', 'manual');
+
+ALTER TABLE "recruitments" ADD COLUMN "closed_at" TIMESTAMP WITH TIME ZONE NULL;
+ALTER TABLE "recruitments" ADD COLUMN "closed_reason" recruitment_closed_reason NULL;
+-- 掲載期限切れの募集を探すためのインデックス
+CREATE INDEX "recruitments_closing_at_idx" ON "recruitments"("closing_at") WHERE "status" = 'published';
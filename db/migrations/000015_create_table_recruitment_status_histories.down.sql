@@ -0,0 +1 @@
+DROP TABLE IF EXISTS "recruitment_status_histories";
\ No newline at end of file
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS "recruitment_status_histories"(
+  "id" BIGSERIAL PRIMARY KEY,
+  "recruitment_id" BIGINT NOT NULL,
+  "from_status" recruitment_status NOT NULL,
+  "to_status" recruitment_status NOT NULL,
+  "user_id" BIGINT NULL,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("recruitment_id") 
+    REFERENCES "recruitments"("id")
+    ON DELETE CASCADE,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE SET NULL
+);
+CREATE INDEX ON "recruitment_status_histories"("recruitment_id");
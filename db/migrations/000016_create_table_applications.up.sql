@@ -0,0 +1,19 @@
+CREATE TYPE application_status AS ENUM ('pending', 'accepted', 'rejected', 'withdrawn');
+
+CREATE TABLE IF NOT EXISTS "applications"(
+  "id" BIGSERIAL PRIMARY KEY,
+  "recruitment_id" BIGINT NOT NULL,
+  "user_id" BIGINT NOT NULL,
+  "message" VARCHAR(1000) NOT NULL,
+  "status" application_status NOT NULL DEFAULT 'pending',
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("recruitment_id") 
+    REFERENCES "recruitments"("id")
+    ON DELETE CASCADE,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE,
+  UNIQUE("recruitment_id", "user_id")
+);
+CREATE INDEX ON "applications"("user_id");
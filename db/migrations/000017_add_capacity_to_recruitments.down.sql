@@ -0,0 +1,19 @@
+DROP INDEX IF EXISTS "applications_recruitment_id_status_idx";
+ALTER TABLE "applications" DROP COLUMN IF EXISTS "waitlisted_at";
+ALTER TABLE "recruitments" DROP COLUMN IF EXISTS "close_when_full";
+ALTER TABLE "recruitments" DROP COLUMN IF EXISTS "capacity";
+
+-- enumの値は削除できないので作り直す
+UPDATE "applications" SET "status" = 'pending' WHERE "status" = 'waitlisted';
+ALTER TYPE application_status RENAME TO application_status_old;
+CREATE TYPE application_status AS ENUM ('pending', 'accepted', 'rejected', 'withdrawn');
+ALTER TABLE "applications" ALTER COLUMN "status" DROP DEFAULT;
+ALTER TABLE "applications" ALTER COLUMN "status" TYPE application_status USING "status"::text::application_status;
+ALTER TABLE "applications" ALTER COLUMN "status" SET DEFAULT 'pending';
+DROP TYPE application_status_old;
+
+UPDATE "recruitments" SET "closed_reason" = 'manual' WHERE "closed_reason" = 'full';
+ALTER TYPE recruitment_closed_reason RENAME TO recruitment_closed_reason_old;
+CREATE TYPE recruitment_closed_reason AS ENUM ('expired', 'manual');
+ALTER TABLE "recruitments" ALTER COLUMN "closed_reason" TYPE recruitment_closed_reason USING "closed_reason"::text::recruitment_closed_reason;
+DROP TYPE recruitment_closed_reason_old;
@@ -0,0 +1,8 @@
+ALTER TYPE application_status ADD VALUE IF NOT EXISTS 'waitlisted';
+ALTER TYPE recruitment_closed_reason ADD VALUE IF NOT EXISTS 'full';
+
+ALTER TABLE "recruitments" ADD COLUMN "capacity" INTEGER NULL CHECK ("capacity" > 0);
+ALTER TABLE "recruitments" ADD COLUMN "close_when_full" BOOLEAN NOT NULL DEFAULT FALSE;
+-- キャンセル待ちの順番
+ALTER TABLE "applications" ADD COLUMN "waitlisted_at" TIMESTAMP WITH TIME ZONE NULL;
+CREATE INDEX ON "applications"("recruitment_id", "status");
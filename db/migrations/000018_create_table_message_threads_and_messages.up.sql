@@ -0,0 +1,40 @@
+-- 募集者と応募者のやり取り 募集と応募者の組み合わせごとに1つ
+CREATE TABLE IF NOT EXISTS "message_threads"(
+  "id" BIGSERIAL PRIMARY KEY,
+  "recruitment_id" BIGINT NOT NULL,
+  "owner_id" BIGINT NOT NULL,
+  "applicant_id" BIGINT NOT NULL,
+  "last_message_id" BIGINT NOT NULL DEFAULT 0,
+  "owner_last_read_message_id" BIGINT NOT NULL DEFAULT 0,
+  "applicant_last_read_message_id" BIGINT NOT NULL DEFAULT 0,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("recruitment_id") 
+    REFERENCES "recruitments"("id")
+    ON DELETE CASCADE,
+  FOREIGN KEY("owner_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE,
+  FOREIGN KEY("applicant_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE,
+  UNIQUE("recruitment_id", "applicant_id")
+);
+CREATE INDEX ON "message_threads"("recruitment_id", "last_message_id" DESC, "id" DESC);
+CREATE INDEX ON "message_threads"("owner_id");
+CREATE INDEX ON "message_threads"("applicant_id");
+
+CREATE TABLE IF NOT EXISTS "messages"(
+  "id" BIGSERIAL PRIMARY KEY,
+  "thread_id" BIGINT NOT NULL,
+  "user_id" BIGINT NOT NULL,
+  "body" VARCHAR(2000) NOT NULL,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("thread_id") 
+    REFERENCES "message_threads"("id")
+    ON DELETE CASCADE,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE
+);
+CREATE INDEX ON "messages"("thread_id", "id" DESC);
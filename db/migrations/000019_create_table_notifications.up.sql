@@ -0,0 +1,34 @@
+CREATE TYPE notification_kind AS ENUM ('followed', 'stocked', 'applied', 'application_accepted', 'application_rejected');
+
+-- 未読の間は同じ種類と募集の通知を1つにまとめ、行動したユーザーをnotification_actorsに追加する
+CREATE TABLE IF NOT EXISTS "notifications"(
+  "id" BIGSERIAL PRIMARY KEY,
+  "user_id" BIGINT NOT NULL,
+  "kind" notification_kind NOT NULL,
+  "recruitment_id" BIGINT,
+  "sort_key" BIGSERIAL NOT NULL,
+  "read_at" TIMESTAMP WITH TIME ZONE,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE,
+  FOREIGN KEY("recruitment_id") 
+    REFERENCES "recruitments"("id")
+    ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX "notifications_unread_group_idx" ON "notifications"("user_id", "kind", COALESCE("recruitment_id", 0)) WHERE "read_at" IS NULL;
+CREATE INDEX ON "notifications"("user_id", "sort_key" DESC, "id" DESC);
+
+CREATE TABLE IF NOT EXISTS "notification_actors"(
+  "notification_id" BIGINT NOT NULL,
+  "user_id" BIGINT NOT NULL,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("notification_id") 
+    REFERENCES "notifications"("id")
+    ON DELETE CASCADE,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE,
+  PRIMARY KEY("notification_id", "user_id")
+);
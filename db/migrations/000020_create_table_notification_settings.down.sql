@@ -0,0 +1,13 @@
+DROP TABLE IF EXISTS "digest_deliveries";
+DROP TABLE IF EXISTS "notification_settings";
+DROP TYPE IF EXISTS digest_frequency;
+DROP TYPE IF EXISTS notification_event;
+
+-- enumの値は削除できないので作り直す
+DELETE FROM "notifications" WHERE "kind" IN ('recruitment_published', 'stock_closing');
+DROP INDEX IF EXISTS "notifications_unread_group_idx";
+ALTER TYPE notification_kind RENAME TO notification_kind_old;
+CREATE TYPE notification_kind AS ENUM ('followed', 'stocked', 'applied', 'application_accepted', 'application_rejected');
+ALTER TABLE "notifications" ALTER COLUMN "kind" TYPE notification_kind USING "kind"::text::notification_kind;
+DROP TYPE notification_kind_old;
+CREATE UNIQUE INDEX "notifications_unread_group_idx" ON "notifications"("user_id", "kind", COALESCE("recruitment_id", 0)) WHERE "read_at" IS NULL;
@@ -0,0 +1,30 @@
+ALTER TYPE notification_kind ADD VALUE IF NOT EXISTS 'recruitment_published';
+ALTER TYPE notification_kind ADD VALUE IF NOT EXISTS 'stock_closing';
+
+CREATE TYPE notification_event AS ENUM ('application', 'followed_recruitment', 'closing_stock');
+CREATE TYPE digest_frequency AS ENUM ('off', 'daily', 'weekly');
+
+-- 行がなければ既定の設定(アプリ内通知あり、毎日のまとめメール)として扱う
+CREATE TABLE IF NOT EXISTS "notification_settings"(
+  "user_id" BIGINT NOT NULL,
+  "event" notification_event NOT NULL,
+  "in_app" BOOLEAN NOT NULL,
+  "email_frequency" digest_frequency NOT NULL,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE,
+  PRIMARY KEY("user_id", "event")
+);
+
+-- まとめメールを最後に送った(送る内容がなかった場合も含む)日時
+CREATE TABLE IF NOT EXISTS "digest_deliveries"(
+  "user_id" BIGINT NOT NULL,
+  "frequency" digest_frequency NOT NULL,
+  "delivered_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE,
+  PRIMARY KEY("user_id", "frequency")
+);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS "recruitments_series_id_series_index_idx";
+ALTER TABLE "recruitments" DROP COLUMN IF EXISTS "series_index";
+ALTER TABLE "recruitments" DROP COLUMN IF EXISTS "series_id";
+DROP TABLE IF EXISTS "recruitment_series";
+DROP TYPE IF EXISTS recurrence_frequency;
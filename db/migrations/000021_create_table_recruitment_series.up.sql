@@ -0,0 +1,19 @@
+CREATE TYPE recurrence_frequency AS ENUM ('weekly', 'biweekly', 'monthly');
+
+CREATE TABLE IF NOT EXISTS "recruitment_series"(
+  "id" BIGSERIAL PRIMARY KEY,
+  "user_id" BIGINT NOT NULL,
+  "frequency" recurrence_frequency NOT NULL,
+  "until" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE
+);
+CREATE INDEX ON "recruitment_series"("user_id");
+
+-- 繰り返しで作成された募集は何回目の開催かを持つ
+ALTER TABLE "recruitments" ADD COLUMN "series_id" BIGINT NULL REFERENCES "recruitment_series"("id") ON DELETE SET NULL;
+ALTER TABLE "recruitments" ADD COLUMN "series_index" INTEGER NULL;
+CREATE UNIQUE INDEX "recruitments_series_id_series_index_idx" ON "recruitments"("series_id", "series_index");
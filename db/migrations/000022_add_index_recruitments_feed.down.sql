@@ -0,0 +1 @@
+DROP INDEX IF EXISTS "recruitments_user_id_published_at_idx";
@@ -0,0 +1,2 @@
+-- フォローしているユーザーごとに新しい公開中の募集を取り出すためのインデックス
+CREATE INDEX "recruitments_user_id_published_at_idx" ON "recruitments"("user_id", "published_at" DESC, "id" DESC) WHERE "status" = 'published';
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS "recruitment_daily_views";
+DROP TABLE IF EXISTS "recruitment_views";
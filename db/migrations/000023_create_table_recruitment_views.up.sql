@@ -0,0 +1,26 @@
+-- 募集の詳細の閲覧 同じ閲覧者の同じ日の閲覧は1回として数える
+CREATE TABLE IF NOT EXISTS "recruitment_views"(
+  "id" BIGSERIAL PRIMARY KEY,
+  "recruitment_id" BIGINT NOT NULL,
+  -- ログインユーザーは'user:ID' 未ログインは接続元から作った'anon:ハッシュ'
+  "viewer_key" TEXT NOT NULL,
+  "viewed_on" DATE NOT NULL,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("recruitment_id") 
+    REFERENCES "recruitments"("id")
+    ON DELETE CASCADE,
+  UNIQUE("recruitment_id", "viewed_on", "viewer_key")
+);
+CREATE INDEX ON "recruitment_views"("viewed_on");
+
+-- 閲覧数を日ごとに集計したもの
+CREATE TABLE IF NOT EXISTS "recruitment_daily_views"(
+  "recruitment_id" BIGINT NOT NULL,
+  "viewed_on" DATE NOT NULL,
+  "view_count" BIGINT NOT NULL,
+  "updated_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("recruitment_id") 
+    REFERENCES "recruitments"("id")
+    ON DELETE CASCADE,
+  PRIMARY KEY("recruitment_id", "viewed_on")
+);
@@ -0,0 +1,30 @@
+ALTER TYPE notification_kind ADD VALUE IF NOT EXISTS 'recruitment_changed';
+
+-- 募集を更新するたびに保存する更新後の募集の内容
+-- 最初の更新では比較できるように更新前の内容も保存する
+CREATE TABLE IF NOT EXISTS "recruitment_revisions"(
+  "id" BIGSERIAL PRIMARY KEY,
+  "recruitment_id" BIGINT NOT NULL,
+  "user_id" BIGINT NULL,
+  "title" VARCHAR(60) NOT NULL,
+  "category" recruitment_category NOT NULL,
+  "venue" VARCHAR NULL,
+  "venue_lat" DOUBLE PRECISION NULL,
+  "venue_lng" DOUBLE PRECISION NULL,
+  "start_at" TIMESTAMP WITH TIME ZONE NULL,
+  "closing_at" TIMESTAMP WITH TIME ZONE NULL,
+  "detail" VARCHAR(10000) NULL,
+  "sport_id" BIGINT NOT NULL,
+  "prefecture_id" BIGINT NOT NULL,
+  "capacity" INTEGER NULL,
+  "close_when_full" BOOLEAN NOT NULL,
+  "tag_ids" BIGINT[] NOT NULL,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("recruitment_id") 
+    REFERENCES "recruitments"("id")
+    ON DELETE CASCADE,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE SET NULL
+);
+CREATE INDEX ON "recruitment_revisions"("recruitment_id", "id");
@@ -0,0 +1,9 @@
+-- カレンダーアプリから購読するURLに含める秘密のトークン 作り直すと古いURLは使えなくなる
+CREATE TABLE IF NOT EXISTS "calendar_tokens"(
+  "user_id" BIGINT PRIMARY KEY,
+  "token" VARCHAR NOT NULL UNIQUE,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+  FOREIGN KEY("user_id") 
+    REFERENCES "users"("id")
+    ON DELETE CASCADE
+);
@@ -183,6 +183,12 @@ async fn create_recruitments(pool: &PgPool) -> Result<()> {
         venue_lng: None,
         status: RecruitmentStatus::Published,
         published_at: Some(now),
+        closed_at: None,
+        closed_reason: None,
+        capacity: None,
+        close_when_full: false,
+        series_id: None,
+        series_index: None,
         created_at: now,
         user_id: users.get(rng.gen_range(0..users.len())).unwrap().id,
         sport_id: sports.get(rng.gen_range(0..sports.len())).unwrap().id,
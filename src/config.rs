@@ -1,7 +1,14 @@
+use std::net::IpAddr;
+
 use anyhow::Result;
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 
+// ブラウザから接続してよいフロントエンドのオリジン
+pub const FRONTEND_ORIGIN: &str = "http://localhost:5173";
+// メールに載せるリンクなどに使うAPIサーバーのオリジン
+pub const API_ORIGIN: &str = "http://localhost:8080";
+
 static CONFIG: Lazy<Config> = Lazy::new(|| Config::new().expect("Unable to retrieve config"));
 
 #[derive(Deserialize, Debug)]
@@ -28,11 +35,34 @@ pub struct Line {
     pub callback_url: String,
 }
 
+// 配信停止のリンクのトークンに署名する鍵 ログインのトークンとは別の鍵にする
+#[derive(Deserialize, Debug)]
+pub struct Unsubscribe {
+    pub secret: String,
+}
+
+// 未ログインの閲覧者を区別するハッシュの鍵 接続元を推測されないように秘密にする
+#[derive(Deserialize, Debug)]
+pub struct View {
+    pub fingerprint_secret: String,
+}
+
+// 前段のリバースプロキシ 環境変数PROXY_TRUSTED_IPSにカンマ区切りで指定する
+// 指定したプロキシからの接続のみX-Forwarded-Forを信用する
+#[derive(Deserialize, Debug)]
+pub struct Proxy {
+    #[serde(default)]
+    pub trusted_ips: Vec<IpAddr>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub database: Database,
     pub google: Google,
     pub line: Line,
+    pub unsubscribe: Unsubscribe,
+    pub view: View,
+    pub proxy: Proxy,
 }
 
 impl Config {
@@ -40,11 +70,17 @@ impl Config {
         let database = envy::prefixed("POSTGRES_").from_env::<Database>()?;
         let google = envy::prefixed("GOOGLE_").from_env::<Google>()?;
         let line = envy::prefixed("LINE_").from_env::<Line>()?;
+        let unsubscribe = envy::prefixed("UNSUBSCRIBE_").from_env::<Unsubscribe>()?;
+        let view = envy::prefixed("VIEW_").from_env::<View>()?;
+        let proxy = envy::prefixed("PROXY_").from_env::<Proxy>()?;
 
         let config = Config {
             database,
             google,
             line,
+            unsubscribe,
+            view,
+            proxy,
         };
         Ok(config)
     }
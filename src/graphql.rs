@@ -1,13 +1,19 @@
-use anyhow::Result;
-use async_graphql::{Context, EmptySubscription, Guard, MergedObject, Schema, ID};
+use std::str::FromStr;
+
+use async_graphql::{
+    Context, ErrorExtensions, Guard, MergedObject, MergedSubscription, Schema, ID,
+};
 use async_trait::async_trait;
 use base64::{decode_config, encode_config, URL_SAFE};
 
 use self::{
     auth::get_viewer,
     resolvers::{
+        application_resolver::{ApplicationMutation, ApplicationSubscription},
+        message_resolver::{MessageMutation, MessageSubscription},
+        notification_resolver::NotificationMutation,
         prefecture_resolver::PrefectureQuery,
-        recruitment_resolver::{RecruitmentMutation, RecruitmentQuery},
+        recruitment_resolver::{RecruitmentMutation, RecruitmentQuery, RecruitmentSubscription},
         sport_resolver::SportQuery,
         stock_resolver::StockMutation,
         tag_resolver::{TagMutation, TagQuery},
@@ -17,11 +23,14 @@ use self::{
 };
 
 pub mod auth;
+pub mod broker;
+pub mod feed;
 pub mod loader;
 pub mod mail;
 pub mod models;
 pub mod mutations;
 pub mod resolvers;
+pub mod share;
 pub mod utils;
 
 #[derive(MergedObject, Default)]
@@ -39,20 +48,117 @@ pub struct Mutation(
     RecruitmentMutation,
     TagMutation,
     StockMutation,
+    ApplicationMutation,
+    MessageMutation,
+    NotificationMutation,
+);
+
+#[derive(MergedSubscription, Default)]
+pub struct Subscription(
+    MessageSubscription,
+    ApplicationSubscription,
+    RecruitmentSubscription,
 );
 
-pub type GraphqlSchema = Schema<Query, Mutation, EmptySubscription>;
+pub type GraphqlSchema = Schema<Query, Mutation, Subscription>;
+
+//* Global ID */
+/// グローバルIDに含まれるオブジェクトのタイプ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeType {
+    Prefecture,
+    Sport,
+    Tag,
+    User,
+    Recruitment,
+    Application,
+    MessageThread,
+    Message,
+    Notification,
+    RecruitmentSeries,
+    RecruitmentRevision,
+}
 
-pub fn id_encode(name: &str, id: i64) -> String {
-    encode_config(format!("{}:{}", name, id), base64::URL_SAFE)
+impl NodeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeType::Prefecture => "Prefecture",
+            NodeType::Sport => "Sport",
+            NodeType::Tag => "Tag",
+            NodeType::User => "User",
+            NodeType::Recruitment => "Recruitment",
+            NodeType::Application => "Application",
+            NodeType::MessageThread => "MessageThread",
+            NodeType::Message => "Message",
+            NodeType::Notification => "Notification",
+            NodeType::RecruitmentSeries => "RecruitmentSeries",
+            NodeType::RecruitmentRevision => "RecruitmentRevision",
+        }
+    }
+}
+
+impl FromStr for NodeType {
+    type Err = IdDecodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Prefecture" => Ok(NodeType::Prefecture),
+            "Sport" => Ok(NodeType::Sport),
+            "Tag" => Ok(NodeType::Tag),
+            "User" => Ok(NodeType::User),
+            "Recruitment" => Ok(NodeType::Recruitment),
+            "Application" => Ok(NodeType::Application),
+            "MessageThread" => Ok(NodeType::MessageThread),
+            "Message" => Ok(NodeType::Message),
+            "Notification" => Ok(NodeType::Notification),
+            "RecruitmentSeries" => Ok(NodeType::RecruitmentSeries),
+            "RecruitmentRevision" => Ok(NodeType::RecruitmentRevision),
+            _ => Err(IdDecodeError::UnknownType(s.to_string())),
+        }
+    }
 }
 
-pub fn id_decode(id: &ID) -> Result<i64> {
-    let bytes = decode_config(id.as_bytes(), URL_SAFE)?;
-    let s = String::from_utf8(bytes)?;
-    let split_id: Vec<&str> = s.split(':').collect();
-    let decoded_id: i64 = split_id[1].parse()?;
-    Ok(decoded_id)
+#[derive(Debug, thiserror::Error)]
+pub enum IdDecodeError {
+    #[error("IDの形式が正しくありません")]
+    Malformed,
+    #[error("IDのタイプが正しくありません: {0}")]
+    UnknownType(String),
+    #[error("{expected}のIDを指定してください")]
+    TypeMismatch { expected: &'static str },
+}
+
+impl ErrorExtensions for IdDecodeError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string())
+            .extend_with(|_, e| e.set("code", "BAD_USER_INPUT"))
+    }
+}
+
+pub fn id_encode(node_type: NodeType, id: i64) -> String {
+    encode_config(format!("{}:{}", node_type.as_str(), id), URL_SAFE)
+}
+
+// "Type:id"の形からタイプとIDを取り出す
+pub fn global_id_decode(id: &ID) -> Result<(NodeType, i64), IdDecodeError> {
+    let bytes = decode_config(id.as_bytes(), URL_SAFE).map_err(|_| IdDecodeError::Malformed)?;
+    let s = String::from_utf8(bytes).map_err(|_| IdDecodeError::Malformed)?;
+    let (type_name, decoded_id) = s.split_once(':').ok_or(IdDecodeError::Malformed)?;
+    let node_type = type_name.parse::<NodeType>()?;
+    let decoded_id = decoded_id
+        .parse::<i64>()
+        .map_err(|_| IdDecodeError::Malformed)?;
+    Ok((node_type, decoded_id))
+}
+
+// 期待しているタイプのIDでなければエラーを返す
+pub fn id_decode(id: &ID, expected: NodeType) -> Result<i64, IdDecodeError> {
+    match global_id_decode(id)? {
+        (node_type, decoded_id) if node_type == expected => Ok(decoded_id),
+        _ => Err(IdDecodeError::TypeMismatch {
+            expected: expected.as_str(),
+        }),
+    }
 }
 
 // todo ディレクトリ作ってそこでガード
@@ -8,7 +8,10 @@ use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData,
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
-use crate::graphql::models::user::{get_user_from_id, User};
+use crate::{
+    config::get_config,
+    graphql::models::user::{get_user_from_id, User},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -51,6 +54,49 @@ pub fn token_decode(token: String) -> Result<TokenData<Claims>> {
     }
 }
 
+// まとめメールの配信停止リンクに含めるトークン
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnsubscribeClaims {
+    pub exp: i64,
+    pub iat: i64,
+    pub sub: String,
+}
+
+// メールを後から開いても配信停止できるように長めにする
+const UNSUBSCRIBE_TOKEN_DAYS: i64 = 365;
+
+// ログインのトークンとして使えないように環境変数UNSUBSCRIBE_SECRETの別の鍵で署名する
+pub fn unsubscribe_token_encode(user_id: i64) -> Result<String> {
+    let claims = UnsubscribeClaims {
+        exp: Local::now()
+            .add(Duration::days(UNSUBSCRIBE_TOKEN_DAYS))
+            .timestamp(),
+        iat: Local::now().timestamp(),
+        sub: user_id.to_string(),
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(get_config().unsubscribe.secret.as_ref()),
+    )?;
+    Ok(token)
+}
+
+// 配信停止するユーザーのIDを取り出す
+pub fn unsubscribe_token_decode(token: &str) -> Result<i64> {
+    let token_data = decode::<UnsubscribeClaims>(
+        token,
+        &DecodingKey::from_secret(get_config().unsubscribe.secret.as_ref()),
+        &Validation::default(),
+    )
+    .map_err(|e| {
+        tracing::error!("{:?}", e);
+        e
+    })?;
+    let user_id = token_data.claims.sub.parse::<i64>()?;
+    Ok(user_id)
+}
+
 // todo エラー返すようにする
 pub async fn get_user_from_token(pool: &PgPool, token: String) -> Option<User> {
     match token_decode(token) {
@@ -0,0 +1,61 @@
+use async_graphql::Context;
+use futures::{stream, Stream};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::graphql::models::{
+    application::Application, message::Message, recruitment::Recruitment,
+};
+
+// サブスクリプションに配信するイベント
+#[derive(Clone, Debug)]
+pub enum Event {
+    // やり取りにメッセージが送られた
+    MessageSent(Message),
+    // 応募のステータスが変わった 募集者にも届けるので募集者のIDを持つ
+    ApplicationStatusChanged {
+        application: Application,
+        owner_id: i64,
+    },
+    // 募集が公開された
+    RecruitmentPublished(Recruitment),
+}
+
+// ミューテーションで起きたイベントを購読中の接続に配信する
+// 同じプロセス内でのみ配信されるので複数台で動かす場合は共有の仕組みに置き換える
+#[derive(Clone)]
+pub struct Broker {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Broker {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    // 購読している接続がなくてもエラーにしない
+    pub fn publish(&self, event: Event) {
+        if self.sender.send(event).is_err() {
+            tracing::debug!("no subscribers");
+        }
+    }
+
+    pub fn subscribe(&self) -> impl Stream<Item = Event> {
+        stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    // 受信が追いつかずに溢れた分は読み飛ばして購読を続ける
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::error!("subscriber lagged: {} events skipped", skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
+
+pub async fn get_broker<'ctx>(ctx: &Context<'ctx>) -> &'ctx Broker {
+    ctx.data_unchecked::<Broker>()
+}
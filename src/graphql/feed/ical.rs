@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use async_graphql::ID;
+use axum::{
+    extract::Path,
+    http::header,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::{DateTime, Duration, Local, Utc};
+use hyper::StatusCode;
+use sqlx::PgPool;
+
+use crate::graphql::{
+    id_decode,
+    mail::sender::recruitment_url,
+    models::{
+        calendar::{get_calendar_recruitments, get_user_id_by_calendar_token},
+        recruitment::{get_recruitment, Recruitment, RecruitmentStatus},
+    },
+    utils::jst::to_jst,
+    NodeType,
+};
+
+// 日時はすべて日本時間で出力する
+const TZID: &str = "Asia/Tokyo";
+// 募集には終了日時がないので開催日時から2時間の予定にする
+const EVENT_DURATION_HOURS: i64 = 2;
+// 1行の最大オクテット数 これを超える行は折り返す(RFC 5545 3.1)
+const MAX_LINE_OCTETS: usize = 75;
+const CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+// 1件の募集のicsファイル 下書きと開催日時が決まっていない募集は404にする
+pub async fn recruitment_ical(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(file): Path<String>,
+) -> Response {
+    let id = match file
+        .strip_suffix(".ics")
+        .and_then(|id| id_decode(&ID::from(id), NodeType::Recruitment).ok())
+    {
+        Some(id) => id,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let recruitment = match get_recruitment(&pool, id).await {
+        Ok(Some(recruitment))
+            if recruitment.status != RecruitmentStatus::Draft && recruitment.start_at.is_some() =>
+        {
+            recruitment
+        }
+        Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let body = render_calendar(None, &[recruitment]);
+    let headers = [
+        (header::CONTENT_TYPE, CONTENT_TYPE.to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!(r#"attachment; filename="recruitment-{}.ics""#, id),
+        ),
+    ];
+    (StatusCode::OK, headers, body).into_response()
+}
+
+// カレンダーアプリから購読するユーザーごとのicsファイル
+// 作成した、ストックした、応募が承諾された募集を載せる
+pub async fn user_calendar_ical(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(file): Path<String>,
+) -> Response {
+    let token = match file.strip_suffix(".ics") {
+        Some(token) => token,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let user_id = match get_user_id_by_calendar_token(&pool, token).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            tracing::error!("invalid calendar token");
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let recruitments = match get_calendar_recruitments(&pool, user_id).await {
+        Ok(recruitments) => recruitments,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let body = render_calendar(Some("connefut"), &recruitments);
+    let headers = [
+        (header::CONTENT_TYPE, CONTENT_TYPE),
+        (header::CACHE_CONTROL, "private, max-age=900"),
+    ];
+    (StatusCode::OK, headers, body).into_response()
+}
+
+fn render_calendar(name: Option<&str>, recruitments: &[Recruitment]) -> String {
+    let mut ical = String::new();
+    push_line(&mut ical, "BEGIN:VCALENDAR");
+    push_line(&mut ical, "VERSION:2.0");
+    push_line(&mut ical, "PRODID:-//connefut//connefut//JA");
+    push_line(&mut ical, "CALSCALE:GREGORIAN");
+    if let Some(name) = name {
+        push_line(&mut ical, &format!("X-WR-CALNAME:{}", escape_text(name)));
+        push_line(&mut ical, &format!("X-WR-TIMEZONE:{}", TZID));
+    }
+    // 日本は夏時間がないので標準時だけ定義する
+    push_line(&mut ical, "BEGIN:VTIMEZONE");
+    push_line(&mut ical, &format!("TZID:{}", TZID));
+    push_line(&mut ical, "BEGIN:STANDARD");
+    push_line(&mut ical, "DTSTART:19700101T000000");
+    push_line(&mut ical, "TZOFFSETFROM:+0900");
+    push_line(&mut ical, "TZOFFSETTO:+0900");
+    push_line(&mut ical, "TZNAME:JST");
+    push_line(&mut ical, "END:STANDARD");
+    push_line(&mut ical, "END:VTIMEZONE");
+
+    let now = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    for recruitment in recruitments {
+        let start_at = match recruitment.start_at {
+            Some(start_at) => start_at,
+            None => continue,
+        };
+        let url = recruitment_url(recruitment.id);
+        push_line(&mut ical, "BEGIN:VEVENT");
+        push_line(
+            &mut ical,
+            &format!("UID:recruitment-{}@connefut", recruitment.id),
+        );
+        push_line(&mut ical, &format!("DTSTAMP:{}", now));
+        push_line(
+            &mut ical,
+            &format!("DTSTART;TZID={}:{}", TZID, format_local(start_at)),
+        );
+        push_line(
+            &mut ical,
+            &format!(
+                "DTEND;TZID={}:{}",
+                TZID,
+                format_local(start_at + Duration::hours(EVENT_DURATION_HOURS))
+            ),
+        );
+        push_line(
+            &mut ical,
+            &format!("SUMMARY:{}", escape_text(&recruitment.title)),
+        );
+        if let Some(ref venue) = recruitment.venue {
+            push_line(&mut ical, &format!("LOCATION:{}", escape_text(venue)));
+        }
+        if let (Some(lat), Some(lng)) = (recruitment.venue_lat, recruitment.venue_lng) {
+            push_line(&mut ical, &format!("GEO:{};{}", lat, lng));
+        }
+        let description = match recruitment.detail {
+            Some(ref detail) => format!("{}\n\n{}", detail, url),
+            None => url.clone(),
+        };
+        push_line(
+            &mut ical,
+            &format!("DESCRIPTION:{}", escape_text(&description)),
+        );
+        push_line(&mut ical, &format!("URL:{}", url));
+        push_line(&mut ical, "END:VEVENT");
+    }
+
+    push_line(&mut ical, "END:VCALENDAR");
+    ical
+}
+
+// TZIDと一緒に使う日本時間の日時
+fn format_local(datetime: DateTime<Local>) -> String {
+    to_jst(datetime).format("%Y%m%dT%H%M%S").to_string()
+}
+
+// TEXT型の値の特殊文字をエスケープする
+fn escape_text(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 行をCRLFで終えて追加する 長い行は文字の途中で切らないように折り返す
+fn push_line(ical: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            // 折り返した行は先頭の空白も数える
+            ical.push_str("\r\n ");
+            octets = 1;
+        }
+        ical.push(c);
+        octets += c.len_utf8();
+    }
+    ical.push_str("\r\n");
+}
@@ -0,0 +1,498 @@
+use std::sync::Arc;
+
+use async_graphql::ID;
+use axum::{
+    http::{header, HeaderMap, HeaderValue, Uri},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use axum_extra::extract::Query;
+use chrono::{DateTime, Local, Utc};
+use hyper::StatusCode;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    config::{API_ORIGIN, FRONTEND_ORIGIN},
+    graphql::{
+        id_decode,
+        mail::sender::recruitment_url,
+        models::{
+            recruitment::{get_newly_published_recruitments, Recruitment, RecruitmentFilter},
+            recruitment_status_history::get_last_status_changed_at,
+        },
+        utils::{html::escape, jst::to_jst},
+        IdDecodeError, NodeType,
+    },
+};
+
+// フィードに載せる募集の数
+const FEED_ENTRIES_LIMIT: i64 = 50;
+// 概要に載せる詳細の最大文字数
+const SUMMARY_DETAIL_LENGTH: usize = 200;
+const FEED_TITLE: &str = "connefut 新着の募集";
+
+// 同じ種類の条件はいずれかに一致 種類の違う条件は全てに一致する募集を載せる
+// 例: /feeds/recruitments.atom?prefecture=...&tag=...&tag=...
+#[derive(Deserialize, Debug)]
+pub struct FeedParams {
+    prefecture: Option<String>,
+    sport: Option<String>,
+    #[serde(default)]
+    tag: Vec<String>,
+}
+
+impl FeedParams {
+    fn to_filter(&self) -> Result<RecruitmentFilter, IdDecodeError> {
+        let prefecture_id = self
+            .prefecture
+            .as_ref()
+            .map(|id| id_decode(&ID::from(id), NodeType::Prefecture))
+            .transpose()?;
+        let sport_id = self
+            .sport
+            .as_ref()
+            .map(|id| id_decode(&ID::from(id), NodeType::Sport))
+            .transpose()?;
+        let tag_ids = self
+            .tag
+            .iter()
+            .map(|id| id_decode(&ID::from(id), NodeType::Tag))
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(RecruitmentFilter {
+            sport_id,
+            prefecture_id,
+            tag_ids,
+            ..Default::default()
+        })
+    }
+}
+
+struct FeedEntry {
+    recruitment: Recruitment,
+    sport_name: String,
+    prefecture_name: String,
+}
+
+impl FeedEntry {
+    fn url(&self) -> String {
+        recruitment_url(self.recruitment.id)
+    }
+    fn published_at(&self) -> DateTime<Local> {
+        self.recruitment
+            .published_at
+            .unwrap_or(self.recruitment.created_at)
+    }
+    // 公開した後に編集されていれば編集した日時
+    fn updated_at(&self) -> DateTime<Local> {
+        self.recruitment.updated_at.max(self.published_at())
+    }
+    // 例: 「サッカー / 東京都 / 2024/01/01 10:00 / 駒沢公園」の後に詳細の冒頭
+    fn summary(&self) -> String {
+        let mut items = vec![self.sport_name.clone(), self.prefecture_name.clone()];
+        if let Some(start_at) = self.recruitment.start_at {
+            items.push(to_jst(start_at).format("%Y/%m/%d %H:%M").to_string());
+        }
+        if let Some(ref venue) = self.recruitment.venue {
+            items.push(venue.clone());
+        }
+        let mut summary = items.join(" / ");
+        if let Some(ref detail) = self.recruitment.detail {
+            let mut chars = detail.chars();
+            let head: String = chars.by_ref().take(SUMMARY_DETAIL_LENGTH).collect();
+            summary.push('\n');
+            summary.push_str(&head);
+            if chars.next().is_some() {
+                summary.push('…');
+            }
+        }
+        summary
+    }
+}
+
+// 新しく公開された募集のAtomフィード
+pub async fn atom_feed(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<FeedParams>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let entries = match get_entries(&pool, &params).await {
+        Ok(entries) => entries,
+        Err(response) => return response,
+    };
+    let last_modified = match get_last_modified(&pool, &entries).await {
+        Ok(last_modified) => last_modified,
+        Err(response) => return response,
+    };
+    let self_url = format!("{}{}", API_ORIGIN, uri);
+    let updated = entries.iter().map(|entry| entry.updated_at()).max();
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    xml.push_str(&format!("<id>{}</id>", escape(&self_url)));
+    xml.push_str(&format!("<title>{}</title>", escape(FEED_TITLE)));
+    xml.push_str(&format!(
+        r#"<link rel="self" type="application/atom+xml" href="{}"/>"#,
+        escape(&self_url)
+    ));
+    xml.push_str(&format!(
+        r#"<link rel="alternate" type="text/html" href="{}"/>"#,
+        escape(FRONTEND_ORIGIN)
+    ));
+    // 募集がない場合も内容から作るETagが変わらないように固定の日時にする
+    xml.push_str(&format!(
+        "<updated>{}</updated>",
+        updated
+            .map(|updated| updated.to_rfc3339())
+            .unwrap_or_else(|| String::from("1970-01-01T00:00:00Z"))
+    ));
+    xml.push_str("<author><name>connefut</name></author>");
+    for entry in entries.iter() {
+        let url = entry.url();
+        xml.push_str("<entry>");
+        xml.push_str(&format!("<id>{}</id>", escape(&url)));
+        xml.push_str(&format!(
+            "<title>{}</title>",
+            escape(&entry.recruitment.title)
+        ));
+        xml.push_str(&format!(
+            r#"<link rel="alternate" type="text/html" href="{}"/>"#,
+            escape(&url)
+        ));
+        xml.push_str(&format!(
+            "<published>{}</published>",
+            entry.published_at().to_rfc3339()
+        ));
+        xml.push_str(&format!(
+            "<updated>{}</updated>",
+            entry.updated_at().to_rfc3339()
+        ));
+        xml.push_str(&format!(
+            r#"<category term="{}"/>"#,
+            escape(&entry.sport_name)
+        ));
+        xml.push_str(&format!("<summary>{}</summary>", escape(&entry.summary())));
+        xml.push_str("</entry>");
+    }
+    xml.push_str("</feed>");
+
+    respond(
+        &headers,
+        xml,
+        "application/atom+xml; charset=utf-8",
+        last_modified,
+    )
+}
+
+// 新しく公開された募集のRSS 2.0フィード
+pub async fn rss_feed(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<FeedParams>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let entries = match get_entries(&pool, &params).await {
+        Ok(entries) => entries,
+        Err(response) => return response,
+    };
+    let last_modified = match get_last_modified(&pool, &entries).await {
+        Ok(last_modified) => last_modified,
+        Err(response) => return response,
+    };
+    let self_url = format!("{}{}", API_ORIGIN, uri);
+    let updated = entries.iter().map(|entry| entry.updated_at()).max();
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>"#);
+    xml.push_str(&format!("<title>{}</title>", escape(FEED_TITLE)));
+    xml.push_str(&format!("<link>{}</link>", escape(FRONTEND_ORIGIN)));
+    xml.push_str(&format!(
+        "<description>{}</description>",
+        escape(FEED_TITLE)
+    ));
+    xml.push_str(&format!(
+        r#"<atom:link rel="self" type="application/rss+xml" href="{}"/>"#,
+        escape(&self_url)
+    ));
+    xml.push_str("<language>ja</language>");
+    if let Some(updated) = updated {
+        xml.push_str(&format!(
+            "<lastBuildDate>{}</lastBuildDate>",
+            updated.to_rfc2822()
+        ));
+    }
+    for entry in entries.iter() {
+        let url = entry.url();
+        xml.push_str("<item>");
+        xml.push_str(&format!(
+            "<title>{}</title>",
+            escape(&entry.recruitment.title)
+        ));
+        xml.push_str(&format!("<link>{}</link>", escape(&url)));
+        xml.push_str(&format!(
+            r#"<guid isPermaLink="true">{}</guid>"#,
+            escape(&url)
+        ));
+        xml.push_str(&format!(
+            "<pubDate>{}</pubDate>",
+            entry.published_at().to_rfc2822()
+        ));
+        xml.push_str(&format!(
+            "<category>{}</category>",
+            escape(&entry.sport_name)
+        ));
+        xml.push_str(&format!(
+            "<description>{}</description>",
+            escape(&entry.summary())
+        ));
+        xml.push_str("</item>");
+    }
+    xml.push_str("</channel></rss>");
+
+    respond(
+        &headers,
+        xml,
+        "application/rss+xml; charset=utf-8",
+        last_modified,
+    )
+}
+
+async fn get_entries(pool: &PgPool, params: &FeedParams) -> Result<Vec<FeedEntry>, Response> {
+    let filter = match params.to_filter() {
+        Ok(filter) => filter,
+        Err(e) => {
+            tracing::error!("feed params decode failed: {:?}", e);
+            return Err((StatusCode::BAD_REQUEST, "IDが正しくありません").into_response());
+        }
+    };
+
+    match get_newly_published_recruitments(pool, &filter, FEED_ENTRIES_LIMIT).await {
+        Ok(rows) => Ok(rows
+            .into_iter()
+            .map(|(recruitment, sport_name, prefecture_name)| FeedEntry {
+                recruitment,
+                sport_name,
+                prefecture_name,
+            })
+            .collect()),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+    }
+}
+
+// フィードの内容が最後に変わった日時
+// 載っている募集の編集、公開日時と、締め切りなどで載らなくなった募集も含めた最後のステータスの変更のうち新しい方
+async fn get_last_modified(
+    pool: &PgPool,
+    entries: &[FeedEntry],
+) -> Result<Option<DateTime<Local>>, Response> {
+    let status_changed_at = match get_last_status_changed_at(pool).await {
+        Ok(changed_at) => changed_at,
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+    };
+    Ok(entries
+        .iter()
+        .map(|entry| entry.updated_at())
+        .chain(status_changed_at)
+        .max())
+}
+
+// 内容から作ったETagとLast-Modifiedを付けて返す 条件付きリクエストで変わっていなければ304を返す
+fn respond(
+    headers: &HeaderMap,
+    body: String,
+    content_type: &'static str,
+    last_modified: Option<DateTime<Local>>,
+) -> Response {
+    let etag = format!(r#""{:016x}""#, fnv1a_64(body.as_bytes()));
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=300"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, value);
+    }
+    if let Some(last_modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&http_date(last_modified)) {
+            response_headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+
+    if is_not_modified(headers, &etag, last_modified) {
+        return (StatusCode::NOT_MODIFIED, response_headers).into_response();
+    }
+    (StatusCode::OK, response_headers, body).into_response()
+}
+
+// FNV-1a 64bit デプロイしてもETagが変わらないようにアルゴリズムが固定のハッシュを使う
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+// HTTPの日付の形式 例: Sun, 06 Nov 1994 08:49:37 GMT
+fn http_date(date: DateTime<Local>) -> String {
+    date.with_timezone(&Utc)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+// If-None-Matchのいずれかが一致すれば変わっていない 弱いETagも一致とみなす(RFC 9110 13.1.2)
+// If-None-MatchがなければIf-Modified-Since以降に変わっていないかで判断する(RFC 9110 13.1.3)
+fn is_not_modified(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<DateTime<Local>>,
+) -> bool {
+    if headers.contains_key(header::IF_NONE_MATCH) {
+        return headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            });
+    }
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+    match (last_modified, if_modified_since) {
+        // HTTPの日付は秒単位なので秒で比べる
+        (Some(last_modified), Some(since)) => last_modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn if_none_match(values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(header::IF_NONE_MATCH, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn is_not_modified_without_if_none_match() {
+        assert!(!is_not_modified(&HeaderMap::new(), r#""abc""#, None));
+    }
+
+    #[test]
+    fn is_not_modified_with_matching_etag() {
+        let headers = if_none_match(&[r#""abc""#]);
+        assert!(is_not_modified(&headers, r#""abc""#, None));
+    }
+
+    #[test]
+    fn is_not_modified_with_different_etag() {
+        let headers = if_none_match(&[r#""xyz""#]);
+        assert!(!is_not_modified(&headers, r#""abc""#, None));
+    }
+
+    #[test]
+    fn is_not_modified_with_etag_list() {
+        let headers = if_none_match(&[r#""xyz", "abc""#]);
+        assert!(is_not_modified(&headers, r#""abc""#, None));
+    }
+
+    #[test]
+    fn is_not_modified_with_multiple_headers() {
+        let headers = if_none_match(&[r#""xyz""#, r#""abc""#]);
+        assert!(is_not_modified(&headers, r#""abc""#, None));
+    }
+
+    #[test]
+    fn is_not_modified_with_weak_etag() {
+        let headers = if_none_match(&[r#"W/"abc""#]);
+        assert!(is_not_modified(&headers, r#""abc""#, None));
+    }
+
+    #[test]
+    fn is_not_modified_with_wildcard() {
+        let headers = if_none_match(&["*"]);
+        assert!(is_not_modified(&headers, r#""abc""#, None));
+    }
+
+    #[test]
+    fn is_not_modified_ignores_unquoted_etag() {
+        let headers = if_none_match(&["abc"]);
+        assert!(!is_not_modified(&headers, r#""abc""#, None));
+    }
+
+    fn if_modified_since(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, HeaderValue::from_static(value));
+        headers
+    }
+
+    fn last_modified() -> Option<DateTime<Local>> {
+        Some(
+            DateTime::parse_from_rfc3339("2024-01-01T10:00:00.500+09:00")
+                .unwrap()
+                .with_timezone(&Local),
+        )
+    }
+
+    #[test]
+    fn http_date_is_in_gmt() {
+        assert_eq!(
+            http_date(last_modified().unwrap()),
+            "Mon, 01 Jan 2024 01:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn is_not_modified_since_same_second() {
+        let headers = if_modified_since("Mon, 01 Jan 2024 01:00:00 GMT");
+        assert!(is_not_modified(&headers, r#""abc""#, last_modified()));
+    }
+
+    #[test]
+    fn is_not_modified_since_later_date() {
+        let headers = if_modified_since("Tue, 02 Jan 2024 00:00:00 GMT");
+        assert!(is_not_modified(&headers, r#""abc""#, last_modified()));
+    }
+
+    #[test]
+    fn is_modified_since_earlier_date() {
+        let headers = if_modified_since("Mon, 01 Jan 2024 00:59:59 GMT");
+        assert!(!is_not_modified(&headers, r#""abc""#, last_modified()));
+    }
+
+    #[test]
+    fn is_modified_with_invalid_if_modified_since() {
+        let headers = if_modified_since("yesterday");
+        assert!(!is_not_modified(&headers, r#""abc""#, last_modified()));
+    }
+
+    #[test]
+    fn is_modified_without_last_modified() {
+        let headers = if_modified_since("Mon, 01 Jan 2024 01:00:00 GMT");
+        assert!(!is_not_modified(&headers, r#""abc""#, None));
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        let mut headers = if_modified_since("Tue, 02 Jan 2024 00:00:00 GMT");
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static(r#""xyz""#));
+        assert!(!is_not_modified(&headers, r#""abc""#, last_modified()));
+    }
+
+    #[test]
+    fn fnv1a_64_is_stable() {
+        assert_eq!(fnv1a_64(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a_64(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -4,14 +4,20 @@ use async_graphql::{dataloader::DataLoader, Context};
 use sqlx::{Pool, Postgres};
 
 use self::{
+    application::{AcceptedCountLoader, ApplicationLoader},
+    message::MessageThreadLoader,
     prefecture::PrefectureLoader,
+    recruitment::RecruitmentLoader,
     sport::SportLoader,
     stock::StockLoader,
-    tag::TagLoader,
+    tag::{TagIdLoader, TagLoader},
     user::{FollowingLoader, UserLoader},
 };
 
+pub mod application;
+pub mod message;
 pub mod prefecture;
+pub mod recruitment;
 pub mod sport;
 pub mod stock;
 pub mod tag;
@@ -21,9 +27,14 @@ pub struct Loaders {
     pub user_loader: DataLoader<UserLoader>,
     pub following_loader: DataLoader<FollowingLoader>,
     pub tag_loader: DataLoader<TagLoader>,
+    pub tag_id_loader: DataLoader<TagIdLoader>,
     pub prefecture_loader: DataLoader<PrefectureLoader>,
     pub sport_loader: DataLoader<SportLoader>,
     pub stock_loader: DataLoader<StockLoader>,
+    pub recruitment_loader: DataLoader<RecruitmentLoader>,
+    pub application_loader: DataLoader<ApplicationLoader>,
+    pub accepted_count_loader: DataLoader<AcceptedCountLoader>,
+    pub message_thread_loader: DataLoader<MessageThreadLoader>,
 }
 
 impl Loaders {
@@ -46,6 +57,12 @@ impl Loaders {
             },
             tokio::spawn,
         );
+        let tag_id_loader = DataLoader::new(
+            TagIdLoader {
+                pool: Arc::clone(pool),
+            },
+            tokio::spawn,
+        );
         let prefecture_loader = DataLoader::new(
             PrefectureLoader {
                 pool: Arc::clone(pool),
@@ -64,14 +81,43 @@ impl Loaders {
             },
             tokio::spawn,
         );
+        let recruitment_loader = DataLoader::new(
+            RecruitmentLoader {
+                pool: Arc::clone(pool),
+            },
+            tokio::spawn,
+        );
+        let application_loader = DataLoader::new(
+            ApplicationLoader {
+                pool: Arc::clone(pool),
+            },
+            tokio::spawn,
+        );
+        let accepted_count_loader = DataLoader::new(
+            AcceptedCountLoader {
+                pool: Arc::clone(pool),
+            },
+            tokio::spawn,
+        );
+        let message_thread_loader = DataLoader::new(
+            MessageThreadLoader {
+                pool: Arc::clone(pool),
+            },
+            tokio::spawn,
+        );
 
         Self {
             user_loader,
             following_loader,
             tag_loader,
+            tag_id_loader,
             prefecture_loader,
             sport_loader,
             stock_loader,
+            recruitment_loader,
+            application_loader,
+            accepted_count_loader,
+            message_thread_loader,
         }
     }
 }
@@ -0,0 +1,127 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+use async_trait::async_trait;
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+
+use crate::graphql::models::application::Application;
+
+pub struct ApplicationLoader {
+    pub pool: Arc<PgPool>,
+}
+
+#[async_trait]
+impl Loader<i64> for ApplicationLoader {
+    type Value = Application;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[i64]) -> Result<HashMap<i64, Self::Value>, Self::Error> {
+        let sql = "SELECT * FROM applications WHERE id IN (";
+        let mut query_builder = QueryBuilder::<Postgres>::new(sql);
+        let mut separated = query_builder.separated(", ");
+        for key in keys.iter() {
+            separated.push_bind(key);
+        }
+        separated.push_unseparated(") ");
+        let query = query_builder.build_query_as::<Application>();
+        let result = query.fetch_all(&*self.pool).await;
+
+        match result {
+            Ok(applications) => {
+                tracing::info!("ApplicationLoader load successed!!");
+                // { application_id: Application }の形に整形する
+                let applications_hash: HashMap<i64, Application> = applications
+                    .iter()
+                    .map(|application| (application.id, application.to_owned()))
+                    .collect();
+                Ok(applications_hash)
+            }
+            Err(e) => {
+                tracing::error!("ApplicationLoader load failed: {:?}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Loader<[i64; 2]> for ApplicationLoader {
+    type Value = Application;
+    type Error = Arc<sqlx::Error>;
+
+    // keysは[user_id, recruitment_id]の形で送られてくる
+    // ユーザーの募集への応募
+    async fn load(&self, keys: &[[i64; 2]]) -> Result<HashMap<[i64; 2], Self::Value>, Self::Error> {
+        let sql = "SELECT * FROM applications WHERE (user_id, recruitment_id) IN";
+        let mut query_builder = QueryBuilder::<Postgres>::new(sql);
+        query_builder.push_tuples(keys, |mut b, key| {
+            b.push_bind(key[0]).push_bind(key[1]);
+        });
+        let query = query_builder.build_query_as::<Application>();
+        let result = query.fetch_all(&*self.pool).await;
+
+        match result {
+            Ok(applications) => {
+                tracing::info!("ApplicationLoader load viewer_application successed!!");
+                // {[user_id, recruitment_id], Application}の形に整形する
+                let applications_hash: HashMap<[i64; 2], Application> = applications
+                    .iter()
+                    .map(|application| {
+                        (
+                            [application.user_id, application.recruitment_id],
+                            application.to_owned(),
+                        )
+                    })
+                    .collect();
+                Ok(applications_hash)
+            }
+            Err(e) => {
+                tracing::error!("ApplicationLoader load viewer_application failed: {:?}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+pub struct AcceptedCountLoader {
+    pub pool: Arc<PgPool>,
+}
+
+#[async_trait]
+impl Loader<i64> for AcceptedCountLoader {
+    type Value = i64;
+    type Error = Arc<sqlx::Error>;
+
+    // 募集で承諾済みの応募の数
+    async fn load(&self, keys: &[i64]) -> Result<HashMap<i64, Self::Value>, Self::Error> {
+        let sql = "SELECT recruitment_id, COUNT(id) FROM applications WHERE status = 'accepted' AND recruitment_id IN (";
+        let mut query_builder = QueryBuilder::<Postgres>::new(sql);
+        let mut separated = query_builder.separated(",");
+        for key in keys.iter() {
+            separated.push_bind(key);
+        }
+        separated.push_unseparated(") ");
+        query_builder.push("GROUP BY recruitment_id");
+        let query = query_builder.build();
+        let result = query.fetch_all(&*self.pool).await;
+
+        match result {
+            Ok(rows) => {
+                tracing::info!("AcceptedCountLoader load successed!!");
+                let accepted_hash: HashMap<i64, i64> = rows
+                    .iter()
+                    .map(|row| {
+                        let recruitment_id: i64 = row.get("recruitment_id");
+                        let count: i64 = row.get("count");
+                        (recruitment_id, count)
+                    })
+                    .collect();
+                Ok(accepted_hash)
+            }
+            Err(e) => {
+                tracing::error!("AcceptedCountLoader load failed: {:?}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
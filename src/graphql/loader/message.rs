@@ -0,0 +1,50 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+use async_trait::async_trait;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::graphql::models::message::MessageThread;
+
+pub struct MessageThreadLoader {
+    pub pool: Arc<PgPool>,
+}
+
+#[async_trait]
+impl Loader<[i64; 2]> for MessageThreadLoader {
+    type Value = MessageThread;
+    type Error = Arc<sqlx::Error>;
+
+    // keysは[applicant_id, recruitment_id]の形で送られてくる
+    // 応募者と募集者のやり取り
+    async fn load(&self, keys: &[[i64; 2]]) -> Result<HashMap<[i64; 2], Self::Value>, Self::Error> {
+        let sql = "SELECT * FROM message_threads WHERE (applicant_id, recruitment_id) IN";
+        let mut query_builder = QueryBuilder::<Postgres>::new(sql);
+        query_builder.push_tuples(keys, |mut b, key| {
+            b.push_bind(key[0]).push_bind(key[1]);
+        });
+        let query = query_builder.build_query_as::<MessageThread>();
+        let result = query.fetch_all(&*self.pool).await;
+
+        match result {
+            Ok(threads) => {
+                tracing::info!("MessageThreadLoader load successed!!");
+                // {[applicant_id, recruitment_id], MessageThread}の形に整形する
+                let threads_hash: HashMap<[i64; 2], MessageThread> = threads
+                    .iter()
+                    .map(|thread| {
+                        (
+                            [thread.applicant_id, thread.recruitment_id],
+                            thread.to_owned(),
+                        )
+                    })
+                    .collect();
+                Ok(threads_hash)
+            }
+            Err(e) => {
+                tracing::error!("MessageThreadLoader load failed: {:?}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+use async_trait::async_trait;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::graphql::models::recruitment::Recruitment;
+
+pub struct RecruitmentLoader {
+    pub pool: Arc<PgPool>,
+}
+
+#[async_trait]
+impl Loader<i64> for RecruitmentLoader {
+    type Value = Recruitment;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[i64]) -> Result<HashMap<i64, Self::Value>, Self::Error> {
+        let sql = "SELECT * FROM recruitments WHERE id IN (";
+        let mut query_builder = QueryBuilder::<Postgres>::new(sql);
+        let mut separated = query_builder.separated(", ");
+        for key in keys.iter() {
+            separated.push_bind(key);
+        }
+        separated.push_unseparated(") ");
+        let query = query_builder.build_query_as::<Recruitment>();
+        let result = query.fetch_all(&*self.pool).await;
+
+        match result {
+            Ok(recruitments) => {
+                tracing::info!("RecruitmentLoader load successed!!");
+                // { recruitment_id: Recruitment }の形に整形する
+                let recruitments_hash: HashMap<i64, Recruitment> = recruitments
+                    .iter()
+                    .map(|recruitment| (recruitment.id, recruitment.to_owned()))
+                    .collect();
+                Ok(recruitments_hash)
+            }
+            Err(e) => {
+                tracing::error!("RecruitmentLoader load failed: {:?}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
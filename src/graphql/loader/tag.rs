@@ -54,3 +54,31 @@ impl Loader<i64> for TagLoader {
         Ok(recruitment_tags_hash)
     }
 }
+
+pub struct TagIdLoader {
+    pub pool: Arc<PgPool>,
+}
+
+#[async_trait]
+impl Loader<i64> for TagIdLoader {
+    type Value = Tag;
+    type Error = Arc<sqlx::Error>;
+
+    // タグのIDからタグを取得する
+    async fn load(&self, keys: &[i64]) -> Result<HashMap<i64, Self::Value>, Self::Error> {
+        let sql = "SELECT id, name FROM tags WHERE id IN (";
+        let mut query_builder = QueryBuilder::<Postgres>::new(sql);
+        let mut separated = query_builder.separated(", ");
+        for key in keys.iter() {
+            separated.push_bind(key);
+        }
+        separated.push_unseparated(") ");
+        let query = query_builder.build_query_as::<Tag>();
+        let tags = query.fetch_all(&*self.pool).await?;
+
+        // { tag_id: Tag }の形になるように整形
+        let tags_hash: HashMap<i64, Tag> =
+            tags.iter().map(|tag| (tag.id, tag.to_owned())).collect();
+        Ok(tags_hash)
+    }
+}
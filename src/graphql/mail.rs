@@ -1 +1,2 @@
 pub mod sender;
+pub mod unsubscribe;
@@ -1,18 +1,90 @@
 use anyhow::{anyhow, Result};
 use lettre::{
-    message::{Mailbox, MultiPart},
+    message::{
+        header::{Header, HeaderName, HeaderValue},
+        Mailbox, MultiPart,
+    },
     transport::smtp::authentication::Credentials,
     AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
 };
 
-use crate::graphql::models::user::User;
+use crate::{
+    config::{API_ORIGIN, FRONTEND_ORIGIN},
+    graphql::{
+        auth::jwt::unsubscribe_token_encode,
+        id_encode,
+        models::{digest::Digest, notification_setting::DigestFrequency, user::User},
+        utils::html::escape,
+        NodeType,
+    },
+};
 
-pub async fn send_email_verification_code(user: &User) -> Result<()> {
+// メールクライアントに配信停止のURLを知らせるヘッダー
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.trim_matches(|c| c == '<' || c == '>').to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), format!("<{}>", self.0))
+    }
+}
+
+// メールクライアントからワンクリックで配信停止できることを知らせるヘッダー
+#[derive(Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), String::from("List-Unsubscribe=One-Click"))
+    }
+}
+
+fn from_mailbox() -> Result<Mailbox> {
     let from: Mailbox = format!("{} <{}>", "connefut", "info@connefut.com").parse()?;
+    Ok(from)
+}
+
+async fn send(email: Message) -> Result<()> {
+    let creds = Credentials::new("user".to_string(), "user".to_string());
+    let mailer: AsyncSmtpTransport<Tokio1Executor> =
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("mailhog")
+            .port(1025)
+            .credentials(creds)
+            .build();
+
+    match mailer.send(email).await {
+        Ok(_) => {
+            tracing::info!("email send successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+pub async fn send_email_verification_code(user: &User) -> Result<()> {
     let to: Mailbox = user.email.as_str().parse()?;
 
     let email = Message::builder()
-        .from(from)
+        .from(from_mailbox()?)
         .to(to)
         .subject("メールアドレスの認証コードを送信しました")
         .multipart(MultiPart::alternative_plain_html(
@@ -26,21 +98,120 @@ pub async fn send_email_verification_code(user: &User) -> Result<()> {
             ),
         ))?;
 
-    let creds = Credentials::new("user".to_string(), "user".to_string());
-    let mailer: AsyncSmtpTransport<Tokio1Executor> =
-        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("mailhog")
-            .port(1025)
-            .credentials(creds)
-            .build();
+    send(email).await
+}
 
-    match mailer.send(email).await {
-        Ok(_) => {
-            tracing::info!("email send successed!!");
-            Ok(())
+// ストックしていた募集が削除されたことを知らせる
+pub async fn send_recruitment_deleted_notice(user: &User, title: &str) -> Result<()> {
+    let to: Mailbox = user.email.as_str().parse()?;
+
+    let email = Message::builder()
+        .from(from_mailbox()?)
+        .to(to)
+        .subject("ストックしていた募集が削除されました")
+        .multipart(MultiPart::alternative_plain_html(
+            format!("ストックしていた募集「{}」が削除されました", title),
+            include_str!("./template/recruitment_deleted.html").replace("{title}", &escape(title)),
+        ))?;
+
+    send(email).await
+}
+
+pub fn recruitment_url(recruitment_id: i64) -> String {
+    format!(
+        "{}/recruitments/{}",
+        FRONTEND_ORIGIN,
+        id_encode(NodeType::Recruitment, recruitment_id)
+    )
+}
+
+// 応募、フォローしているユーザーの新しい募集、締め切りが近いストックをまとめて知らせる
+pub async fn send_digest(user: &User, frequency: DigestFrequency, digest: &Digest) -> Result<()> {
+    let to: Mailbox = user.email.as_str().parse()?;
+    let subject = match frequency {
+        DigestFrequency::Weekly => "今週のconnefutのお知らせ",
+        _ => "今日のconnefutのお知らせ",
+    };
+    let unsubscribe_url = format!(
+        "{}/unsubscribe?token={}",
+        API_ORIGIN,
+        unsubscribe_token_encode(user.id)?
+    );
+
+    let mut plain = Vec::new();
+    let mut html = Vec::new();
+    if !digest.applications.is_empty() {
+        plain.push(String::from("■ あなたの募集への応募"));
+        html.push(String::from("<h2>あなたの募集への応募</h2><ul>"));
+        for application in &digest.applications {
+            let url = recruitment_url(application.recruitment_id);
+            plain.push(format!(
+                "・{} ({}件) {}",
+                application.title, application.application_count, url
+            ));
+            html.push(format!(
+                r#"<li><a href="{}">{}</a> ({}件)</li>"#,
+                escape(&url),
+                escape(&application.title),
+                application.application_count
+            ));
         }
-        Err(e) => {
-            tracing::error!("{:?}", e);
-            Err(e.into())
+        html.push(String::from("</ul>"));
+    }
+    if !digest.followed_recruitments.is_empty() {
+        plain.push(String::from("■ フォローしているユーザーの新しい募集"));
+        html.push(String::from(
+            "<h2>フォローしているユーザーの新しい募集</h2><ul>",
+        ));
+        for recruitment in &digest.followed_recruitments {
+            let url = recruitment_url(recruitment.recruitment_id);
+            plain.push(format!(
+                "・{} ({}さん) {}",
+                recruitment.title, recruitment.user_name, url
+            ));
+            html.push(format!(
+                r#"<li><a href="{}">{}</a> ({}さん)</li>"#,
+                escape(&url),
+                escape(&recruitment.title),
+                escape(&recruitment.user_name)
+            ));
+        }
+        html.push(String::from("</ul>"));
+    }
+    if !digest.closing_stocks.is_empty() {
+        plain.push(String::from("■ 締め切りが近いストックした募集"));
+        html.push(String::from("<h2>締め切りが近いストックした募集</h2><ul>"));
+        for stock in &digest.closing_stocks {
+            let url = recruitment_url(stock.recruitment_id);
+            let closing_at = stock.closing_at.format("%m/%d %H:%M");
+            plain.push(format!(
+                "・{} ({}締め切り) {}",
+                stock.title, closing_at, url
+            ));
+            html.push(format!(
+                r#"<li><a href="{}">{}</a> ({}締め切り)</li>"#,
+                escape(&url),
+                escape(&stock.title),
+                closing_at
+            ));
         }
+        html.push(String::from("</ul>"));
     }
+    plain.push(format!("配信停止: {}", unsubscribe_url));
+
+    let email = Message::builder()
+        .from(from_mailbox()?)
+        .to(to)
+        .subject(subject)
+        .header(ListUnsubscribe(unsubscribe_url.clone()))
+        .header(ListUnsubscribePost)
+        .multipart(MultiPart::alternative_plain_html(
+            plain.join("\n"),
+            include_str!("./template/digest.html")
+                .replace("{subject}", subject)
+                .replace("{content}", &html.join("\n"))
+                .replace("{unsubscribe_url}", &escape(&unsubscribe_url)),
+        ))?;
+
+    send(email).await
 }
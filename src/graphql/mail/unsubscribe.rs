@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use axum::{extract::Query, response::Html, Extension};
+use hyper::StatusCode;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::graphql::{
+    auth::jwt::unsubscribe_token_decode, models::notification_setting::unsubscribe_digest,
+    utils::html::escape,
+};
+
+#[derive(Deserialize)]
+pub struct UnsubscribeParams {
+    token: String,
+}
+
+// リンクを開いただけで配信停止されないように確認のボタンを表示する
+pub async fn unsubscribe_page(
+    Query(params): Query<UnsubscribeParams>,
+) -> (StatusCode, Html<String>) {
+    match unsubscribe_token_decode(&params.token) {
+        Ok(_) => (
+            StatusCode::OK,
+            render("まとめメールの配信を停止しますか?", Some(&params.token)),
+        ),
+        Err(_) => invalid_token(),
+    }
+}
+
+// 確認のボタンとメールクライアントのワンクリック配信停止(RFC 8058)のPOSTで配信停止する
+pub async fn unsubscribe(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Query(params): Query<UnsubscribeParams>,
+) -> (StatusCode, Html<String>) {
+    let user_id = match unsubscribe_token_decode(&params.token) {
+        Ok(user_id) => user_id,
+        Err(_) => return invalid_token(),
+    };
+
+    match unsubscribe_digest(&pool, user_id).await {
+        Ok(_) => (
+            StatusCode::OK,
+            render("まとめメールの配信を停止しました", None),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            render(
+                "配信停止に失敗しました 時間をおいてもう一度お試しください",
+                None,
+            ),
+        ),
+    }
+}
+
+fn invalid_token() -> (StatusCode, Html<String>) {
+    tracing::error!("invalid unsubscribe token");
+    (
+        StatusCode::BAD_REQUEST,
+        render("配信停止のリンクが正しくないか期限が切れています", None),
+    )
+}
+
+fn render(message: &str, token: Option<&str>) -> Html<String> {
+    let form = match token {
+        Some(token) => format!(
+            r#"<form method="post" action="/unsubscribe?token={}"><button type="submit">配信を停止する</button></form>"#,
+            escape(token)
+        ),
+        None => String::new(),
+    };
+    Html(
+        include_str!("./template/unsubscribe.html")
+            .replace("{message}", &escape(message))
+            .replace("{form}", &form),
+    )
+}
@@ -1,6 +1,17 @@
+pub mod application;
 pub mod authentication;
+pub mod calendar;
+pub mod digest;
+pub mod message;
+pub mod notification;
+pub mod notification_setting;
 pub mod prefecture;
+pub mod recommendation;
 pub mod recruitment;
+pub mod recruitment_revision;
+pub mod recruitment_series;
+pub mod recruitment_status_history;
+pub mod recruitment_view;
 pub mod sport;
 pub mod stock;
 pub mod tag;
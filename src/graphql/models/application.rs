@@ -0,0 +1,667 @@
+use anyhow::Result;
+use async_graphql::{Context, Enum, Object, ID};
+use chrono::{DateTime, Local};
+use sqlx::{
+    postgres::{PgHasArrayType, PgRow, PgTypeInfo},
+    PgPool, Postgres, Row, Transaction,
+};
+
+use crate::{
+    database::get_db_pool,
+    graphql::{
+        auth::get_viewer, id_encode, loader::get_loaders, utils::pagination::SearchParams, NodeType,
+    },
+};
+
+use super::{
+    message::MessageThread,
+    recruitment::{Recruitment, RecruitmentClosedReason, RecruitmentStatus},
+    recruitment_status_history::add_recruitment_status_history_tx,
+    user::User,
+};
+
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "application_status")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ApplicationStatus {
+    /// 募集者の返答待ち
+    Pending,
+    /// 募集者が承諾した
+    Accepted,
+    /// 募集者が断った
+    Rejected,
+    /// 応募者が取り下げた
+    Withdrawn,
+    /// 定員に達しているためキャンセル待ち
+    Waitlisted,
+}
+
+impl PgHasArrayType for ApplicationStatus {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::with_name("_application_status")
+    }
+}
+
+// 応募のステータスの遷移
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ApplicationStatusTransition {
+    Accept,   // 募集者が承諾する
+    Reject,   // 募集者が断る
+    Withdraw, // 応募者が取り下げる
+}
+
+impl ApplicationStatusTransition {
+    pub fn from_statuses(&self) -> Vec<ApplicationStatus> {
+        match self {
+            Self::Accept => vec![ApplicationStatus::Pending],
+            Self::Reject => vec![ApplicationStatus::Pending, ApplicationStatus::Waitlisted],
+            Self::Withdraw => vec![
+                ApplicationStatus::Pending,
+                ApplicationStatus::Accepted,
+                ApplicationStatus::Waitlisted,
+            ],
+        }
+    }
+    pub fn to_status(&self) -> ApplicationStatus {
+        match self {
+            Self::Accept => ApplicationStatus::Accepted,
+            Self::Reject => ApplicationStatus::Rejected,
+            Self::Withdraw => ApplicationStatus::Withdrawn,
+        }
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Application {
+    pub id: i64,
+    pub recruitment_id: i64,
+    pub user_id: i64,
+    pub message: String,
+    pub status: ApplicationStatus,
+    pub waitlisted_at: Option<DateTime<Local>>,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+}
+
+impl Application {
+    // 応募は応募したユーザーと募集を作成したユーザーにしか見せない
+    pub fn is_visible_to(&self, viewer: Option<&User>, recruitment: &Recruitment) -> bool {
+        match viewer {
+            Some(viewer) => viewer.id == self.user_id || viewer.id == recruitment.user_id,
+            None => false,
+        }
+    }
+}
+
+#[Object]
+/// 募集への応募
+impl Application {
+    pub async fn id(&self) -> ID {
+        id_encode(NodeType::Application, self.id).into()
+    }
+    /// 応募時のメッセージ
+    async fn message(&self) -> &str {
+        &self.message
+    }
+    /// 応募のステータス
+    async fn status(&self) -> ApplicationStatus {
+        self.status
+    }
+    /// 応募したユーザー
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        let loaders = get_loaders(ctx).await;
+        let user = loaders.user_loader.load_one(self.user_id).await?;
+        match user {
+            Some(user) => Ok(user),
+            None => Err(async_graphql::Error::new(String::from("User are a must!"))),
+        }
+    }
+    /// 応募先の募集
+    async fn recruitment(&self, ctx: &Context<'_>) -> async_graphql::Result<Recruitment> {
+        let loaders = get_loaders(ctx).await;
+        let recruitment = loaders
+            .recruitment_loader
+            .load_one(self.recruitment_id)
+            .await?;
+        match recruitment {
+            Some(recruitment) => Ok(recruitment),
+            None => Err(async_graphql::Error::new(String::from(
+                "Recruitment are a must!",
+            ))),
+        }
+    }
+    /// キャンセル待ちの順番 1から始まる キャンセル待ちでなければnull
+    async fn waitlist_position(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<i64>> {
+        if self.status != ApplicationStatus::Waitlisted {
+            return Ok(None);
+        }
+        let pool = get_db_pool(ctx).await?;
+        let position = get_waitlist_position(pool, self).await?;
+        Ok(Some(position))
+    }
+    /// ログインユーザー(Viewer)がこの応募に返答できるか
+    async fn viewer_can_respond(&self, ctx: &Context<'_>) -> async_graphql::Result<bool> {
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Ok(false),
+        };
+        let loaders = get_loaders(ctx).await;
+        let recruitment = loaders
+            .recruitment_loader
+            .load_one(self.recruitment_id)
+            .await?;
+        match recruitment {
+            Some(recruitment) => {
+                Ok(recruitment.user_id == viewer.id && self.status == ApplicationStatus::Pending)
+            }
+            None => Ok(false),
+        }
+    }
+    /// 募集者とのやり取り まだメッセージがなければnull
+    async fn message_thread(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<MessageThread>> {
+        let loaders = get_loaders(ctx).await;
+        let thread = loaders
+            .message_thread_loader
+            .load_one([self.user_id, self.recruitment_id])
+            .await?;
+        Ok(thread)
+    }
+    /// 応募した日時
+    async fn created_at(&self) -> DateTime<Local> {
+        self.created_at
+    }
+    /// 応募のステータスが変わった日時
+    async fn updated_at(&self) -> DateTime<Local> {
+        self.updated_at
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_application(pool: &PgPool, id: i64) -> Result<Option<Application>> {
+    let sql = "SELECT * FROM applications WHERE id = $1";
+    let row = sqlx::query_as::<_, Application>(sql)
+        .bind(id)
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(application) => {
+            tracing::info!("get application successed!!");
+            Ok(application)
+        }
+        Err(e) => {
+            tracing::error!("get application failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 募集への応募を新しい順に取得する
+#[tracing::instrument]
+pub async fn get_recruitment_applications(
+    pool: &PgPool,
+    recruitment_id: i64,
+    params: SearchParams,
+) -> Result<Vec<Application>> {
+    let sql = r#"
+        SELECT *
+        FROM applications
+        WHERE recruitment_id = $1
+        AND ($2 OR id < $3)
+        ORDER BY id DESC
+        LIMIT $4
+    "#;
+
+    let rows = sqlx::query_as::<_, Application>(sql)
+        .bind(recruitment_id)
+        .bind(!params.use_after)
+        .bind(params.after)
+        .bind(params.num_rows)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(applications) => {
+            tracing::info!("get recruitment applications successed!!");
+            Ok(applications)
+        }
+        Err(e) => {
+            tracing::error!("get recruitment applications failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_next_recruitment_application(
+    pool: &PgPool,
+    recruitment_id: i64,
+    id: i64,
+) -> Result<bool> {
+    let sql = r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM applications
+            WHERE recruitment_id = $1
+            AND id < $2
+        )
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(recruitment_id)
+        .bind(id)
+        .map(|row: PgRow| row.get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_next) => {
+            tracing::info!("is next recruitment application successed!!");
+            Ok(is_next)
+        }
+        Err(e) => {
+            tracing::error!("is next recruitment application failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// キャンセル待ちの中で何番目か
+#[tracing::instrument]
+pub async fn get_waitlist_position(pool: &PgPool, application: &Application) -> Result<i64> {
+    let sql = r#"
+        SELECT COUNT(*)
+        FROM applications
+        WHERE recruitment_id = $1
+        AND status = 'waitlisted'
+        AND (waitlisted_at, id) <= ($2, $3)
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(application.recruitment_id)
+        .bind(application.waitlisted_at)
+        .bind(application.id)
+        .map(|row: PgRow| row.get::<i64, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(position) => {
+            tracing::info!("get waitlist position successed!!");
+            Ok(position)
+        }
+        Err(e) => {
+            tracing::error!("get waitlist position failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 募集の定員とステータスごとの応募の数
+#[derive(Debug)]
+struct RecruitmentCapacity {
+    capacity: Option<i32>,
+    accepted_count: i64,
+    pending_count: i64,
+    waitlisted_count: i64,
+}
+
+impl RecruitmentCapacity {
+    fn is_full(&self, accepted_count: i64) -> bool {
+        match self.capacity {
+            Some(capacity) => accepted_count >= capacity as i64,
+            None => false,
+        }
+    }
+    // 空いている枠のうち返答待ちで埋まっていない数 定員がなければNone
+    fn promotable_count(&self) -> Option<i64> {
+        self.capacity
+            .map(|capacity| (capacity as i64 - self.accepted_count - self.pending_count).max(0))
+    }
+}
+
+// 同じ募集への応募の処理が同時に定員を数えないように募集の行をロックする
+#[tracing::instrument]
+async fn lock_recruitment_capacity_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_id: i64,
+) -> Result<RecruitmentCapacity> {
+    let sql = r#"
+        SELECT r.capacity, c.accepted_count, c.pending_count, c.waitlisted_count
+        FROM recruitments as r
+        CROSS JOIN LATERAL (
+            SELECT
+                COUNT(*) FILTER (WHERE a.status = 'accepted') AS accepted_count,
+                COUNT(*) FILTER (WHERE a.status = 'pending') AS pending_count,
+                COUNT(*) FILTER (WHERE a.status = 'waitlisted') AS waitlisted_count
+            FROM applications as a
+            WHERE a.recruitment_id = r.id
+        ) as c
+        WHERE r.id = $1
+        FOR UPDATE OF r
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(recruitment_id)
+        .map(|row: PgRow| RecruitmentCapacity {
+            capacity: row.get("capacity"),
+            accepted_count: row.get("accepted_count"),
+            pending_count: row.get("pending_count"),
+            waitlisted_count: row.get("waitlisted_count"),
+        })
+        .fetch_one(tx)
+        .await;
+
+    match row {
+        Ok(capacity) => {
+            tracing::info!("lock recruitment capacity successed!!");
+            Ok(capacity)
+        }
+        Err(e) => {
+            tracing::error!("lock recruitment capacity failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 募集に応募する 定員に達しているかキャンセル待ちがいればキャンセル待ちの最後になる
+// 取り下げた応募は再び応募できる 応募した日時も再び応募した日時にしてキャンセル待ちの順番を最後にする
+// 既に応募中か断られている場合はNoneを返す
+#[tracing::instrument]
+pub async fn apply(
+    pool: &PgPool,
+    recruitment_id: i64,
+    user_id: i64,
+    message: &str,
+) -> Result<Option<Application>> {
+    let sql = r#"
+        INSERT INTO applications
+            (recruitment_id, user_id, message, status, waitlisted_at, created_at, updated_at)
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $6)
+        ON CONFLICT (recruitment_id, user_id) DO UPDATE
+        SET message = EXCLUDED.message, status = EXCLUDED.status,
+            waitlisted_at = EXCLUDED.waitlisted_at, created_at = EXCLUDED.created_at,
+            updated_at = EXCLUDED.updated_at
+        WHERE applications.status = 'withdrawn'
+        RETURNING *
+    "#;
+
+    let now = Local::now();
+    let mut tx = pool.begin().await?;
+
+    let capacity = match lock_recruitment_capacity_tx(&mut tx, recruitment_id).await {
+        Ok(capacity) => capacity,
+        Err(e) => {
+            tracing::error!("lock_recruitment_capacity_tx failed rollback...");
+            tx.rollback().await?;
+            return Err(e);
+        }
+    };
+    let (status, waitlisted_at) =
+        if capacity.is_full(capacity.accepted_count) || capacity.waitlisted_count > 0 {
+            (ApplicationStatus::Waitlisted, Some(now))
+        } else {
+            (ApplicationStatus::Pending, None)
+        };
+
+    let row = sqlx::query_as::<_, Application>(sql)
+        .bind(recruitment_id)
+        .bind(user_id)
+        .bind(message)
+        .bind(status)
+        .bind(waitlisted_at)
+        .bind(now)
+        .fetch_optional(&mut tx)
+        .await;
+
+    let application = match row {
+        Ok(application) => application,
+        Err(e) => {
+            tracing::error!("apply failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    tx.commit().await?;
+    tracing::info!("apply successed!!");
+    Ok(application)
+}
+
+// 応募のステータスを遷移させた結果
+#[derive(Debug)]
+pub enum ApplicationStatusChange {
+    Changed {
+        application: Application,
+        waitlist_changed: Vec<Application>, // キャンセル待ちに回った、または繰り上がった応募
+    },
+    Conflict,     // 遷移元のステータスでなくなっていた
+    CapacityFull, // 定員に達しているため承諾できない
+}
+
+// 応募のステータスを遷移させる
+// 定員に達したら残りの返答待ちをキャンセル待ちに回し、枠が空いたらキャンセル待ちの先頭から繰り上げる
+#[tracing::instrument]
+pub async fn change_status(
+    pool: &PgPool,
+    id: i64,
+    recruitment_id: i64,
+    transition: ApplicationStatusTransition,
+) -> Result<ApplicationStatusChange> {
+    let sql = r#"
+        UPDATE applications
+        SET status = $1, waitlisted_at = NULL, updated_at = $2
+        WHERE id = $3
+        AND status = ANY($4)
+        RETURNING *
+    "#;
+
+    let now = Local::now();
+    let mut tx = pool.begin().await?;
+
+    let capacity = match lock_recruitment_capacity_tx(&mut tx, recruitment_id).await {
+        Ok(capacity) => capacity,
+        Err(e) => {
+            tracing::error!("lock_recruitment_capacity_tx failed rollback...");
+            tx.rollback().await?;
+            return Err(e);
+        }
+    };
+    if transition == ApplicationStatusTransition::Accept
+        && capacity.is_full(capacity.accepted_count)
+    {
+        tracing::error!("recruitment capacity is full");
+        tx.rollback().await?;
+        return Ok(ApplicationStatusChange::CapacityFull);
+    }
+
+    let row = sqlx::query_as::<_, Application>(sql)
+        .bind(transition.to_status())
+        .bind(now)
+        .bind(id)
+        .bind(transition.from_statuses())
+        .fetch_optional(&mut tx)
+        .await;
+
+    let application = match row {
+        Ok(Some(application)) => application,
+        Ok(None) => {
+            tracing::error!("application status has already changed");
+            tx.rollback().await?;
+            return Ok(ApplicationStatusChange::Conflict);
+        }
+        Err(e) => {
+            tracing::error!("change application status failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    if transition == ApplicationStatusTransition::Accept
+        && capacity.is_full(capacity.accepted_count + 1)
+    {
+        if let Err(e) = close_when_full_tx(&mut tx, recruitment_id, now).await {
+            tracing::error!("close_when_full_tx failed rollback...");
+            tx.rollback().await?;
+            return Err(e);
+        }
+    }
+    let waitlist_changed = match sync_waitlist_tx(&mut tx, recruitment_id, now).await {
+        Ok(waitlist_changed) => waitlist_changed,
+        Err(e) => {
+            tracing::error!("sync_waitlist_tx failed rollback...");
+            tx.rollback().await?;
+            return Err(e);
+        }
+    };
+
+    tx.commit().await?;
+    tracing::info!("change application status successed!!");
+    Ok(ApplicationStatusChange::Changed {
+        application,
+        waitlist_changed,
+    })
+}
+
+// 承諾済みの数と定員に合わせてキャンセル待ちを1つの列として整える ステータスが変わった応募を返す
+// 定員に達していれば返答待ちを応募順にキャンセル待ちに回し、枠が空いていればキャンセル待ちの先頭から繰り上げる
+#[tracing::instrument]
+pub async fn sync_waitlist_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_id: i64,
+    now: DateTime<Local>,
+) -> Result<Vec<Application>> {
+    let capacity = lock_recruitment_capacity_tx(tx, recruitment_id).await?;
+    if capacity.is_full(capacity.accepted_count) {
+        if capacity.pending_count == 0 {
+            return Ok(Vec::new());
+        }
+        return waitlist_pending_tx(tx, recruitment_id, now).await;
+    }
+    match capacity.promotable_count() {
+        Some(0) => Ok(Vec::new()),
+        _ if capacity.waitlisted_count == 0 => Ok(Vec::new()),
+        limit => promote_waitlist_tx(tx, recruitment_id, now, limit).await,
+    }
+}
+
+// 返答待ちの応募をキャンセル待ちにする 応募した日時の順に並ぶように応募日時をキャンセル待ちの日時にする
+// 取り下げてから再び応募した場合の応募日時は再び応募した日時になっている
+#[tracing::instrument]
+async fn waitlist_pending_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_id: i64,
+    now: DateTime<Local>,
+) -> Result<Vec<Application>> {
+    let sql = r#"
+        UPDATE applications
+        SET status = 'waitlisted', waitlisted_at = created_at, updated_at = $1
+        WHERE recruitment_id = $2
+        AND status = 'pending'
+        RETURNING *
+    "#;
+
+    let rows = sqlx::query_as::<_, Application>(sql)
+        .bind(now)
+        .bind(recruitment_id)
+        .fetch_all(tx)
+        .await;
+
+    match rows {
+        Ok(waitlisted) => {
+            tracing::info!("waitlist pending applications successed!!");
+            Ok(waitlisted)
+        }
+        Err(e) => {
+            tracing::error!("waitlist pending applications failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// キャンセル待ちの先頭からlimit件を返答待ちに繰り上げる limitがNoneなら全て繰り上げる
+#[tracing::instrument]
+async fn promote_waitlist_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_id: i64,
+    now: DateTime<Local>,
+    limit: Option<i64>,
+) -> Result<Vec<Application>> {
+    let sql = r#"
+        UPDATE applications
+        SET status = 'pending', waitlisted_at = NULL, updated_at = $1
+        WHERE id IN (
+            SELECT id
+            FROM applications
+            WHERE recruitment_id = $2
+            AND status = 'waitlisted'
+            ORDER BY waitlisted_at, id
+            LIMIT $3
+        )
+        RETURNING *
+    "#;
+
+    let rows = sqlx::query_as::<_, Application>(sql)
+        .bind(now)
+        .bind(recruitment_id)
+        .bind(limit)
+        .fetch_all(tx)
+        .await;
+
+    match rows {
+        Ok(promoted) => {
+            tracing::info!("promote waitlist successed!!");
+            Ok(promoted)
+        }
+        Err(e) => {
+            tracing::error!("promote waitlist failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 定員に達したら締め切る設定の募集を締め切る
+#[tracing::instrument]
+async fn close_when_full_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_id: i64,
+    now: DateTime<Local>,
+) -> Result<()> {
+    let sql = r#"
+        UPDATE recruitments
+        SET status = 'closed', closed_at = $1, closed_reason = $2, updated_at = $1
+        WHERE id = $3
+        AND status = 'published'
+        AND close_when_full
+    "#;
+
+    let result = sqlx::query(sql)
+        .bind(now)
+        .bind(RecruitmentClosedReason::Full)
+        .bind(recruitment_id)
+        .execute(&mut *tx)
+        .await;
+
+    match result {
+        Ok(result) if result.rows_affected() > 0 => {
+            tracing::info!("close recruitment when full successed!!");
+            add_recruitment_status_history_tx(
+                tx,
+                recruitment_id,
+                RecruitmentStatus::Published,
+                RecruitmentStatus::Closed,
+                None,
+            )
+            .await
+        }
+        Ok(_) => Ok(()),
+        Err(e) => {
+            tracing::error!("close recruitment when full failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::{Duration, Local};
+use rand::Rng;
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::config::API_ORIGIN;
+
+use super::recruitment::Recruitment;
+
+// カレンダーに載せる過去の募集の日数
+const CALENDAR_PAST_DAYS: i64 = 90;
+// カレンダーに載せる募集の最大数
+const CALENDAR_MAX_EVENTS: i64 = 500;
+
+// カレンダーを購読するURL
+pub fn calendar_feed_url(token: &str) -> String {
+    format!("{}/ical/users/{}.ics", API_ORIGIN, token)
+}
+
+// 推測されないように十分な長さの乱数から作る
+fn generate_calendar_token() -> String {
+    let bytes = rand::thread_rng().gen::<[u8; 32]>();
+    encode_config(bytes, URL_SAFE_NO_PAD)
+}
+
+#[tracing::instrument]
+pub async fn get_calendar_token(pool: &PgPool, user_id: i64) -> Result<Option<String>> {
+    let sql = "SELECT token FROM calendar_tokens WHERE user_id = $1";
+
+    let row = sqlx::query(sql)
+        .bind(user_id)
+        .map(|row: PgRow| row.get::<String, _>("token"))
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(token) => {
+            tracing::info!("get calendar token successed!!");
+            Ok(token)
+        }
+        Err(e) => {
+            tracing::error!("get calendar token failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// カレンダーのトークンを作り直す 古いトークンのURLは使えなくなる
+#[tracing::instrument]
+pub async fn reset_calendar_token(pool: &PgPool, user_id: i64) -> Result<String> {
+    let sql = r#"
+        INSERT INTO calendar_tokens
+            (user_id, token, created_at)
+        VALUES
+            ($1, $2, $3)
+        ON CONFLICT (user_id) DO UPDATE
+        SET token = EXCLUDED.token, created_at = EXCLUDED.created_at
+        RETURNING token
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(user_id)
+        .bind(generate_calendar_token())
+        .bind(Local::now())
+        .map(|row: PgRow| row.get::<String, _>("token"))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(token) => {
+            tracing::info!("reset calendar token successed!!");
+            Ok(token)
+        }
+        Err(e) => {
+            tracing::error!("reset calendar token failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument(skip(token))]
+pub async fn get_user_id_by_calendar_token(pool: &PgPool, token: &str) -> Result<Option<i64>> {
+    let sql = "SELECT user_id FROM calendar_tokens WHERE token = $1";
+
+    let row = sqlx::query(sql)
+        .bind(token)
+        .map(|row: PgRow| row.get::<i64, _>("user_id"))
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(user_id) => {
+            tracing::info!("get user id by calendar token successed!!");
+            Ok(user_id)
+        }
+        Err(e) => {
+            tracing::error!("get user id by calendar token failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// ユーザーが作成した、ストックした、応募が承諾された募集を開催日時の順に取得する
+// 下書きと開催日時が決まっていない募集は載せない
+// 件数の上限を超える場合はこれからの募集を優先し、開催日時が近いものから残す
+#[tracing::instrument]
+pub async fn get_calendar_recruitments(pool: &PgPool, user_id: i64) -> Result<Vec<Recruitment>> {
+    let sql = r#"
+        SELECT *
+        FROM (
+            SELECT r.*
+            FROM recruitments as r
+            WHERE r.status <> 'draft'
+            AND r.start_at >= $2
+            AND (
+                r.user_id = $1
+                OR EXISTS (
+                    SELECT 1
+                    FROM stocks as s
+                    WHERE s.recruitment_id = r.id
+                    AND s.user_id = $1
+                )
+                OR EXISTS (
+                    SELECT 1
+                    FROM applications as a
+                    WHERE a.recruitment_id = r.id
+                    AND a.user_id = $1
+                    AND a.status = 'accepted'
+                )
+            )
+            ORDER BY r.start_at < $4, ABS(EXTRACT(EPOCH FROM r.start_at - $4)), r.id
+            LIMIT $3
+        ) as r
+        ORDER BY r.start_at, r.id
+    "#;
+
+    let now = Local::now();
+    let rows = sqlx::query_as::<_, Recruitment>(sql)
+        .bind(user_id)
+        .bind(now - Duration::days(CALENDAR_PAST_DAYS))
+        .bind(CALENDAR_MAX_EVENTS)
+        .bind(now)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(recruitments) => {
+            tracing::info!("get calendar recruitments successed!!");
+            Ok(recruitments)
+        }
+        Err(e) => {
+            tracing::error!("get calendar recruitments failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use sqlx::{postgres::PgRow, FromRow, PgPool, Row};
+
+use super::{
+    notification_setting::{DigestFrequency, CLOSING_SOON_DAYS, DEFAULT_EMAIL_FREQUENCY},
+    user::{EmailVerificationStatus, User},
+};
+
+// まとめメールに載せる応募があった募集
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct DigestApplication {
+    pub recruitment_id: i64,
+    pub title: String,
+    pub application_count: i64,
+}
+
+// まとめメールに載せるフォローしているユーザーの新しい募集
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct DigestFollowedRecruitment {
+    pub recruitment_id: i64,
+    pub title: String,
+    pub user_name: String,
+}
+
+// まとめメールに載せる締め切りが近いストックした募集
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct DigestClosingStock {
+    pub recruitment_id: i64,
+    pub title: String,
+    pub closing_at: DateTime<Local>,
+}
+
+#[derive(Debug, Default)]
+pub struct Digest {
+    pub applications: Vec<DigestApplication>,
+    pub followed_recruitments: Vec<DigestFollowedRecruitment>,
+    pub closing_stocks: Vec<DigestClosingStock>,
+}
+
+impl Digest {
+    pub fn is_empty(&self) -> bool {
+        self.applications.is_empty()
+            && self.followed_recruitments.is_empty()
+            && self.closing_stocks.is_empty()
+    }
+}
+
+// 前回から頻度の期間が過ぎているユーザーを取得し、送る前に送った日時をnowに進めて確保する
+// 複数のサーバーで同時に実行しても送った日時を進められたユーザーだけを返すので二重に送らない
+// 前回送った日時も一緒に返す
+#[tracing::instrument]
+pub async fn claim_due_digest_users(
+    pool: &PgPool,
+    frequency: DigestFrequency,
+    due_at: DateTime<Local>,
+    now: DateTime<Local>,
+    limit: i64,
+) -> Result<Vec<(User, Option<DateTime<Local>>)>> {
+    let sql = r#"
+        WITH due AS (
+            SELECT u.id, d.delivered_at
+            FROM users as u
+            LEFT JOIN digest_deliveries as d
+                ON d.user_id = u.id AND d.frequency = $1
+            WHERE u.email_verification_status = $2
+            AND (d.delivered_at IS NULL OR d.delivered_at <= $3)
+            AND EXISTS (
+                SELECT 1
+                FROM unnest(enum_range(NULL::notification_event)) as e(event)
+                LEFT JOIN notification_settings as s
+                    ON s.user_id = u.id AND s.event = e.event
+                WHERE COALESCE(s.email_frequency, $4) = $1
+            )
+            ORDER BY u.id
+            LIMIT $5
+        ),
+        claimed AS (
+            INSERT INTO digest_deliveries
+                (user_id, frequency, delivered_at)
+            SELECT id, $1, $6
+            FROM due
+            ON CONFLICT (user_id, frequency) DO UPDATE
+            SET delivered_at = EXCLUDED.delivered_at
+            WHERE digest_deliveries.delivered_at <= $3
+            RETURNING user_id
+        )
+        SELECT u.*, due.delivered_at as last_delivered_at
+        FROM claimed
+        INNER JOIN users as u
+            ON u.id = claimed.user_id
+        INNER JOIN due
+            ON due.id = claimed.user_id
+        ORDER BY u.id
+    "#;
+
+    let rows = sqlx::query(sql)
+        .bind(frequency)
+        .bind(EmailVerificationStatus::Verified)
+        .bind(due_at)
+        .bind(DEFAULT_EMAIL_FREQUENCY)
+        .bind(limit)
+        .bind(now)
+        .try_map(|row: PgRow| {
+            let user = User::from_row(&row)?;
+            let last_delivered_at = row.try_get("last_delivered_at")?;
+            Ok((user, last_delivered_at))
+        })
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(users) => {
+            tracing::info!("claim due digest users successed!!");
+            Ok(users)
+        }
+        Err(e) => {
+            tracing::error!("claim due digest users failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// まとめメールの頻度がfrequencyのイベントについてsince以降の内容を集める
+#[tracing::instrument]
+pub async fn get_digest(
+    pool: &PgPool,
+    user_id: i64,
+    frequency: DigestFrequency,
+    since: DateTime<Local>,
+    now: DateTime<Local>,
+) -> Result<Digest> {
+    // イベントの設定がこの頻度か 設定していなければ既定の頻度で判定する
+    let setting_condition = |event: &str| {
+        format!(
+            r#"
+            COALESCE(
+                (SELECT email_frequency FROM notification_settings WHERE user_id = $1 AND event = '{}'),
+                $2
+            ) = $3
+            "#,
+            event
+        )
+    };
+    let applications_sql = format!(
+        r#"
+        SELECT r.id as recruitment_id, r.title, COUNT(a.id) as application_count
+        FROM applications as a
+        INNER JOIN recruitments as r
+            ON r.id = a.recruitment_id
+        WHERE r.user_id = $1
+        AND a.status IN ('pending', 'waitlisted')
+        AND a.created_at > $4
+        AND {}
+        GROUP BY r.id, r.title
+        ORDER BY r.id DESC
+    "#,
+        setting_condition("application")
+    );
+    let applications = sqlx::query_as::<_, DigestApplication>(&applications_sql)
+        .bind(user_id)
+        .bind(DEFAULT_EMAIL_FREQUENCY)
+        .bind(frequency)
+        .bind(since)
+        .fetch_all(pool)
+        .await;
+
+    let followed_recruitments_sql = format!(
+        r#"
+        SELECT r.id as recruitment_id, r.title, u.name as user_name
+        FROM recruitments as r
+        INNER JOIN relationships as f
+            ON f.followed_id = r.user_id
+        INNER JOIN users as u
+            ON u.id = r.user_id
+        WHERE f.follower_id = $1
+        AND r.status = 'published'
+        AND r.published_at > $4
+        AND {}
+        ORDER BY r.published_at DESC
+    "#,
+        setting_condition("followed_recruitment")
+    );
+    let followed_recruitments =
+        sqlx::query_as::<_, DigestFollowedRecruitment>(&followed_recruitments_sql)
+            .bind(user_id)
+            .bind(DEFAULT_EMAIL_FREQUENCY)
+            .bind(frequency)
+            .bind(since)
+            .fetch_all(pool)
+            .await;
+
+    // 前回から今回までの間に締め切りが近くなった募集を載せる
+    let closing_stocks_sql = format!(
+        r#"
+        SELECT r.id as recruitment_id, r.title, r.closing_at
+        FROM stocks as s
+        INNER JOIN recruitments as r
+            ON r.id = s.recruitment_id
+        WHERE s.user_id = $1
+        AND r.status = 'published'
+        AND r.closing_at > $5
+        AND r.closing_at > $6
+        AND r.closing_at <= $7
+        AND {}
+        ORDER BY r.closing_at
+    "#,
+        setting_condition("closing_stock")
+    );
+    let closing_stocks = sqlx::query_as::<_, DigestClosingStock>(&closing_stocks_sql)
+        .bind(user_id)
+        .bind(DEFAULT_EMAIL_FREQUENCY)
+        .bind(frequency)
+        .bind(since)
+        .bind(now)
+        .bind(since + Duration::days(CLOSING_SOON_DAYS))
+        .bind(now + Duration::days(CLOSING_SOON_DAYS))
+        .fetch_all(pool)
+        .await;
+
+    match (applications, followed_recruitments, closing_stocks) {
+        (Ok(applications), Ok(followed_recruitments), Ok(closing_stocks)) => {
+            tracing::info!("get digest successed!!");
+            Ok(Digest {
+                applications,
+                followed_recruitments,
+                closing_stocks,
+            })
+        }
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            tracing::error!("get digest failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 送れなかったまとめメールの確保を取り消して次の確認で送り直せるように前回送った日時に戻す
+// 確保した後に他で送った日時が変わっていれば何もしない
+#[tracing::instrument]
+pub async fn release_digest_claim(
+    pool: &PgPool,
+    user_id: i64,
+    frequency: DigestFrequency,
+    claimed_at: DateTime<Local>,
+    last_delivered_at: Option<DateTime<Local>>,
+) -> Result<()> {
+    let sql = match last_delivered_at {
+        Some(_) => {
+            r#"
+            UPDATE digest_deliveries
+            SET delivered_at = $4
+            WHERE user_id = $1
+            AND frequency = $2
+            AND delivered_at = $3
+            "#
+        }
+        None => {
+            r#"
+            DELETE FROM digest_deliveries
+            WHERE user_id = $1
+            AND frequency = $2
+            AND delivered_at = $3
+            "#
+        }
+    };
+
+    let result = sqlx::query(sql)
+        .bind(user_id)
+        .bind(frequency)
+        .bind(claimed_at)
+        .bind(last_delivered_at)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("release digest claim successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("release digest claim failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
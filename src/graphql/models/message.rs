@@ -0,0 +1,554 @@
+use anyhow::Result;
+use async_graphql::{Context, Object, ID};
+use chrono::{DateTime, Local};
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::{
+    database::get_db_pool,
+    graphql::{
+        auth::get_viewer,
+        id_encode,
+        loader::get_loaders,
+        resolvers::message_resolver::{MessageConnection, MessageEdge},
+        utils::pagination::{PageInfo, SearchParams, SortedSearchParams},
+        NodeType,
+    },
+};
+
+use super::{recruitment::Recruitment, user::User};
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct MessageThread {
+    pub id: i64,
+    pub recruitment_id: i64,
+    pub owner_id: i64,
+    pub applicant_id: i64,
+    pub last_message_id: i64,
+    pub owner_last_read_message_id: i64,
+    pub applicant_last_read_message_id: i64,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+}
+
+impl MessageThread {
+    // やり取りは募集者と応募者の2人にしか見せない
+    pub fn is_visible_to(&self, viewer: Option<&User>) -> bool {
+        match viewer {
+            Some(viewer) => viewer.id == self.owner_id || viewer.id == self.applicant_id,
+            None => false,
+        }
+    }
+    // ユーザーが最後に読んだメッセージのID
+    fn last_read_message_id(&self, user_id: i64) -> i64 {
+        if user_id == self.owner_id {
+            self.owner_last_read_message_id
+        } else {
+            self.applicant_last_read_message_id
+        }
+    }
+}
+
+#[Object]
+/// 募集者と応募者のメッセージのやり取り
+impl MessageThread {
+    pub async fn id(&self) -> ID {
+        id_encode(NodeType::MessageThread, self.id).into()
+    }
+    /// やり取りしている募集
+    async fn recruitment(&self, ctx: &Context<'_>) -> async_graphql::Result<Recruitment> {
+        let loaders = get_loaders(ctx).await;
+        let recruitment = loaders
+            .recruitment_loader
+            .load_one(self.recruitment_id)
+            .await?;
+        match recruitment {
+            Some(recruitment) => Ok(recruitment),
+            None => Err(async_graphql::Error::new(String::from(
+                "Recruitment are a must!",
+            ))),
+        }
+    }
+    /// 応募したユーザー
+    async fn applicant(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        let loaders = get_loaders(ctx).await;
+        let user = loaders.user_loader.load_one(self.applicant_id).await?;
+        match user {
+            Some(user) => Ok(user),
+            None => Err(async_graphql::Error::new(String::from("User are a must!"))),
+        }
+    }
+    /// メッセージのリスト 新しい順
+    async fn messages(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<ID>,
+        first: Option<i32>,
+    ) -> async_graphql::Result<MessageConnection> {
+        let pool = get_db_pool(ctx).await?;
+        let params = SearchParams::new(after, first, NodeType::Message)?;
+        let messages = get_thread_messages(pool, self.id, params).await?;
+
+        let edges: Vec<Option<MessageEdge>> = messages
+            .iter()
+            .map(|message| {
+                MessageEdge {
+                    node: message.to_owned(),
+                }
+                .into()
+            })
+            .collect();
+
+        let page_info = match messages.last() {
+            Some(message) => {
+                let has_next_page = is_next_thread_message(pool, self.id, message.id).await?;
+                let end_cursor = Some(id_encode(NodeType::Message, message.id));
+                PageInfo {
+                    has_next_page,
+                    end_cursor,
+                    ..Default::default()
+                }
+            }
+            None => Default::default(),
+        };
+
+        Ok(MessageConnection {
+            edges: edges.into(),
+            page_info,
+        })
+    }
+    /// ログインユーザー(Viewer)が読んでいないメッセージの数
+    async fn unread_count(&self, ctx: &Context<'_>) -> async_graphql::Result<i64> {
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Ok(0),
+        };
+        let pool = get_db_pool(ctx).await?;
+        let count = count_unread_messages(pool, self, viewer.id).await?;
+        Ok(count)
+    }
+    /// やり取りを始めた日時
+    async fn created_at(&self) -> DateTime<Local> {
+        self.created_at
+    }
+    /// 最後にメッセージが送られた日時
+    async fn updated_at(&self) -> DateTime<Local> {
+        self.updated_at
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Message {
+    pub id: i64,
+    pub thread_id: i64,
+    pub user_id: i64,
+    pub body: String,
+    pub created_at: DateTime<Local>,
+}
+
+#[Object]
+/// メッセージ
+impl Message {
+    pub async fn id(&self) -> ID {
+        id_encode(NodeType::Message, self.id).into()
+    }
+    /// メッセージの本文
+    async fn body(&self) -> &str {
+        &self.body
+    }
+    /// メッセージを送ったユーザー
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        let loaders = get_loaders(ctx).await;
+        let user = loaders.user_loader.load_one(self.user_id).await?;
+        match user {
+            Some(user) => Ok(user),
+            None => Err(async_graphql::Error::new(String::from("User are a must!"))),
+        }
+    }
+    /// メッセージを送った日時
+    async fn created_at(&self) -> DateTime<Local> {
+        self.created_at
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_message_thread(pool: &PgPool, id: i64) -> Result<Option<MessageThread>> {
+    let sql = "SELECT * FROM message_threads WHERE id = $1";
+    let row = sqlx::query_as::<_, MessageThread>(sql)
+        .bind(id)
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(thread) => {
+            tracing::info!("get message thread successed!!");
+            Ok(thread)
+        }
+        Err(e) => {
+            tracing::error!("get message thread failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 募集へのやり取りを最後にメッセージが送られた順に取得する
+#[tracing::instrument]
+pub async fn get_recruitment_message_threads(
+    pool: &PgPool,
+    recruitment_id: i64,
+    params: &SortedSearchParams<i64>,
+) -> Result<Vec<MessageThread>> {
+    let sql = r#"
+        SELECT *
+        FROM message_threads
+        WHERE recruitment_id = $1
+        AND ($2 OR (last_message_id, id) < ($3, $4))
+        ORDER BY last_message_id DESC, id DESC
+        LIMIT $5
+    "#;
+
+    let (last_message_id, id) = params.after.unwrap_or_default();
+    let rows = sqlx::query_as::<_, MessageThread>(sql)
+        .bind(recruitment_id)
+        .bind(params.after.is_none())
+        .bind(last_message_id)
+        .bind(id)
+        .bind(params.num_rows)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(threads) => {
+            tracing::info!("get recruitment message threads successed!!");
+            Ok(threads)
+        }
+        Err(e) => {
+            tracing::error!("get recruitment message threads failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_next_recruitment_message_thread(
+    pool: &PgPool,
+    recruitment_id: i64,
+    thread: &MessageThread,
+) -> Result<bool> {
+    let sql = r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM message_threads
+            WHERE recruitment_id = $1
+            AND (last_message_id, id) < ($2, $3)
+        )
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(recruitment_id)
+        .bind(thread.last_message_id)
+        .bind(thread.id)
+        .map(|row: PgRow| row.get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_next) => {
+            tracing::info!("is next recruitment message thread successed!!");
+            Ok(is_next)
+        }
+        Err(e) => {
+            tracing::error!("is next recruitment message thread failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_thread_messages(
+    pool: &PgPool,
+    thread_id: i64,
+    params: SearchParams,
+) -> Result<Vec<Message>> {
+    let sql = r#"
+        SELECT *
+        FROM messages
+        WHERE thread_id = $1
+        AND ($2 OR id < $3)
+        ORDER BY id DESC
+        LIMIT $4
+    "#;
+
+    let rows = sqlx::query_as::<_, Message>(sql)
+        .bind(thread_id)
+        .bind(!params.use_after)
+        .bind(params.after)
+        .bind(params.num_rows)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(messages) => {
+            tracing::info!("get thread messages successed!!");
+            Ok(messages)
+        }
+        Err(e) => {
+            tracing::error!("get thread messages failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_next_thread_message(pool: &PgPool, thread_id: i64, id: i64) -> Result<bool> {
+    let sql = r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM messages
+            WHERE thread_id = $1
+            AND id < $2
+        )
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(thread_id)
+        .bind(id)
+        .map(|row: PgRow| row.get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_next) => {
+            tracing::info!("is next thread message successed!!");
+            Ok(is_next)
+        }
+        Err(e) => {
+            tracing::error!("is next thread message failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// メッセージを送る やり取りがまだなければ作る
+// 送ったユーザーは自分のメッセージまで既読にする
+#[tracing::instrument]
+pub async fn send_message(
+    pool: &PgPool,
+    recruitment: &Recruitment,
+    applicant_id: i64,
+    user_id: i64,
+    body: &str,
+) -> Result<(MessageThread, Message)> {
+    let thread_sql = r#"
+        INSERT INTO message_threads
+            (recruitment_id, owner_id, applicant_id, created_at, updated_at)
+        VALUES
+            ($1, $2, $3, $4, $4)
+        ON CONFLICT (recruitment_id, applicant_id) DO UPDATE
+        SET updated_at = EXCLUDED.updated_at
+        RETURNING id
+    "#;
+    let message_sql = r#"
+        INSERT INTO messages
+            (thread_id, user_id, body, created_at)
+        VALUES
+            ($1, $2, $3, $4)
+        RETURNING *
+    "#;
+    let read_sql = r#"
+        UPDATE message_threads
+        SET last_message_id = $1,
+            owner_last_read_message_id = CASE
+                                             WHEN owner_id = $2 THEN $1
+                                             ELSE owner_last_read_message_id
+                                         END,
+            applicant_last_read_message_id = CASE
+                                                 WHEN applicant_id = $2 THEN $1
+                                                 ELSE applicant_last_read_message_id
+                                             END
+        WHERE id = $3
+        RETURNING *
+    "#;
+
+    let now = Local::now();
+    let mut tx = pool.begin().await?;
+
+    let thread_id = match sqlx::query(thread_sql)
+        .bind(recruitment.id)
+        .bind(recruitment.user_id)
+        .bind(applicant_id)
+        .bind(now)
+        .map(|row: PgRow| row.get::<i64, _>("id"))
+        .fetch_one(&mut tx)
+        .await
+    {
+        Ok(thread_id) => thread_id,
+        Err(e) => {
+            tracing::error!("create message thread failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    let message = match sqlx::query_as::<_, Message>(message_sql)
+        .bind(thread_id)
+        .bind(user_id)
+        .bind(body)
+        .bind(now)
+        .fetch_one(&mut tx)
+        .await
+    {
+        Ok(message) => message,
+        Err(e) => {
+            tracing::error!("create message failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    let thread = match sqlx::query_as::<_, MessageThread>(read_sql)
+        .bind(message.id)
+        .bind(user_id)
+        .bind(thread_id)
+        .fetch_one(&mut tx)
+        .await
+    {
+        Ok(thread) => thread,
+        Err(e) => {
+            tracing::error!("update message thread failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    tx.commit().await?;
+    tracing::info!("send message successed!!");
+    Ok((thread, message))
+}
+
+// やり取りのメッセージを最新まで既読にする
+#[tracing::instrument]
+pub async fn mark_message_thread_read(
+    pool: &PgPool,
+    thread_id: i64,
+    user_id: i64,
+) -> Result<MessageThread> {
+    let sql = r#"
+        UPDATE message_threads
+        SET owner_last_read_message_id = CASE
+                                             WHEN owner_id = $1 THEN last_message_id
+                                             ELSE owner_last_read_message_id
+                                         END,
+            applicant_last_read_message_id = CASE
+                                                 WHEN applicant_id = $1 THEN last_message_id
+                                                 ELSE applicant_last_read_message_id
+                                             END
+        WHERE id = $2
+        RETURNING *
+    "#;
+
+    let row = sqlx::query_as::<_, MessageThread>(sql)
+        .bind(user_id)
+        .bind(thread_id)
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(thread) => {
+            tracing::info!("mark message thread read successed!!");
+            Ok(thread)
+        }
+        Err(e) => {
+            tracing::error!("mark message thread read failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 相手から送られたメッセージのうち読んでいないものの数
+#[tracing::instrument]
+pub async fn count_unread_messages(
+    pool: &PgPool,
+    thread: &MessageThread,
+    user_id: i64,
+) -> Result<i64> {
+    let sql = r#"
+        SELECT COUNT(*)
+        FROM messages
+        WHERE thread_id = $1
+        AND id > $2
+        AND user_id <> $3
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(thread.id)
+        .bind(thread.last_read_message_id(user_id))
+        .bind(user_id)
+        .map(|row: PgRow| row.get::<i64, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(count) => {
+            tracing::info!("count unread messages successed!!");
+            Ok(count)
+        }
+        Err(e) => {
+            tracing::error!("count unread messages failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// ユーザーが参加している全てのやり取りで読んでいないメッセージの数
+#[tracing::instrument]
+pub async fn count_user_unread_messages(pool: &PgPool, user_id: i64) -> Result<i64> {
+    let sql = r#"
+        SELECT COUNT(*)
+        FROM message_threads as t
+        INNER JOIN messages as m
+            ON m.thread_id = t.id
+        WHERE (t.owner_id = $1 OR t.applicant_id = $1)
+        AND m.user_id <> $1
+        AND m.id > CASE
+                       WHEN t.owner_id = $1 THEN t.owner_last_read_message_id
+                       ELSE t.applicant_last_read_message_id
+                   END
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(user_id)
+        .map(|row: PgRow| row.get::<i64, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(count) => {
+            tracing::info!("count user unread messages successed!!");
+            Ok(count)
+        }
+        Err(e) => {
+            tracing::error!("count user unread messages failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_message(pool: &PgPool, id: i64) -> Result<Option<Message>> {
+    let sql = "SELECT * FROM messages WHERE id = $1";
+    let row = sqlx::query_as::<_, Message>(sql)
+        .bind(id)
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(message) => {
+            tracing::info!("get message successed!!");
+            Ok(message)
+        }
+        Err(e) => {
+            tracing::error!("get message failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -0,0 +1,590 @@
+use anyhow::Result;
+use async_graphql::{Context, Enum, Object, ID};
+use chrono::{DateTime, Duration, Local};
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::graphql::{
+    id_encode, loader::get_loaders, utils::pagination::SortedSearchParams, NodeType,
+};
+
+use super::{
+    notification_setting::{
+        is_in_app_enabled, NotificationEvent, CLOSING_SOON_DAYS, DEFAULT_IN_APP,
+    },
+    recruitment::Recruitment,
+    user::User,
+};
+
+// まとめた通知で名前を出すユーザーの数
+const NOTIFICATION_ACTORS_LIMIT: i64 = 3;
+
+/// 通知の種類
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "notification_kind")]
+#[sqlx(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// フォローされた
+    Followed,
+    /// 募集がストックされた
+    Stocked,
+    /// 募集に応募された
+    Applied,
+    /// 応募が承諾された
+    ApplicationAccepted,
+    /// 応募が断られた
+    ApplicationRejected,
+    /// フォローしているユーザーが募集を公開した
+    RecruitmentPublished,
+    /// ストックした募集の締め切りが近い
+    StockClosing,
+    /// 応募またはストックした募集の開催日時か開催場所が変更された
+    RecruitmentChanged,
+}
+
+impl NotificationKind {
+    // 通知の設定でアプリ内通知を止められるイベント
+    fn event(&self) -> Option<NotificationEvent> {
+        match self {
+            Self::Applied => Some(NotificationEvent::Application),
+            Self::RecruitmentPublished => Some(NotificationEvent::FollowedRecruitment),
+            Self::StockClosing => Some(NotificationEvent::ClosingStock),
+            Self::Followed
+            | Self::Stocked
+            | Self::ApplicationAccepted
+            | Self::ApplicationRejected
+            | Self::RecruitmentChanged => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct Notification {
+    pub id: i64,
+    pub user_id: i64,
+    pub kind: NotificationKind,
+    pub recruitment_id: Option<i64>,
+    pub sort_key: i64,
+    pub read_at: Option<DateTime<Local>>,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+    pub actor_ids: Vec<i64>, // 最近行動したユーザーから順に
+    pub actor_count: i64,
+}
+
+impl Notification {
+    // 通知は受け取ったユーザーにしか見せない
+    pub fn is_visible_to(&self, viewer: Option<&User>) -> bool {
+        match viewer {
+            Some(viewer) => viewer.id == self.user_id,
+            None => false,
+        }
+    }
+}
+
+#[Object]
+/// 通知 未読の間は同じ種類と募集の通知が1つにまとめられる
+impl Notification {
+    pub async fn id(&self) -> ID {
+        id_encode(NodeType::Notification, self.id).into()
+    }
+    /// 通知の種類
+    async fn kind(&self) -> NotificationKind {
+        self.kind
+    }
+    /// 通知に関係する募集 フォローの通知ではnull
+    async fn recruitment(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Recruitment>> {
+        let recruitment_id = match self.recruitment_id {
+            Some(recruitment_id) => recruitment_id,
+            None => return Ok(None),
+        };
+        let loaders = get_loaders(ctx).await;
+        let recruitment = loaders.recruitment_loader.load_one(recruitment_id).await?;
+        Ok(recruitment)
+    }
+    /// 最近行動したユーザー 最大3人
+    async fn actors(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<User>> {
+        let loaders = get_loaders(ctx).await;
+        let users = loaders
+            .user_loader
+            .load_many(self.actor_ids.iter().copied())
+            .await?;
+        let actors = self
+            .actor_ids
+            .iter()
+            .filter_map(|id| users.get(id).cloned())
+            .collect();
+        Ok(actors)
+    }
+    /// 行動したユーザーの数
+    async fn actor_count(&self) -> i64 {
+        self.actor_count
+    }
+    /// 表示用の文章 例: 「山田さん他2人があなたの募集「...」をストックしました」
+    async fn message(&self, ctx: &Context<'_>) -> async_graphql::Result<String> {
+        let loaders = get_loaders(ctx).await;
+        let actor = match self.actor_ids.first() {
+            Some(actor_id) => loaders.user_loader.load_one(*actor_id).await?,
+            None => None,
+        };
+        let actors = match (actor, self.actor_count) {
+            (Some(actor), 1) => format!("{}さん", actor.name),
+            (Some(actor), count) => format!("{}さん他{}人", actor.name, count - 1),
+            (None, count) => format!("{}人", count),
+        };
+        let title = match self.recruitment_id {
+            Some(recruitment_id) => loaders
+                .recruitment_loader
+                .load_one(recruitment_id)
+                .await?
+                .map(|recruitment| recruitment.title)
+                .unwrap_or_default(),
+            None => String::new(),
+        };
+
+        let message = match self.kind {
+            NotificationKind::Followed => format!("{}があなたをフォローしました", actors),
+            NotificationKind::Stocked => {
+                format!("{}があなたの募集「{}」をストックしました", actors, title)
+            }
+            NotificationKind::Applied => {
+                format!("{}があなたの募集「{}」に応募しました", actors, title)
+            }
+            NotificationKind::ApplicationAccepted => {
+                format!("募集「{}」への応募が承諾されました", title)
+            }
+            NotificationKind::ApplicationRejected => {
+                format!("募集「{}」への応募が見送られました", title)
+            }
+            NotificationKind::RecruitmentPublished => {
+                format!("{}が募集「{}」を公開しました", actors, title)
+            }
+            NotificationKind::StockClosing => {
+                format!("ストックした募集「{}」がまもなく締め切られます", title)
+            }
+            NotificationKind::RecruitmentChanged => {
+                format!("募集「{}」の開催日時または開催場所が変更されました", title)
+            }
+        };
+        Ok(message)
+    }
+    /// 既読か
+    async fn is_read(&self) -> bool {
+        self.read_at.is_some()
+    }
+    /// 最初に通知された日時
+    async fn created_at(&self) -> DateTime<Local> {
+        self.created_at
+    }
+    /// 最後に通知がまとめられた日時
+    async fn updated_at(&self) -> DateTime<Local> {
+        self.updated_at
+    }
+}
+
+// 通知に最近行動したユーザーと人数を付けて取得するSELECT句
+const NOTIFICATION_COLUMNS: &str = r#"
+    n.*,
+    ARRAY(
+        SELECT n_a.user_id
+        FROM notification_actors as n_a
+        WHERE n_a.notification_id = n.id
+        ORDER BY n_a.created_at DESC, n_a.user_id DESC
+        LIMIT $1
+    ) as actor_ids,
+    (
+        SELECT COUNT(*)
+        FROM notification_actors as n_a
+        WHERE n_a.notification_id = n.id
+    ) as actor_count
+"#;
+
+// 通知する 同じ種類と募集の未読の通知があればそこに行動したユーザーを追加する
+// 自分の行動とアプリ内通知を止めているイベントは通知しない
+#[tracing::instrument]
+pub async fn notify(
+    pool: &PgPool,
+    user_id: i64,
+    kind: NotificationKind,
+    actor_id: i64,
+    recruitment_id: Option<i64>,
+) -> Result<()> {
+    if user_id == actor_id {
+        return Ok(());
+    }
+    if let Some(event) = kind.event() {
+        if !is_in_app_enabled(pool, user_id, event).await? {
+            return Ok(());
+        }
+    }
+
+    let sql = r#"
+        WITH n AS (
+            INSERT INTO notifications
+                (user_id, kind, recruitment_id, created_at, updated_at)
+            VALUES
+                ($1, $2, $3, $4, $4)
+            ON CONFLICT (user_id, kind, COALESCE(recruitment_id, 0)) WHERE read_at IS NULL
+            DO UPDATE
+            SET sort_key = nextval('notifications_sort_key_seq'), updated_at = EXCLUDED.updated_at
+            RETURNING id
+        )
+        INSERT INTO notification_actors
+            (notification_id, user_id, created_at)
+        SELECT id, $5, $4
+        FROM n
+        ON CONFLICT (notification_id, user_id) DO UPDATE
+        SET created_at = EXCLUDED.created_at
+    "#;
+
+    let result = sqlx::query(sql)
+        .bind(user_id)
+        .bind(kind)
+        .bind(recruitment_id)
+        .bind(Local::now())
+        .bind(actor_id)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("notify successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("notify failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 募集を公開したユーザーのフォロワーに通知する
+#[tracing::instrument]
+pub async fn notify_followers(pool: &PgPool, recruitment: &Recruitment) -> Result<()> {
+    let sql = r#"
+        WITH n AS (
+            INSERT INTO notifications
+                (user_id, kind, recruitment_id, created_at, updated_at)
+            SELECT f.follower_id, $1, $2, $3, $3
+            FROM relationships as f
+            LEFT JOIN notification_settings as s
+                ON s.user_id = f.follower_id AND s.event = $4
+            WHERE f.followed_id = $5
+            AND COALESCE(s.in_app, $6)
+            ON CONFLICT DO NOTHING
+            RETURNING id
+        )
+        INSERT INTO notification_actors
+            (notification_id, user_id, created_at)
+        SELECT id, $5, $3
+        FROM n
+    "#;
+
+    let result = sqlx::query(sql)
+        .bind(NotificationKind::RecruitmentPublished)
+        .bind(recruitment.id)
+        .bind(Local::now())
+        .bind(NotificationEvent::FollowedRecruitment)
+        .bind(recruitment.user_id)
+        .bind(DEFAULT_IN_APP)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("notify followers successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("notify followers failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 募集の開催日時か開催場所が変わったことを応募中のユーザーとストックしたユーザーに通知する
+// 未読の同じ通知があれば新しい通知としてまとめる
+#[tracing::instrument]
+pub async fn notify_recruitment_changed(
+    pool: &PgPool,
+    recruitment_id: i64,
+    actor_id: i64,
+) -> Result<()> {
+    let sql = r#"
+        WITH n AS (
+            INSERT INTO notifications
+                (user_id, kind, recruitment_id, created_at, updated_at)
+            SELECT u.user_id, $1, $2, $3, $3
+            FROM (
+                SELECT user_id
+                FROM applications
+                WHERE recruitment_id = $2
+                AND status IN ('pending', 'accepted', 'waitlisted')
+                UNION
+                SELECT user_id
+                FROM stocks
+                WHERE recruitment_id = $2
+            ) as u
+            WHERE u.user_id <> $4
+            ON CONFLICT (user_id, kind, COALESCE(recruitment_id, 0)) WHERE read_at IS NULL
+            DO UPDATE
+            SET sort_key = nextval('notifications_sort_key_seq'), updated_at = EXCLUDED.updated_at
+            RETURNING id
+        )
+        INSERT INTO notification_actors
+            (notification_id, user_id, created_at)
+        SELECT id, $4, $3
+        FROM n
+        ON CONFLICT (notification_id, user_id) DO UPDATE
+        SET created_at = EXCLUDED.created_at
+    "#;
+
+    let result = sqlx::query(sql)
+        .bind(NotificationKind::RecruitmentChanged)
+        .bind(recruitment_id)
+        .bind(Local::now())
+        .bind(actor_id)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("notify recruitment changed successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("notify recruitment changed failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 締め切りが近くなったストックした募集を通知する 同じ募集は一度だけ通知する
+#[tracing::instrument]
+pub async fn notify_closing_stocks(pool: &PgPool) -> Result<u64> {
+    let sql = r#"
+        WITH n AS (
+            INSERT INTO notifications
+                (user_id, kind, recruitment_id, created_at, updated_at)
+            SELECT s.user_id, $1, r.id, $2, $2
+            FROM stocks as s
+            INNER JOIN recruitments as r
+                ON r.id = s.recruitment_id
+            LEFT JOIN notification_settings as n_s
+                ON n_s.user_id = s.user_id AND n_s.event = $3
+            WHERE r.status = 'published'
+            AND r.closing_at > $2
+            AND r.closing_at <= $4
+            AND s.user_id <> r.user_id
+            AND COALESCE(n_s.in_app, $5)
+            AND NOT EXISTS (
+                SELECT 1
+                FROM notifications as e
+                WHERE e.user_id = s.user_id
+                AND e.kind = $1
+                AND e.recruitment_id = r.id
+            )
+            ON CONFLICT DO NOTHING
+            RETURNING id, recruitment_id
+        )
+        INSERT INTO notification_actors
+            (notification_id, user_id, created_at)
+        SELECT n.id, r.user_id, $2
+        FROM n
+        INNER JOIN recruitments as r
+            ON r.id = n.recruitment_id
+    "#;
+
+    let now = Local::now();
+    let result = sqlx::query(sql)
+        .bind(NotificationKind::StockClosing)
+        .bind(now)
+        .bind(NotificationEvent::ClosingStock)
+        .bind(now + Duration::days(CLOSING_SOON_DAYS))
+        .bind(DEFAULT_IN_APP)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(result) => {
+            tracing::info!("notify closing stocks successed!!");
+            Ok(result.rows_affected())
+        }
+        Err(e) => {
+            tracing::error!("notify closing stocks failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_notification(pool: &PgPool, id: i64) -> Result<Option<Notification>> {
+    let sql = format!(
+        "SELECT {} FROM notifications as n WHERE n.id = $2",
+        NOTIFICATION_COLUMNS
+    );
+    let row = sqlx::query_as::<_, Notification>(&sql)
+        .bind(NOTIFICATION_ACTORS_LIMIT)
+        .bind(id)
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(notification) => {
+            tracing::info!("get notification successed!!");
+            Ok(notification)
+        }
+        Err(e) => {
+            tracing::error!("get notification failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// ユーザーの通知を最後にまとめられた順に取得する
+#[tracing::instrument]
+pub async fn get_user_notifications(
+    pool: &PgPool,
+    user_id: i64,
+    params: &SortedSearchParams<i64>,
+) -> Result<Vec<Notification>> {
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM notifications as n
+        WHERE n.user_id = $2
+        AND ($3 OR (n.sort_key, n.id) < ($4, $5))
+        ORDER BY n.sort_key DESC, n.id DESC
+        LIMIT $6
+    "#,
+        NOTIFICATION_COLUMNS
+    );
+
+    let (sort_key, id) = params.after.unwrap_or_default();
+    let rows = sqlx::query_as::<_, Notification>(&sql)
+        .bind(NOTIFICATION_ACTORS_LIMIT)
+        .bind(user_id)
+        .bind(params.after.is_none())
+        .bind(sort_key)
+        .bind(id)
+        .bind(params.num_rows)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(notifications) => {
+            tracing::info!("get user notifications successed!!");
+            Ok(notifications)
+        }
+        Err(e) => {
+            tracing::error!("get user notifications failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_next_user_notification(
+    pool: &PgPool,
+    user_id: i64,
+    notification: &Notification,
+) -> Result<bool> {
+    let sql = r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM notifications
+            WHERE user_id = $1
+            AND (sort_key, id) < ($2, $3)
+        )
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(user_id)
+        .bind(notification.sort_key)
+        .bind(notification.id)
+        .try_map(|row: PgRow| row.try_get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_next) => {
+            tracing::info!("is next user notification successed!!");
+            Ok(is_next)
+        }
+        Err(e) => {
+            tracing::error!("is next user notification failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn count_unread_notifications(pool: &PgPool, user_id: i64) -> Result<i64> {
+    let sql = r#"
+        SELECT COUNT(*)
+        FROM notifications
+        WHERE user_id = $1
+        AND read_at IS NULL
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(user_id)
+        .try_map(|row: PgRow| row.try_get::<i64, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(count) => {
+            tracing::info!("count unread notifications successed!!");
+            Ok(count)
+        }
+        Err(e) => {
+            tracing::error!("count unread notifications failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 通知を既読にして既読にした通知を返す idsがNoneなら全ての未読の通知を既読にする
+#[tracing::instrument]
+pub async fn mark_notifications_read(
+    pool: &PgPool,
+    user_id: i64,
+    ids: Option<&[i64]>,
+) -> Result<Vec<Notification>> {
+    let sql = format!(
+        r#"
+        WITH n AS (
+            UPDATE notifications
+            SET read_at = $2
+            WHERE user_id = $3
+            AND read_at IS NULL
+            AND ($4 OR id = ANY($5))
+            RETURNING *
+        )
+        SELECT {}
+        FROM n
+        ORDER BY n.sort_key DESC, n.id DESC
+    "#,
+        NOTIFICATION_COLUMNS
+    );
+
+    let rows = sqlx::query_as::<_, Notification>(&sql)
+        .bind(NOTIFICATION_ACTORS_LIMIT)
+        .bind(Local::now())
+        .bind(user_id)
+        .bind(ids.is_none())
+        .bind(ids.unwrap_or_default())
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(notifications) => {
+            tracing::info!("mark notifications read successed!!");
+            Ok(notifications)
+        }
+        Err(e) => {
+            tracing::error!("mark notifications read failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use anyhow::Result;
+use async_graphql::{Enum, Object, SimpleObject};
+use chrono::{Duration, Local};
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+/// 通知の設定ができるイベント
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "notification_event")]
+#[sqlx(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    /// 自分の募集への応募
+    Application,
+    /// フォローしているユーザーの新しい募集
+    FollowedRecruitment,
+    /// ストックした募集の締め切りが近い
+    ClosingStock,
+}
+
+/// まとめメールの頻度
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "digest_frequency")]
+#[sqlx(rename_all = "lowercase")]
+pub enum DigestFrequency {
+    /// 送らない
+    Off,
+    /// 毎日
+    Daily,
+    /// 毎週
+    Weekly,
+}
+
+impl DigestFrequency {
+    // まとめメールを送る間隔
+    pub fn period(&self) -> Option<Duration> {
+        match self {
+            Self::Off => None,
+            Self::Daily => Some(Duration::days(1)),
+            Self::Weekly => Some(Duration::weeks(1)),
+        }
+    }
+}
+
+// ストックした募集の締め切りが近いとみなす日数
+pub const CLOSING_SOON_DAYS: i64 = 2;
+
+// 設定を変更していないイベントはアプリ内通知ありで毎日まとめメールを送る
+pub const DEFAULT_IN_APP: bool = true;
+pub const DEFAULT_EMAIL_FREQUENCY: DigestFrequency = DigestFrequency::Daily;
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct NotificationSetting {
+    pub user_id: i64,
+    pub event: NotificationEvent,
+    pub in_app: bool,
+    pub email_frequency: DigestFrequency,
+}
+
+impl NotificationSetting {
+    pub fn default_for(user_id: i64, event: NotificationEvent) -> Self {
+        Self {
+            user_id,
+            event,
+            in_app: DEFAULT_IN_APP,
+            email_frequency: DEFAULT_EMAIL_FREQUENCY,
+        }
+    }
+}
+
+#[Object]
+/// イベントごとの通知の設定
+impl NotificationSetting {
+    /// アプリ内で通知するか
+    async fn in_app(&self) -> bool {
+        self.in_app
+    }
+    /// まとめメールの頻度
+    async fn email_frequency(&self) -> DigestFrequency {
+        self.email_frequency
+    }
+}
+
+/// 通知の設定
+#[derive(SimpleObject, Debug)]
+pub struct NotificationSettings {
+    pub application: NotificationSetting,
+    pub followed_recruitment: NotificationSetting,
+    pub closing_stock: NotificationSetting,
+}
+
+// ユーザーの通知の設定を取得する 設定していないイベントは既定の設定を返す
+#[tracing::instrument]
+pub async fn get_notification_settings(
+    pool: &PgPool,
+    user_id: i64,
+) -> Result<NotificationSettings> {
+    let sql = "SELECT * FROM notification_settings WHERE user_id = $1";
+    let rows = sqlx::query_as::<_, NotificationSetting>(sql)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await;
+
+    let settings = match rows {
+        Ok(settings) => {
+            tracing::info!("get notification settings successed!!");
+            settings
+        }
+        Err(e) => {
+            tracing::error!("get notification settings failed: {:?}", e);
+            return Err(e.into());
+        }
+    };
+
+    let setting = |event: NotificationEvent| {
+        settings
+            .iter()
+            .find(|setting| setting.event == event)
+            .cloned()
+            .unwrap_or_else(|| NotificationSetting::default_for(user_id, event))
+    };
+    Ok(NotificationSettings {
+        application: setting(NotificationEvent::Application),
+        followed_recruitment: setting(NotificationEvent::FollowedRecruitment),
+        closing_stock: setting(NotificationEvent::ClosingStock),
+    })
+}
+
+// 指定された項目だけ通知の設定を変更する
+#[tracing::instrument]
+pub async fn update_notification_setting(
+    pool: &PgPool,
+    user_id: i64,
+    event: NotificationEvent,
+    in_app: Option<bool>,
+    email_frequency: Option<DigestFrequency>,
+) -> Result<()> {
+    let sql = r#"
+        INSERT INTO notification_settings
+            (user_id, event, in_app, email_frequency, created_at, updated_at)
+        VALUES
+            ($1, $2, $3, $4, $5, $5)
+        ON CONFLICT (user_id, event) DO UPDATE
+        SET in_app = COALESCE($6, notification_settings.in_app),
+            email_frequency = COALESCE($7, notification_settings.email_frequency),
+            updated_at = $5
+    "#;
+
+    let result = sqlx::query(sql)
+        .bind(user_id)
+        .bind(event)
+        .bind(in_app.unwrap_or(DEFAULT_IN_APP))
+        .bind(email_frequency.unwrap_or(DEFAULT_EMAIL_FREQUENCY))
+        .bind(Local::now())
+        .bind(in_app)
+        .bind(email_frequency)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("update notification setting successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("update notification setting failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 全てのイベントのまとめメールを止める
+#[tracing::instrument]
+pub async fn unsubscribe_digest(pool: &PgPool, user_id: i64) -> Result<()> {
+    let sql = r#"
+        INSERT INTO notification_settings
+            (user_id, event, in_app, email_frequency, created_at, updated_at)
+        SELECT $1, event, $2, 'off', $3, $3
+        FROM unnest(enum_range(NULL::notification_event)) as event
+        ON CONFLICT (user_id, event) DO UPDATE
+        SET email_frequency = 'off', updated_at = $3
+    "#;
+
+    let result = sqlx::query(sql)
+        .bind(user_id)
+        .bind(DEFAULT_IN_APP)
+        .bind(Local::now())
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("unsubscribe digest successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("unsubscribe digest failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_in_app_enabled(
+    pool: &PgPool,
+    user_id: i64,
+    event: NotificationEvent,
+) -> Result<bool> {
+    let sql = r#"
+        SELECT in_app
+        FROM notification_settings
+        WHERE user_id = $1
+        AND event = $2
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(user_id)
+        .bind(event)
+        .try_map(|row: PgRow| row.try_get::<bool, _>("in_app"))
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(in_app) => {
+            tracing::info!("is in app enabled successed!!");
+            Ok(in_app.unwrap_or(DEFAULT_IN_APP))
+        }
+        Err(e) => {
+            tracing::error!("is in app enabled failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use async_graphql::*;
-use base64::{encode_config, URL_SAFE};
 use sqlx::postgres::PgPool;
 
+use crate::graphql::{id_encode, NodeType};
+
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Prefecture {
     pub id: i64,
@@ -11,7 +12,7 @@ pub struct Prefecture {
 #[Object]
 impl Prefecture {
     pub async fn id(&self) -> ID {
-        encode_config(format!("Prefecture:{}", self.id), URL_SAFE).into()
+        id_encode(NodeType::Prefecture, self.id).into()
     }
     async fn name(&self) -> &str {
         &self.name
@@ -36,3 +37,23 @@ pub async fn get_prefectures(pool: &PgPool) -> anyhow::Result<Vec<Prefecture>> {
         }
     }
 }
+
+#[tracing::instrument]
+pub async fn get_prefecture(pool: &PgPool, id: i64) -> anyhow::Result<Option<Prefecture>> {
+    let prefecture = sqlx::query_as::<_, Prefecture>(
+        r#"
+        SELECT * FROM prefectures WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await;
+
+    match prefecture {
+        Ok(prefecture) => Ok(prefecture),
+        Err(e) => {
+            tracing::error!("prefecture fetch_optional error: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
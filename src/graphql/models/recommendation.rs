@@ -0,0 +1,230 @@
+use anyhow::Result;
+use sqlx::{postgres::PgRow, FromRow, PgPool, Row};
+
+use crate::graphql::utils::pagination::SortedSearchParams;
+
+use super::recruitment::Recruitment;
+
+// おすすめのスコアの重み
+const FOLLOWED_WEIGHT: i64 = 50;
+const TAG_WEIGHT: i64 = 30; // 一致したタグ1件ごと
+const SPORT_WEIGHT: i64 = 20;
+const PREFECTURE_WEIGHT: i64 = 20;
+const NEAR_WEIGHT: i64 = 20;
+// ログインしている場合の人気の加点の上限 他の理由より優先されないようにする
+const POPULARITY_LIMIT: i64 = 9;
+// ストックしたいずれかの募集の開催場所からこの距離以内を近いとみなす
+const NEAR_DISTANCE_M: f64 = 20_000.0;
+// スコアを付ける候補は公開中の新しい募集からこの件数までに絞る
+const CANDIDATE_LIMIT: i64 = 1000;
+
+// おすすめの募集とスコアの内訳
+#[derive(Clone, Debug)]
+pub struct Recommendation {
+    pub recruitment: Recruitment,
+    pub score: f64,
+    pub tag_match_count: i64,
+    pub sport_matched: bool,
+    pub followed: bool,
+    pub prefecture_matched: bool,
+    pub is_near: bool,
+    pub stock_count: i64,
+}
+
+impl Recommendation {
+    // 加点された理由を重みの大きい順に並べる
+    pub fn reason(&self) -> String {
+        let mut reasons = Vec::new();
+        if self.followed {
+            reasons.push(String::from("フォローしているユーザーの募集です"));
+        }
+        if self.tag_match_count > 0 {
+            reasons.push(format!(
+                "ストックした募集と同じタグが{}件あります",
+                self.tag_match_count
+            ));
+        }
+        if self.sport_matched {
+            reasons.push(String::from("ストックした募集と同じスポーツです"));
+        }
+        if self.prefecture_matched {
+            reasons.push(String::from("ストックや作成した募集に多い都道府県です"));
+        }
+        if self.is_near {
+            reasons.push(String::from("ストックした募集の開催場所に近いです"));
+        }
+        if reasons.is_empty() {
+            if self.stock_count > 0 {
+                reasons.push(format!("{}人がストックしています", self.stock_count));
+            } else {
+                reasons.push(String::from("新着の募集です"));
+            }
+        }
+        reasons.join("、")
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for Recommendation {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            recruitment: Recruitment::from_row(row)?,
+            score: row.try_get("score")?,
+            tag_match_count: row.try_get("tag_match_count")?,
+            sport_matched: row.try_get("sport_matched")?,
+            followed: row.try_get("followed")?,
+            prefecture_matched: row.try_get("prefecture_matched")?,
+            is_near: row.try_get("is_near")?,
+            stock_count: row.try_get("stock_count")?,
+        })
+    }
+}
+
+// スコアを付けた公開中の募集をsとして取得するサブクエリ
+// $1はログインユーザーのID(未ログインならNULL) $2は近いとみなす距離(m)
+// ログインユーザーがストックした募集のタグ、スポーツ、開催場所とストックや作成した募集に多い都道府県に一致するほど加点する
+// 開催場所はストックしたいずれかの募集の開催場所に近ければ加点する
+// 未ログインやストックがない場合はストックされた数だけで並ぶ
+// 全ての公開中の募集にスコアを付けないようにrecruitments_status_id_idxで新しい募集から候補を絞る
+fn recommendation_subquery() -> String {
+    format!(
+        r#"
+        (
+            WITH stocked AS (
+                SELECT r.*
+                FROM stocks as st
+                INNER JOIN recruitments as r
+                    ON r.id = st.recruitment_id
+                WHERE st.user_id = $1
+            ),
+            signals AS (
+                SELECT
+                    (
+                        SELECT p.prefecture_id
+                        FROM (
+                            SELECT prefecture_id FROM stocked
+                            UNION ALL
+                            SELECT prefecture_id FROM recruitments WHERE user_id = $1
+                        ) as p
+                        GROUP BY p.prefecture_id
+                        ORDER BY COUNT(*) DESC, p.prefecture_id
+                        LIMIT 1
+                    ) as prefecture_id
+            ),
+            candidates AS (
+                SELECT r.*,
+                    (
+                        SELECT COUNT(*)
+                        FROM recruitment_tags as rt
+                        WHERE rt.recruitment_id = r.id
+                        AND rt.tag_id IN (
+                            SELECT s_rt.tag_id
+                            FROM recruitment_tags as s_rt
+                            WHERE s_rt.recruitment_id IN (SELECT id FROM stocked)
+                        )
+                    ) as tag_match_count,
+                    EXISTS (
+                        SELECT 1 FROM stocked WHERE stocked.sport_id = r.sport_id
+                    ) as sport_matched,
+                    EXISTS (
+                        SELECT 1
+                        FROM relationships as f
+                        WHERE f.follower_id = $1
+                        AND f.followed_id = r.user_id
+                    ) as followed,
+                    COALESCE(r.prefecture_id = sig.prefecture_id, FALSE) as prefecture_matched,
+                    (
+                        r.venue_lat IS NOT NULL
+                        AND r.venue_lng IS NOT NULL
+                        AND EXISTS (
+                            SELECT 1
+                            FROM stocked
+                            WHERE stocked.venue_lat IS NOT NULL
+                            AND stocked.venue_lng IS NOT NULL
+                            AND earth_box(ll_to_earth(stocked.venue_lat, stocked.venue_lng), $2)
+                                @> ll_to_earth(r.venue_lat, r.venue_lng)
+                            AND earth_distance(
+                                ll_to_earth(stocked.venue_lat, stocked.venue_lng),
+                                ll_to_earth(r.venue_lat, r.venue_lng)
+                            ) <= $2
+                        )
+                    ) as is_near,
+                    (SELECT COUNT(*) FROM stocks WHERE recruitment_id = r.id) as stock_count
+                FROM (
+                    SELECT *
+                    FROM recruitments
+                    WHERE status = 'published'
+                    AND user_id IS DISTINCT FROM $1
+                    AND NOT EXISTS (SELECT 1 FROM stocked WHERE stocked.id = recruitments.id)
+                    ORDER BY id DESC
+                    LIMIT {candidate_limit}
+                ) as r
+                CROSS JOIN signals as sig
+            )
+            SELECT c.*,
+                (
+                    c.tag_match_count * {tag}
+                    + CASE WHEN c.followed THEN {followed} ELSE 0 END
+                    + CASE WHEN c.sport_matched THEN {sport} ELSE 0 END
+                    + CASE WHEN c.prefecture_matched THEN {prefecture} ELSE 0 END
+                    + CASE WHEN c.is_near THEN {near} ELSE 0 END
+                    + CASE
+                          WHEN $1 IS NULL THEN c.stock_count
+                          ELSE LEAST(c.stock_count, {popularity})
+                      END
+                )::FLOAT8 as score
+            FROM candidates as c
+        ) AS s
+        "#,
+        tag = TAG_WEIGHT,
+        followed = FOLLOWED_WEIGHT,
+        sport = SPORT_WEIGHT,
+        prefecture = PREFECTURE_WEIGHT,
+        near = NEAR_WEIGHT,
+        popularity = POPULARITY_LIMIT,
+        candidate_limit = CANDIDATE_LIMIT,
+    )
+}
+
+// おすすめの募集をスコアの高い順に取得する 同じスコアは新しい順
+// 1件多く取得して次のページがあるかを返す
+#[tracing::instrument]
+pub async fn get_recommendations(
+    pool: &PgPool,
+    viewer_id: Option<i64>,
+    params: &SortedSearchParams,
+) -> Result<(Vec<Recommendation>, bool)> {
+    let sql = format!(
+        r#"
+        SELECT *
+        FROM {}
+        WHERE ($3 OR (s.score, s.id) < ($4, $5))
+        ORDER BY s.score DESC, s.id DESC
+        LIMIT $6
+        "#,
+        recommendation_subquery()
+    );
+    let (score, id) = params.after.unwrap_or_default();
+
+    let rows = sqlx::query_as::<_, Recommendation>(&sql)
+        .bind(viewer_id)
+        .bind(NEAR_DISTANCE_M)
+        .bind(params.after.is_none())
+        .bind(score)
+        .bind(id)
+        .bind(params.num_rows as i64 + 1)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(mut recommendations) => {
+            tracing::info!("get recommendations successed!!");
+            let has_next_page = recommendations.len() > params.num_rows.max(0) as usize;
+            recommendations.truncate(params.num_rows.max(0) as usize);
+            Ok((recommendations, has_next_page))
+        }
+        Err(e) => {
+            tracing::error!("get recommendations failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
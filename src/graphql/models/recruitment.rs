@@ -1,23 +1,51 @@
 use anyhow::Result;
 use async_graphql::{Context, Enum, FieldResult, Object, ID};
-use base64::{encode_config, URL_SAFE};
-use chrono::{DateTime, Local};
-use sqlx::{postgres::PgRow, PgPool, Row};
-
-use crate::graphql::{
-    auth::get_viewer,
-    id_decode,
-    loader::get_loaders,
-    mutations::recruitment_mutation::RecruitmentInput,
-    utils::pagination::{RecruitmentSearchParams, SearchParams},
+use chrono::{DateTime, Duration, Local, TimeZone};
+use sqlx::{postgres::PgRow, FromRow, PgPool, Postgres, QueryBuilder, Row};
+
+use crate::{
+    database::get_db_pool,
+    graphql::{
+        auth::get_viewer,
+        id_encode,
+        loader::get_loaders,
+        mutations::recruitment_mutation::{
+            ChangeRecruitmentStatusInvalidInputError, RecruitmentInput, RecruitmentInputIds,
+            RecruitmentInvalidInputField,
+        },
+        resolvers::{
+            application_resolver::{ApplicationConnection, ApplicationEdge},
+            message_resolver::{MessageThreadConnection, MessageThreadEdge},
+            recruitment_resolver::{RecruitmentRevisionConnection, RecruitmentRevisionEdge},
+        },
+        utils::pagination::{
+            sort_cursor_encode, PageInfo, RecruitmentSearchParams, SearchParams, SortedSearchParams,
+        },
+        FieldGuard, NodeType,
+    },
 };
 
 use super::{
+    application::{
+        get_recruitment_applications, is_next_recruitment_application, sync_waitlist_tx,
+        Application,
+    },
+    message::{get_recruitment_message_threads, is_next_recruitment_message_thread},
     prefecture::Prefecture,
+    recruitment_revision::{
+        add_original_revisions_tx, add_recruitment_revisions_tx, get_recruitment_revisions,
+        is_next_recruitment_revision,
+    },
+    recruitment_series::{get_series, update_following_tx, RecruitmentSeries},
+    recruitment_status_history::{
+        add_recruitment_status_history_tx, get_recruitment_status_histories,
+        RecruitmentStatusHistory,
+    },
+    recruitment_view::{get_recruitment_daily_stats, get_recruitment_totals, RecruitmentAnalytics},
     sport::Sport,
     tag::{
         add_recruitment_tags, add_recruitment_tags_tx, get_recruitment_tags,
-        remove_recruitment_tags_tx, Tag,
+        remove_all_recruitment_tags_tx, remove_recruitment_tags_tx, Tag,
     },
     user::User,
 };
@@ -43,6 +71,45 @@ pub enum RecruitmentStatus {
     Closed,
 }
 
+// 募集のステータスの遷移 これ以外の遷移はできない
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum RecruitmentStatusTransition {
+    Publish,   // 下書きを公開する
+    Close,     // 公開中の募集を締め切る
+    Reopen,    // 締め切った募集を再び公開する
+    Unpublish, // 公開中の募集を下書きに戻す
+}
+
+impl RecruitmentStatusTransition {
+    pub fn from_status(&self) -> RecruitmentStatus {
+        match self {
+            Self::Publish => RecruitmentStatus::Draft,
+            Self::Close | Self::Unpublish => RecruitmentStatus::Published,
+            Self::Reopen => RecruitmentStatus::Closed,
+        }
+    }
+    pub fn to_status(&self) -> RecruitmentStatus {
+        match self {
+            Self::Publish | Self::Reopen => RecruitmentStatus::Published,
+            Self::Close => RecruitmentStatus::Closed,
+            Self::Unpublish => RecruitmentStatus::Draft,
+        }
+    }
+}
+
+/// 募集が締め切られた理由
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "recruitment_closed_reason")]
+#[sqlx(rename_all = "lowercase")]
+pub enum RecruitmentClosedReason {
+    /// 掲載期限を過ぎたため自動で締め切られた
+    Expired,
+    /// 作成したユーザーが締め切った
+    Manual,
+    /// 定員に達したため自動で締め切られた
+    Full,
+}
+
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Recruitment {
     pub id: i64,
@@ -59,14 +126,229 @@ pub struct Recruitment {
     pub status: RecruitmentStatus,
     pub user_id: i64,
     pub published_at: Option<DateTime<Local>>,
+    pub closed_at: Option<DateTime<Local>>,
+    pub closed_reason: Option<RecruitmentClosedReason>,
+    pub capacity: Option<i32>,
+    pub close_when_full: bool,
+    pub series_id: Option<i64>,
+    pub series_index: Option<i32>,
     pub created_at: DateTime<Local>,
 }
 
+/// 募集の並び順
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum RecruitmentOrder {
+    /// 新しい順
+    #[default]
+    Newest,
+    /// キーワードとの関連度と新しさの順 keywordの指定が必要
+    Relevance,
+    /// 開催場所が近い順 nearの指定が必要
+    Distance,
+}
+
+/// タグで絞り込む時の条件
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum TagMatch {
+    /// いずれかのタグが付与されている
+    #[default]
+    Any,
+    /// 全てのタグが付与されている
+    All,
+}
+
+// 指定した地点から半径radius_km以内
+#[derive(Debug, Clone, Copy)]
+pub struct NearFilter {
+    pub lat: f64,
+    pub lng: f64,
+    pub radius_km: f64,
+}
+
+impl NearFilter {
+    // ll_to_earth(r.venue_lat, r.venue_lng)との距離(m)
+    fn push_distance_m(&self, query_builder: &mut QueryBuilder<'_, Postgres>) {
+        query_builder
+            .push("earth_distance(ll_to_earth(")
+            .push_bind(self.lat)
+            .push(", ")
+            .push_bind(self.lng)
+            .push("), ll_to_earth(r.venue_lat, r.venue_lng))");
+    }
+}
+
+// 公開中の募集の検索条件 IDはデコード済みのものを保持する
+#[derive(Debug, Default)]
+pub struct RecruitmentFilter {
+    pub sport_id: Option<i64>,
+    pub prefecture_id: Option<i64>,
+    pub category: Option<RecruitmentCategory>,
+    pub tag_ids: Vec<i64>,
+    pub tag_match: TagMatch,
+    pub start_at_from: Option<DateTime<Local>>,
+    pub start_at_to: Option<DateTime<Local>>,
+    pub include_closed: bool,
+    pub keyword: Option<String>,
+    pub near: Option<NearFilter>,
+}
+
+impl RecruitmentFilter {
+    // recruitmentsをrとしたWHERE句に絞り込み条件を追加する
+    fn push_conditions(&self, query_builder: &mut QueryBuilder<'_, Postgres>) {
+        if self.include_closed {
+            query_builder.push(" AND r.status IN ('published', 'closed')");
+        } else {
+            // 自動で締め切られるまでの間も掲載期限を過ぎた募集は出さない
+            query_builder.push(
+                " AND r.status = 'published' AND (r.closing_at IS NULL OR r.closing_at > now())",
+            );
+        }
+        if let Some(sport_id) = self.sport_id {
+            query_builder.push(" AND r.sport_id = ").push_bind(sport_id);
+        }
+        if let Some(prefecture_id) = self.prefecture_id {
+            query_builder
+                .push(" AND r.prefecture_id = ")
+                .push_bind(prefecture_id);
+        }
+        if let Some(category) = self.category {
+            query_builder.push(" AND r.category = ").push_bind(category);
+        }
+        if !self.tag_ids.is_empty() {
+            match self.tag_match {
+                TagMatch::Any => {
+                    query_builder
+                        .push(
+                            " AND EXISTS (SELECT 1 FROM recruitment_tags as r_t \
+                             WHERE r_t.recruitment_id = r.id AND r_t.tag_id = ANY(",
+                        )
+                        .push_bind(self.tag_ids.clone())
+                        .push("))");
+                }
+                TagMatch::All => {
+                    // 指定したタグが全て付与されていれば件数が一致する
+                    query_builder
+                        .push(
+                            " AND (SELECT COUNT(DISTINCT r_t.tag_id) FROM recruitment_tags as r_t \
+                             WHERE r_t.recruitment_id = r.id AND r_t.tag_id = ANY(",
+                        )
+                        .push_bind(self.tag_ids.clone())
+                        .push(")) = ")
+                        .push_bind(self.tag_ids.len() as i64);
+                }
+            }
+        }
+        if let Some(start_at_from) = self.start_at_from {
+            query_builder
+                .push(" AND r.start_at >= ")
+                .push_bind(start_at_from);
+        }
+        if let Some(start_at_to) = self.start_at_to {
+            query_builder
+                .push(" AND r.start_at < ")
+                .push_bind(start_at_to);
+        }
+        if let Some(keyword) = self.keyword.as_deref() {
+            // 正規化とbigramへの分割はDB側のsearch_ngram_queryで行う
+            query_builder
+                .push(" AND r.search_vector @@ search_ngram_query(")
+                .push_bind(keyword.to_string())
+                .push(")");
+        }
+        if let Some(near) = self.near {
+            // earth_boxでGiSTインデックスを使って絞り込んでから正確な距離で判定する
+            // 部分インデックスの条件も付けないとインデックスが使われない
+            let radius_m = near.radius_km * 1000.0;
+            query_builder
+                .push(" AND r.venue_lat IS NOT NULL AND r.venue_lng IS NOT NULL")
+                .push(" AND earth_box(ll_to_earth(")
+                .push_bind(near.lat)
+                .push(", ")
+                .push_bind(near.lng)
+                .push("), ")
+                .push_bind(radius_m)
+                .push(") @> ll_to_earth(r.venue_lat, r.venue_lng) AND ");
+            near.push_distance_m(query_builder);
+            query_builder.push(" <= ").push_bind(radius_m);
+        }
+    }
+}
+
+impl Recruitment {
+    // タイムラインのカーソルに使う並び順の値 公開日時のマイクロ秒
+    pub fn feed_sort_key(&self) -> i64 {
+        match self.published_at {
+            Some(published_at) => published_at.timestamp_micros(),
+            None => 0,
+        }
+    }
+    // カーソルのマイクロ秒を公開日時に戻す 範囲外の値ならNone
+    pub fn feed_cursor_published_at(sort_key: i64) -> Option<DateTime<Local>> {
+        let secs = sort_key.div_euclid(1_000_000);
+        let nsecs = sort_key.rem_euclid(1_000_000) * 1000;
+        Local.timestamp_opt(secs, nsecs as u32).single()
+    }
+    // 下書きの募集は作成したユーザーにしか見せない
+    pub fn is_visible_to(&self, viewer: Option<&User>) -> bool {
+        match self.status {
+            RecruitmentStatus::Draft => match viewer {
+                Some(viewer) => viewer.id == self.user_id,
+                None => false,
+            },
+            _ => true,
+        }
+    }
+    // 公開する時に必要な項目が揃っているか 足りない項目のエラーを返す
+    pub fn publish_errors(&self) -> Vec<ChangeRecruitmentStatusInvalidInputError> {
+        let mut errors = Vec::new();
+        let now = Local::now();
+        if let RecruitmentCategory::Opponent | RecruitmentCategory::Personal = self.category {
+            if self
+                .venue
+                .as_deref()
+                .map(str::trim)
+                .unwrap_or_default()
+                .is_empty()
+            {
+                errors.push(ChangeRecruitmentStatusInvalidInputError {
+                    message: String::from("開催場所を入力してください"),
+                    field: RecruitmentInvalidInputField::Venue,
+                });
+            }
+            match self.start_at {
+                Some(start_at) if start_at <= now => {
+                    errors.push(ChangeRecruitmentStatusInvalidInputError {
+                        message: String::from("開催日時が過ぎています"),
+                        field: RecruitmentInvalidInputField::StartAt,
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    errors.push(ChangeRecruitmentStatusInvalidInputError {
+                        message: String::from("開催日時を入力してください"),
+                        field: RecruitmentInvalidInputField::StartAt,
+                    });
+                }
+            }
+        }
+        // 掲載期限を過ぎていると公開してすぐに自動で締め切られてしまう
+        if let Some(closing_at) = self.closing_at {
+            if closing_at <= now {
+                errors.push(ChangeRecruitmentStatusInvalidInputError {
+                    message: String::from("掲載期限が過ぎています"),
+                    field: RecruitmentInvalidInputField::ClosingAt,
+                });
+            }
+        }
+        errors
+    }
+}
+
 #[Object]
 /// 募集
 impl Recruitment {
     pub async fn id(&self) -> ID {
-        encode_config(format!("Recruitment:{}", self.id), URL_SAFE).into()
+        id_encode(NodeType::Recruitment, self.id).into()
     }
     /// 募集のタイトル
     pub async fn title(&self) -> &str {
@@ -100,6 +382,50 @@ impl Recruitment {
     pub async fn published_at(&self) -> Option<DateTime<Local>> {
         self.published_at
     }
+    /// 募集を締め切った日時
+    pub async fn closed_at(&self) -> Option<DateTime<Local>> {
+        self.closed_at
+    }
+    /// 募集が締め切られた理由
+    pub async fn closed_reason(&self) -> Option<RecruitmentClosedReason> {
+        self.closed_reason
+    }
+    /// 参加人数の定員 個人参加の募集のみ
+    pub async fn capacity(&self) -> Option<i32> {
+        self.capacity
+    }
+    /// 定員に達したら自動で締め切るか
+    pub async fn close_when_full(&self) -> bool {
+        self.close_when_full
+    }
+    /// 定員までの残り人数 定員がなければnull
+    pub async fn remaining_slots(&self, ctx: &Context<'_>) -> FieldResult<Option<i64>> {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity as i64,
+            None => return Ok(None),
+        };
+        let loaders = get_loaders(ctx).await;
+        let accepted_count = loaders
+            .accepted_count_loader
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default();
+        Ok(Some((capacity - accepted_count).max(0)))
+    }
+    /// この募集を作成した繰り返し
+    pub async fn series(&self, ctx: &Context<'_>) -> FieldResult<Option<RecruitmentSeries>> {
+        let series_id = match self.series_id {
+            Some(series_id) => series_id,
+            None => return Ok(None),
+        };
+        let pool = get_db_pool(ctx).await?;
+        let series = get_series(pool, series_id).await?;
+        Ok(series)
+    }
+    /// 繰り返しの何回目の開催か
+    pub async fn series_index(&self) -> Option<i32> {
+        self.series_index
+    }
     /// 募集の詳細
     pub async fn detail(&self) -> Option<&str> {
         self.detail.as_deref()
@@ -137,6 +463,13 @@ impl Recruitment {
             None => Err(async_graphql::Error::new(String::from("User are a must!"))),
         }
     }
+    /// ログインユーザー(Viewer)がこの募集を編集できるか
+    pub async fn viewer_can_edit(&self, ctx: &Context<'_>) -> bool {
+        match get_viewer(ctx).await {
+            Some(viewer) => viewer.id == self.user_id,
+            None => false,
+        }
+    }
     /// 募集の作成日時
     pub async fn created_at(&self) -> DateTime<Local> {
         self.created_at
@@ -145,6 +478,181 @@ impl Recruitment {
     pub async fn status(&self) -> RecruitmentStatus {
         self.status
     }
+    /// 閲覧数、ストック数、応募数と直近days日の推移 作成したユーザーのみ取得できる
+    #[graphql(guard = "FieldGuard::new(self.user_id)")]
+    pub async fn analytics(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 30)] days: i32,
+    ) -> async_graphql::Result<Option<RecruitmentAnalytics>> {
+        if !(1..=90).contains(&days) {
+            return Err(async_graphql::Error::new(
+                "日数は1から90の範囲で指定してください",
+            ));
+        }
+        let pool = get_db_pool(ctx).await?;
+        let loaders = get_loaders(ctx).await;
+        let stock_count = loaders
+            .stock_loader
+            .load_one(self.id)
+            .await?
+            .unwrap_or_default();
+        let (view_count, application_count) = get_recruitment_totals(pool, self.id).await?;
+        let daily = get_recruitment_daily_stats(pool, self.id, days.into()).await?;
+        Ok(Some(RecruitmentAnalytics {
+            view_count,
+            stock_count,
+            application_count,
+            daily,
+        }))
+    }
+    /// 募集のステータスの変更履歴 新しい順 作成したユーザーのみ取得できる
+    #[graphql(guard = "FieldGuard::new(self.user_id)")]
+    pub async fn status_histories(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<Vec<RecruitmentStatusHistory>>> {
+        let pool = get_db_pool(ctx).await?;
+        let histories = get_recruitment_status_histories(pool, self.id).await?;
+        Ok(Some(histories))
+    }
+    /// 募集の変更履歴のリスト 新しい順
+    pub async fn revisions(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<ID>,
+        first: Option<i32>,
+    ) -> async_graphql::Result<RecruitmentRevisionConnection> {
+        let pool = get_db_pool(ctx).await?;
+        let params = SearchParams::new(after, first, NodeType::RecruitmentRevision)?;
+        let revisions = get_recruitment_revisions(pool, self.id, params).await?;
+
+        let page_info = match revisions.last() {
+            Some(revision) => {
+                let has_next_page =
+                    is_next_recruitment_revision(pool, self.id, revision.id).await?;
+                let end_cursor = Some(id_encode(NodeType::RecruitmentRevision, revision.id));
+                PageInfo {
+                    has_next_page,
+                    end_cursor,
+                    ..Default::default()
+                }
+            }
+            None => Default::default(),
+        };
+
+        let edges: Vec<Option<RecruitmentRevisionEdge>> = revisions
+            .into_iter()
+            .map(|revision| RecruitmentRevisionEdge { node: revision }.into())
+            .collect();
+
+        Ok(RecruitmentRevisionConnection {
+            edges: edges.into(),
+            page_info,
+        })
+    }
+    /// この募集への応募のリスト 作成したユーザーのみ取得できる
+    #[graphql(guard = "FieldGuard::new(self.user_id)")]
+    pub async fn applications(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<ID>,
+        first: Option<i32>,
+    ) -> async_graphql::Result<Option<ApplicationConnection>> {
+        let pool = get_db_pool(ctx).await?;
+        let params = SearchParams::new(after, first, NodeType::Application)?;
+        let applications = get_recruitment_applications(pool, self.id, params).await?;
+
+        let edges: Vec<Option<ApplicationEdge>> = applications
+            .iter()
+            .map(|application| {
+                ApplicationEdge {
+                    node: application.to_owned(),
+                }
+                .into()
+            })
+            .collect();
+
+        let page_info = match applications.last() {
+            Some(application) => {
+                let has_next_page =
+                    is_next_recruitment_application(pool, self.id, application.id).await?;
+                let end_cursor = Some(id_encode(NodeType::Application, application.id));
+                PageInfo {
+                    has_next_page,
+                    end_cursor,
+                    ..Default::default()
+                }
+            }
+            None => Default::default(),
+        };
+
+        Ok(Some(ApplicationConnection {
+            edges: edges.into(),
+            page_info,
+        }))
+    }
+    /// この募集への応募者とのやり取りのリスト 作成したユーザーのみ取得できる
+    #[graphql(guard = "FieldGuard::new(self.user_id)")]
+    pub async fn message_threads(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<ID>,
+        first: Option<i32>,
+    ) -> async_graphql::Result<Option<MessageThreadConnection>> {
+        let pool = get_db_pool(ctx).await?;
+        let params = SortedSearchParams::<i64>::new(after, first, NodeType::MessageThread)?;
+        let threads = get_recruitment_message_threads(pool, self.id, &params).await?;
+
+        let edges: Vec<Option<MessageThreadEdge>> = threads
+            .iter()
+            .map(|thread| {
+                MessageThreadEdge {
+                    node: thread.to_owned(),
+                }
+                .into()
+            })
+            .collect();
+
+        let page_info = match threads.last() {
+            Some(thread) => {
+                let has_next_page =
+                    is_next_recruitment_message_thread(pool, self.id, thread).await?;
+                let end_cursor = Some(sort_cursor_encode(
+                    NodeType::MessageThread,
+                    thread.id,
+                    thread.last_message_id,
+                ));
+                PageInfo {
+                    has_next_page,
+                    end_cursor,
+                    ..Default::default()
+                }
+            }
+            None => Default::default(),
+        };
+
+        Ok(Some(MessageThreadConnection {
+            edges: edges.into(),
+            page_info,
+        }))
+    }
+    /// ログインユーザー(Viewer)のこの募集への応募
+    pub async fn viewer_application(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<Application>> {
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Ok(None),
+        };
+        let loaders = get_loaders(ctx).await;
+        let application = loaders
+            .application_loader
+            .load_one([viewer.id, self.id])
+            .await?;
+        Ok(application)
+    }
     /// この募集に付与されているタグのリスト
     pub async fn tags(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Tag>> {
         let loaders = get_loaders(ctx).await;
@@ -183,20 +691,22 @@ impl Recruitment {
 pub async fn get_recruitments(
     pool: &PgPool,
     search_params: SearchParams,
+    filter: &RecruitmentFilter,
 ) -> Result<Vec<Recruitment>> {
-    let sql = r#"
-        SELECT * 
-        FROM recruitments
-        WHERE ($1 OR id < $2)
-        AND status = 'published'
-        ORDER BY id DESC
-        LIMIT $3
-    "#;
+    let mut query_builder =
+        QueryBuilder::<Postgres>::new("SELECT r.* FROM recruitments as r WHERE TRUE");
+    if search_params.use_after {
+        query_builder
+            .push(" AND r.id < ")
+            .push_bind(search_params.after);
+    }
+    filter.push_conditions(&mut query_builder);
+    query_builder
+        .push(" ORDER BY r.id DESC LIMIT ")
+        .push_bind(search_params.num_rows);
 
-    let recruitments = sqlx::query_as::<_, Recruitment>(sql)
-        .bind(!search_params.use_after)
-        .bind(search_params.after)
-        .bind(search_params.num_rows)
+    let recruitments = query_builder
+        .build_query_as::<Recruitment>()
         .fetch_all(pool)
         .await;
 
@@ -213,6 +723,253 @@ pub async fn get_recruitments(
     }
 }
 
+// 新しく公開された募集をスポーツと都道府県の名前と一緒に公開日時の新しい順に取得する
+#[tracing::instrument]
+pub async fn get_newly_published_recruitments(
+    pool: &PgPool,
+    filter: &RecruitmentFilter,
+    limit: i64,
+) -> Result<Vec<(Recruitment, String, String)>> {
+    let mut query_builder = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT r.*, s.name as sport_name, p.name as prefecture_name
+        FROM recruitments as r
+        INNER JOIN sports as s
+            ON s.id = r.sport_id
+        INNER JOIN prefectures as p
+            ON p.id = r.prefecture_id
+        WHERE TRUE
+        "#,
+    );
+    filter.push_conditions(&mut query_builder);
+    query_builder
+        .push(" ORDER BY r.published_at DESC, r.id DESC LIMIT ")
+        .push_bind(limit);
+
+    let rows = query_builder
+        .build()
+        .try_map(|row: PgRow| {
+            let recruitment = Recruitment::from_row(&row)?;
+            let sport_name = row.try_get::<String, _>("sport_name")?;
+            let prefecture_name = row.try_get::<String, _>("prefecture_name")?;
+            Ok((recruitment, sport_name, prefecture_name))
+        })
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(rows) => {
+            tracing::info!("get newly published recruitments successed!!");
+            Ok(rows)
+        }
+        Err(e) => {
+            tracing::error!("get newly published recruitments failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 公開日時がこの秒数新しいと関連度のスコアが1加算される 30日で0.1
+const RECENCY_SECONDS_PER_SCORE: i64 = 30 * 24 * 60 * 60 * 10;
+
+// キーワードとの関連度に公開日時の新しさを加えたスコア
+// 現在時刻を使わないのでカーソルのスコアが変わらない
+fn push_relevance_score(query_builder: &mut QueryBuilder<'_, Postgres>, keyword: &str) {
+    query_builder
+        .push("ts_rank(r.search_vector, search_ngram_query(")
+        .push_bind(keyword.to_string())
+        .push("))::float8 + EXTRACT(EPOCH FROM COALESCE(r.published_at, r.created_at))::float8 / ")
+        .push(RECENCY_SECONDS_PER_SCORE);
+}
+
+// スコアを付けた募集をsとして取得するサブクエリ
+fn push_relevance_subquery(
+    query_builder: &mut QueryBuilder<'_, Postgres>,
+    filter: &RecruitmentFilter,
+    keyword: &str,
+) {
+    query_builder.push("(SELECT r.*, ");
+    push_relevance_score(query_builder, keyword);
+    query_builder.push(" AS score FROM recruitments as r WHERE TRUE");
+    filter.push_conditions(query_builder);
+    query_builder.push(") AS s");
+}
+
+// キーワードに関連する募集をスコアの高い順に取得する
+#[tracing::instrument]
+pub async fn search_recruitments(
+    pool: &PgPool,
+    params: &SortedSearchParams,
+    filter: &RecruitmentFilter,
+    keyword: &str,
+) -> Result<Vec<(Recruitment, f64)>> {
+    let mut query_builder = QueryBuilder::<Postgres>::new("SELECT * FROM ");
+    push_relevance_subquery(&mut query_builder, filter, keyword);
+    if let Some((score, id)) = params.after {
+        query_builder
+            .push(" WHERE (s.score, s.id) < (")
+            .push_bind(score)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    query_builder
+        .push(" ORDER BY s.score DESC, s.id DESC LIMIT ")
+        .push_bind(params.num_rows);
+
+    let rows = query_builder
+        .build()
+        .try_map(|row: PgRow| {
+            let recruitment = Recruitment::from_row(&row)?;
+            let score = row.try_get::<f64, _>("score")?;
+            Ok((recruitment, score))
+        })
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(rows) => {
+            tracing::info!("search recruitments successed!!");
+            Ok(rows)
+        }
+        Err(e) => {
+            tracing::error!("search recruitments failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_next_searched_recruitment(
+    pool: &PgPool,
+    score: f64,
+    id: i64,
+    filter: &RecruitmentFilter,
+    keyword: &str,
+) -> Result<bool> {
+    let mut query_builder = QueryBuilder::<Postgres>::new("SELECT EXISTS (SELECT 1 FROM ");
+    push_relevance_subquery(&mut query_builder, filter, keyword);
+    query_builder
+        .push(" WHERE (s.score, s.id) < (")
+        .push_bind(score)
+        .push(", ")
+        .push_bind(id)
+        .push("))");
+
+    let row = query_builder
+        .build()
+        .map(|row: PgRow| row.get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_next) => {
+            tracing::info!("is next searched recruitment successed!!");
+            Ok(is_next)
+        }
+        Err(e) => {
+            tracing::error!("is next searched recruitment failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 距離を付けた募集をsとして取得するサブクエリ
+fn push_distance_subquery(
+    query_builder: &mut QueryBuilder<'_, Postgres>,
+    filter: &RecruitmentFilter,
+    near: &NearFilter,
+) {
+    query_builder.push("(SELECT r.*, ");
+    near.push_distance_m(query_builder);
+    // 部分インデックスrecruitments_venue_earth_idxを使えるように条件を明示する
+    query_builder.push(
+        " / 1000 AS distance_km FROM recruitments as r \
+         WHERE r.venue_lat IS NOT NULL AND r.venue_lng IS NOT NULL",
+    );
+    filter.push_conditions(query_builder);
+    query_builder.push(") AS s");
+}
+
+// 開催場所が近い順に募集を取得する
+#[tracing::instrument]
+pub async fn get_recruitments_by_distance(
+    pool: &PgPool,
+    params: &SortedSearchParams,
+    filter: &RecruitmentFilter,
+    near: &NearFilter,
+) -> Result<Vec<(Recruitment, f64)>> {
+    let mut query_builder = QueryBuilder::<Postgres>::new("SELECT * FROM ");
+    push_distance_subquery(&mut query_builder, filter, near);
+    if let Some((distance_km, id)) = params.after {
+        query_builder
+            .push(" WHERE (s.distance_km, s.id) > (")
+            .push_bind(distance_km)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    query_builder
+        .push(" ORDER BY s.distance_km, s.id LIMIT ")
+        .push_bind(params.num_rows);
+
+    let rows = query_builder
+        .build()
+        .try_map(|row: PgRow| {
+            let recruitment = Recruitment::from_row(&row)?;
+            let distance_km = row.try_get::<f64, _>("distance_km")?;
+            Ok((recruitment, distance_km))
+        })
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(rows) => {
+            tracing::info!("get recruitments by distance successed!!");
+            Ok(rows)
+        }
+        Err(e) => {
+            tracing::error!("get recruitments by distance failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_next_recruitment_by_distance(
+    pool: &PgPool,
+    distance_km: f64,
+    id: i64,
+    filter: &RecruitmentFilter,
+    near: &NearFilter,
+) -> Result<bool> {
+    let mut query_builder = QueryBuilder::<Postgres>::new("SELECT EXISTS (SELECT 1 FROM ");
+    push_distance_subquery(&mut query_builder, filter, near);
+    query_builder
+        .push(" WHERE (s.distance_km, s.id) > (")
+        .push_bind(distance_km)
+        .push(", ")
+        .push_bind(id)
+        .push("))");
+
+    let row = query_builder
+        .build()
+        .map(|row: PgRow| row.get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_next) => {
+            tracing::info!("is next recruitment by distance successed!!");
+            Ok(is_next)
+        }
+        Err(e) => {
+            tracing::error!("is next recruitment by distance failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
 #[tracing::instrument]
 pub async fn get_recruitment(pool: &PgPool, id: i64) -> Result<Option<Recruitment>> {
     let sql = "SELECT * FROM recruitments WHERE id = $1";
@@ -233,6 +990,38 @@ pub async fn get_recruitment(pool: &PgPool, id: i64) -> Result<Option<Recruitmen
     }
 }
 
+// 募集が検索条件に一致するか 公開された募集をサブスクリプションで絞り込むのに使う
+#[tracing::instrument]
+pub async fn is_matched_recruitment(
+    pool: &PgPool,
+    id: i64,
+    filter: &RecruitmentFilter,
+) -> Result<bool> {
+    let mut query_builder = QueryBuilder::<Postgres>::new(
+        "SELECT EXISTS (SELECT 1 FROM recruitments as r WHERE r.id = ",
+    );
+    query_builder.push_bind(id);
+    filter.push_conditions(&mut query_builder);
+    query_builder.push(")");
+
+    let row = query_builder
+        .build()
+        .try_map(|row: PgRow| row.try_get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_matched) => {
+            tracing::info!("is matched recruitment successed!!");
+            Ok(is_matched)
+        }
+        Err(e) => {
+            tracing::error!("is matched recruitment failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
 #[tracing::instrument]
 pub async fn get_user_recruitments(
     pool: &PgPool,
@@ -312,20 +1101,20 @@ pub async fn is_next_user_recruitment(
 }
 
 #[tracing::instrument]
-pub async fn is_next_recruitment(pool: &PgPool, id: i64) -> Result<bool> {
-    let sql = r#"
-        SELECT EXISTS (
-            SELECT id
-            FROM recruitments
-            WHERE id < $1
-            AND status = 'published'
-            ORDER BY id DESC
-            LIMIT 1
-        )
-    "#;
+pub async fn is_next_recruitment(
+    pool: &PgPool,
+    id: i64,
+    filter: &RecruitmentFilter,
+) -> Result<bool> {
+    let mut query_builder = QueryBuilder::<Postgres>::new(
+        "SELECT EXISTS (SELECT 1 FROM recruitments as r WHERE r.id < ",
+    );
+    query_builder.push_bind(id);
+    filter.push_conditions(&mut query_builder);
+    query_builder.push(")");
 
-    let row = sqlx::query(sql)
-        .bind(id)
+    let row = query_builder
+        .build()
         .map(|row: PgRow| row.get::<bool, _>(0)) // SELECT EXISTSで true or falseのどちらかが返る
         .fetch_one(pool)
         .await;
@@ -385,6 +1174,96 @@ pub async fn get_stocked_recruitments(
     }
 }
 
+// フォローしているユーザーの公開中の募集を公開日時の新しい順に取得する
+// フォローしているユーザーごとにインデックスで必要な件数だけ取り出してから並べる
+#[tracing::instrument]
+pub async fn get_feed_recruitments(
+    pool: &PgPool,
+    user_id: i64,
+    params: &SortedSearchParams<DateTime<Local>>,
+) -> Result<Vec<Recruitment>> {
+    let mut query_builder = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT r.*
+        FROM relationships as f
+        CROSS JOIN LATERAL (
+            SELECT *
+            FROM recruitments
+            WHERE user_id = f.followed_id
+            AND status = 'published'
+        "#,
+    );
+    if let Some((published_at, id)) = params.after {
+        query_builder
+            .push(" AND (published_at, id) < (")
+            .push_bind(published_at)
+            .push(", ")
+            .push_bind(id)
+            .push(")");
+    }
+    query_builder
+        .push(" ORDER BY published_at DESC, id DESC LIMIT ")
+        .push_bind(params.num_rows)
+        .push(") as r WHERE f.follower_id = ")
+        .push_bind(user_id)
+        .push(" ORDER BY r.published_at DESC, r.id DESC LIMIT ")
+        .push_bind(params.num_rows);
+
+    let rows = query_builder
+        .build_query_as::<Recruitment>()
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(recruitments) => {
+            tracing::info!("get feed recruitments successed!!");
+            Ok(recruitments)
+        }
+        Err(e) => {
+            tracing::error!("get feed recruitments failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_next_feed_recruitment(
+    pool: &PgPool,
+    user_id: i64,
+    recruitment: &Recruitment,
+) -> Result<bool> {
+    let sql = r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM relationships as f
+            INNER JOIN recruitments as r
+                ON r.user_id = f.followed_id
+            WHERE f.follower_id = $1
+            AND r.status = 'published'
+            AND (r.published_at, r.id) < ($2, $3)
+        )
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(user_id)
+        .bind(recruitment.published_at)
+        .bind(recruitment.id)
+        .map(|row: PgRow| row.get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(has_next) => {
+            tracing::info!("is next feed recruitment successed!!");
+            Ok(has_next)
+        }
+        Err(e) => {
+            tracing::error!("is next feed recruitment failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
 #[tracing::instrument]
 pub async fn is_next_stocked_recruitment(pool: &PgPool, id: i64, user_id: i64) -> Result<bool> {
     let sql = r#"
@@ -424,22 +1303,24 @@ pub async fn is_next_stocked_recruitment(pool: &PgPool, id: i64, user_id: i64) -
     }
 }
 
+// 募集は下書きとして作成する 公開はchange_statusで行う
 #[tracing::instrument]
-pub async fn create(pool: &PgPool, input: RecruitmentInput, user_id: i64) -> Result<Recruitment> {
+pub async fn create(
+    pool: &PgPool,
+    input: RecruitmentInput,
+    ids: RecruitmentInputIds,
+    user_id: i64,
+) -> Result<Recruitment> {
     let sql = r#"
       INSERT INTO recruitments
         (title, category, venue, venue_lat, venue_lng, start_at, closing_at, 
-            detail, sport_id, prefecture_id, status, user_id, published_at, created_at, updated_at)
+            detail, sport_id, prefecture_id, capacity, close_when_full, user_id, created_at, updated_at)
       VALUES
         ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
       RETURNING *
     "#;
 
     let now = Local::now();
-    let published_at = match input.status {
-        RecruitmentStatus::Published => Some(now),
-        _ => None,
-    };
 
     let row = sqlx::query_as::<_, Recruitment>(sql)
         .bind(input.title)
@@ -450,11 +1331,11 @@ pub async fn create(pool: &PgPool, input: RecruitmentInput, user_id: i64) -> Res
         .bind(input.start_at)
         .bind(input.closing_at)
         .bind(input.detail)
-        .bind(id_decode(&input.sport_id)?)
-        .bind(id_decode(&input.prefecture_id)?)
-        .bind(input.status)
+        .bind(ids.sport_id)
+        .bind(ids.prefecture_id)
+        .bind(input.capacity)
+        .bind(input.close_when_full)
         .bind(user_id)
-        .bind(published_at)
         .bind(now)
         .bind(now)
         .fetch_one(pool)
@@ -469,41 +1350,121 @@ pub async fn create(pool: &PgPool, input: RecruitmentInput, user_id: i64) -> Res
         }
     };
 
-    let decoded_tag_ids = input
-        .tag_ids
-        .iter()
-        .filter_map(|sent_tag| id_decode(sent_tag).ok())
-        .collect::<Vec<i64>>();
-    add_recruitment_tags(pool, decoded_tag_ids, recruitment.id).await?;
+    add_recruitment_tags(pool, ids.tag_ids, recruitment.id).await?;
     Ok(recruitment)
 }
 
+// 募集を複製して下書きとして作成する 日時はdate_shiftだけずらし、指定しなければ空にする
+// 定員や応募などの募集の状態は複製しない
 #[tracing::instrument]
-pub async fn update(
+pub async fn duplicate(
     pool: &PgPool,
-    input: RecruitmentInput,
     id: i64,
     user_id: i64,
+    date_shift: Option<Duration>,
 ) -> Result<Recruitment> {
+    let recruitment_sql = r#"
+        INSERT INTO recruitments
+            (title, category, venue, venue_lat, venue_lng, start_at, closing_at,
+                detail, sport_id, prefecture_id, user_id, created_at, updated_at)
+        SELECT title, category, venue, venue_lat, venue_lng, start_at + $1, closing_at + $1,
+            detail, sport_id, prefecture_id, $2, $3, $3
+        FROM recruitments
+        WHERE id = $4
+        RETURNING *
+    "#;
+    let tags_sql = r#"
+        INSERT INTO recruitment_tags
+            (tag_id, recruitment_id, created_at, updated_at)
+        SELECT tag_id, $1, $2, $2
+        FROM recruitment_tags
+        WHERE recruitment_id = $3
+    "#;
+
+    let now = Local::now();
+    let mut tx = pool.begin().await?;
+
+    let recruitment = match sqlx::query_as::<_, Recruitment>(recruitment_sql)
+        .bind(date_shift)
+        .bind(user_id)
+        .bind(now)
+        .bind(id)
+        .fetch_one(&mut tx)
+        .await
+    {
+        Ok(recruitment) => recruitment,
+        Err(e) => {
+            tracing::error!("duplicate recruitment failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    if let Err(e) = sqlx::query(tags_sql)
+        .bind(recruitment.id)
+        .bind(now)
+        .bind(id)
+        .execute(&mut tx)
+        .await
+    {
+        tracing::error!("duplicate recruitment tags failed rollback: {:?}", e);
+        tx.rollback().await?;
+        return Err(e.into());
+    }
+
+    tx.commit().await?;
+    tracing::info!("duplicate recruitment successed!!");
+    Ok(recruitment)
+}
+
+// 更新した内容は変更履歴に保存する with_followingなら同じ繰り返しの後の募集にも反映する
+// 更新した募集、反映した後の募集、開催日時か開催場所が変わった募集のIDを返す
+#[tracing::instrument]
+pub async fn update(
+    pool: &PgPool,
+    input: RecruitmentInput,
+    ids: RecruitmentInputIds,
+    recruitment: &Recruitment,
+    user_id: i64,
+    with_following: bool,
+) -> Result<(Recruitment, Vec<Recruitment>, Vec<i64>)> {
     let sql = r#"
         UPDATE recruitments
         SET title = $1, category = $2, venue = $3, venue_lat = $4, venue_lng = $5, start_at = $6,
-            closing_at = $7, detail = $8, sport_id = $9, prefecture_id = $10, status = $11, updated_at = $12,
-            published_at = CASE
-                               WHEN published_at IS NULL THEN $13
-                               ELSE published_at
-                           END
+            closing_at = $7, detail = $8, sport_id = $9, prefecture_id = $10, capacity = $11,
+            close_when_full = $12, updated_at = $13
         WHERE id = $14
         AND user_id = $15
         RETURNING *
     "#;
 
     let now = Local::now();
-    let published_at = match input.status {
-        RecruitmentStatus::Published => Some(now),
-        _ => None,
+
+    // ? タグを探す処理これでいいか考え直す
+    let current_tags = get_recruitment_tags(pool, recruitment.id).await?; // 募集に不要されているタグを全て取得
+
+    // 後の募集も含めた募集とタグの更新と変更履歴の保存で整合性を保つためにトランザクション
+    let mut tx = pool.begin().await?;
+
+    let (following, mut schedule_changed_ids) = if with_following {
+        match update_following_tx(&mut tx, &input, &ids, recruitment, user_id, now).await {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::error!("update_following_tx failed rollback...");
+                tx.rollback().await?;
+                return Err(e);
+            }
+        }
+    } else {
+        (Vec::new(), Vec::new())
     };
 
+    if let Err(e) = add_original_revisions_tx(&mut tx, &[recruitment.id]).await {
+        tracing::error!("add_original_revisions_tx failed rollback...");
+        tx.rollback().await?;
+        return Err(e);
+    }
+
     let row = sqlx::query_as::<_, Recruitment>(sql)
         .bind(input.title)
         .bind(input.category)
@@ -513,14 +1474,14 @@ pub async fn update(
         .bind(input.start_at)
         .bind(input.closing_at)
         .bind(input.detail)
-        .bind(id_decode(&input.sport_id)?)
-        .bind(id_decode(&input.prefecture_id)?)
-        .bind(input.status)
+        .bind(ids.sport_id)
+        .bind(ids.prefecture_id)
+        .bind(input.capacity)
+        .bind(input.close_when_full)
         .bind(now)
-        .bind(published_at)
-        .bind(id)
+        .bind(recruitment.id)
         .bind(user_id)
-        .fetch_one(pool)
+        .fetch_one(&mut tx)
         .await;
 
     let recruitment = match row {
@@ -529,26 +1490,18 @@ pub async fn update(
             recruitment
         }
         Err(e) => {
-            tracing::error!("update recruitment failed...");
+            tracing::error!("update recruitment failed rollback...");
             tracing::error!("{:?}", e);
+            tx.rollback().await?;
             return Err(e.into());
         }
     };
 
-    // ? タグを探す処理これでいいか考え直す
-    let current_tags = get_recruitment_tags(pool, recruitment.id).await?; // 募集に不要されているタグを全て取得
-    let decoded_sent_tag = input
+    // 送られてきたタグを起点に現在付与されているタグと比較して付与するタグを取得
+    let add_tags = ids
         .tag_ids
         .iter()
-        .filter_map(|sent_tag| id_decode(sent_tag).ok());
-
-    // タグの付与と削除で整合性を保つためにトランザクション
-    let mut tx = pool.begin().await?;
-
-    // 送られてきたタグを起点に現在付与されているタグと比較して付与するタグを取得
-    let add_tags = decoded_sent_tag
-        .clone()
-        .into_iter()
+        .copied()
         .filter(|sent_tag| {
             !current_tags
                 .iter()
@@ -565,12 +1518,7 @@ pub async fn update(
     let remove_tags = current_tags
         .iter()
         .map(|current_tag| current_tag.id)
-        .filter(|&current_tag| {
-            !decoded_sent_tag
-                .clone()
-                .into_iter()
-                .any(|sent_tag| sent_tag == current_tag)
-        })
+        .filter(|&current_tag| !ids.tag_ids.contains(&current_tag))
         .collect::<Vec<i64>>();
     if let Err(e) = remove_recruitment_tags_tx(&mut tx, remove_tags, recruitment.id).await {
         tracing::error!("remove_recruitment_tags_tx failed rollback...");
@@ -578,8 +1526,182 @@ pub async fn update(
         return Err(e);
     }
 
-    // タグの付与、削除に成功したらコミットする
+    match add_recruitment_revisions_tx(&mut tx, &[recruitment.id], user_id).await {
+        Ok(changed_ids) => schedule_changed_ids.extend(changed_ids),
+        Err(e) => {
+            tracing::error!("add_recruitment_revisions_tx failed rollback...");
+            tx.rollback().await?;
+            return Err(e);
+        }
+    };
+
+    // 定員が変わったらキャンセル待ちを整える
+    if let Err(e) = sync_waitlist_tx(&mut tx, recruitment.id, now).await {
+        tracing::error!("sync_waitlist_tx failed rollback...");
+        tx.rollback().await?;
+        return Err(e);
+    }
+
+    // タグの付与、削除と変更履歴の保存に成功したらコミットする
     tx.commit().await?;
     tracing::info!("Transaction Commit!!");
-    Ok(recruitment)
+    Ok((recruitment, following, schedule_changed_ids))
+}
+
+// 削除した募集をストックしていたユーザーを返す
+#[tracing::instrument]
+pub async fn delete(pool: &PgPool, id: i64) -> Result<Vec<User>> {
+    let mut tx = pool.begin().await?;
+
+    // ストックはCASCADEで消えるため削除前に取得しておく
+    let sql = r#"
+        SELECT u.*
+        FROM users as u
+        INNER JOIN stocks as s
+            ON u.id = s.user_id
+        WHERE s.recruitment_id = $1
+    "#;
+    let stocked_users = match sqlx::query_as::<_, User>(sql)
+        .bind(id)
+        .fetch_all(&mut tx)
+        .await
+    {
+        Ok(users) => users,
+        Err(e) => {
+            tracing::error!("get stocked users failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    if let Err(e) = remove_all_recruitment_tags_tx(&mut tx, id).await {
+        tracing::error!("remove_all_recruitment_tags_tx failed rollback...");
+        tx.rollback().await?;
+        return Err(e);
+    }
+
+    let sql = "DELETE FROM recruitments WHERE id = $1";
+    if let Err(e) = sqlx::query(sql).bind(id).execute(&mut tx).await {
+        tracing::error!("delete recruitment failed rollback: {:?}", e);
+        tx.rollback().await?;
+        return Err(e.into());
+    }
+
+    tx.commit().await?;
+    tracing::info!("delete recruitment successed!!");
+    Ok(stocked_users)
+}
+
+// 掲載期限を過ぎた公開中の募集をlimit件まで締め切る
+// 複数のサーバーで同時に実行しても他がロックしている行は飛ばすので同じ募集を二重に処理しない
+#[tracing::instrument]
+pub async fn close_expired(pool: &PgPool, limit: i64) -> Result<Vec<Recruitment>> {
+    let sql = r#"
+        WITH expired AS (
+            SELECT id
+            FROM recruitments
+            WHERE status = 'published'
+            AND closing_at <= $1
+            ORDER BY closing_at
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        ), closed AS (
+            UPDATE recruitments as r
+            SET status = 'closed', closed_at = $1, closed_reason = 'expired', updated_at = $1
+            FROM expired
+            WHERE r.id = expired.id
+            RETURNING r.*
+        ), histories AS (
+            INSERT INTO recruitment_status_histories
+                (recruitment_id, from_status, to_status, user_id, created_at)
+            SELECT id, 'published', 'closed', NULL, $1
+            FROM closed
+        )
+        SELECT * FROM closed
+    "#;
+
+    let rows = sqlx::query_as::<_, Recruitment>(sql)
+        .bind(Local::now())
+        .bind(limit)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(recruitments) => {
+            tracing::info!("close expired recruitments successed!!");
+            Ok(recruitments)
+        }
+        Err(e) => {
+            tracing::error!("close expired recruitments failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 募集のステータスを遷移させて履歴に記録する
+// 遷移元のステータスでなくなっていた場合は何もせずにNoneを返す
+#[tracing::instrument]
+pub async fn change_status(
+    pool: &PgPool,
+    id: i64,
+    transition: RecruitmentStatusTransition,
+    user_id: i64,
+) -> Result<Option<Recruitment>> {
+    let sql = r#"
+        UPDATE recruitments
+        SET status = $1, updated_at = $2,
+            published_at = CASE
+                               WHEN $1 = 'published' THEN COALESCE(published_at, $2)
+                               ELSE published_at
+                           END,
+            closed_at = CASE WHEN $1 = 'closed' THEN $2 ELSE NULL END,
+            closed_reason = CASE WHEN $1 = 'closed' THEN $3 ELSE NULL END
+        WHERE id = $4
+        AND status = $5
+        RETURNING *
+    "#;
+
+    let now = Local::now();
+    let mut tx = pool.begin().await?;
+
+    let row = sqlx::query_as::<_, Recruitment>(sql)
+        .bind(transition.to_status())
+        .bind(now)
+        .bind(RecruitmentClosedReason::Manual)
+        .bind(id)
+        .bind(transition.from_status())
+        .fetch_optional(&mut tx)
+        .await;
+
+    let recruitment = match row {
+        Ok(Some(recruitment)) => recruitment,
+        Ok(None) => {
+            tracing::error!("recruitment status has already changed");
+            tx.rollback().await?;
+            return Ok(None);
+        }
+        Err(e) => {
+            tracing::error!("change recruitment status failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    if let Err(e) = add_recruitment_status_history_tx(
+        &mut tx,
+        id,
+        transition.from_status(),
+        transition.to_status(),
+        Some(user_id),
+    )
+    .await
+    {
+        tracing::error!("add_recruitment_status_history_tx failed rollback...");
+        tx.rollback().await?;
+        return Err(e);
+    }
+
+    tx.commit().await?;
+    tracing::info!("change recruitment status successed!!");
+    Ok(Some(recruitment))
 }
@@ -0,0 +1,527 @@
+use anyhow::Result;
+use async_graphql::{Context, Enum, InputType, Object, SimpleObject, ID};
+use chrono::{DateTime, Local};
+use sqlx::{postgres::PgRow, FromRow, PgPool, Postgres, Row, Transaction};
+
+use crate::graphql::{id_encode, loader::get_loaders, utils::pagination::SearchParams, NodeType};
+
+use super::{recruitment::RecruitmentCategory, user::User};
+
+// 変更履歴に保存する募集の項目 タグはtag_idsとして別に保存する
+const REVISION_COLUMNS: &str = r#"
+    title, category, venue, venue_lat, venue_lng, start_at, closing_at, detail,
+    sport_id, prefecture_id, capacity, close_when_full
+"#;
+
+// 募集に付いているタグのIDを並べた配列 変更の比較のためにID順にする
+const TAG_IDS_COLUMN: &str = r#"
+    ARRAY(
+        SELECT rt.tag_id
+        FROM recruitment_tags as rt
+        WHERE rt.recruitment_id = r.id
+        ORDER BY rt.tag_id
+    )
+"#;
+
+// 変更履歴と1つ前の変更履歴の内容を取得するSELECT句
+const REVISION_WITH_PREVIOUS_COLUMNS: &str = r#"
+    rv.*,
+    prev.id as prev_id, prev.title as prev_title, prev.category as prev_category,
+    prev.venue as prev_venue, prev.venue_lat as prev_venue_lat,
+    prev.venue_lng as prev_venue_lng, prev.start_at as prev_start_at,
+    prev.closing_at as prev_closing_at, prev.detail as prev_detail,
+    prev.sport_id as prev_sport_id, prev.prefecture_id as prev_prefecture_id,
+    prev.capacity as prev_capacity, prev.close_when_full as prev_close_when_full,
+    prev.tag_ids as prev_tag_ids
+"#;
+
+const PREVIOUS_REVISION_JOIN: &str = r#"
+    LEFT JOIN LATERAL (
+        SELECT *
+        FROM recruitment_revisions as p
+        WHERE p.recruitment_id = rv.recruitment_id
+        AND p.id < rv.id
+        ORDER BY p.id DESC
+        LIMIT 1
+    ) as prev ON TRUE
+"#;
+
+// ある時点の募集の内容
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecruitmentSnapshot {
+    pub title: String,
+    pub category: RecruitmentCategory,
+    pub venue: Option<String>,
+    pub venue_lat: Option<f64>,
+    pub venue_lng: Option<f64>,
+    pub start_at: Option<DateTime<Local>>,
+    pub closing_at: Option<DateTime<Local>>,
+    pub detail: Option<String>,
+    pub sport_id: i64,
+    pub prefecture_id: i64,
+    pub capacity: Option<i32>,
+    pub close_when_full: bool,
+    pub tag_ids: Vec<i64>,
+}
+
+impl RecruitmentSnapshot {
+    // prefixを付けたカラム名から取得する
+    fn from_row_with_prefix(row: &PgRow, prefix: &str) -> Result<Self, sqlx::Error> {
+        let column = |name: &str| format!("{}{}", prefix, name);
+        Ok(Self {
+            title: row.try_get(column("title").as_str())?,
+            category: row.try_get(column("category").as_str())?,
+            venue: row.try_get(column("venue").as_str())?,
+            venue_lat: row.try_get(column("venue_lat").as_str())?,
+            venue_lng: row.try_get(column("venue_lng").as_str())?,
+            start_at: row.try_get(column("start_at").as_str())?,
+            closing_at: row.try_get(column("closing_at").as_str())?,
+            detail: row.try_get(column("detail").as_str())?,
+            sport_id: row.try_get(column("sport_id").as_str())?,
+            prefecture_id: row.try_get(column("prefecture_id").as_str())?,
+            capacity: row.try_get(column("capacity").as_str())?,
+            close_when_full: row.try_get(column("close_when_full").as_str())?,
+            tag_ids: row.try_get(column("tag_ids").as_str())?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecruitmentRevision {
+    pub id: i64,
+    pub recruitment_id: i64,
+    pub user_id: Option<i64>,
+    pub snapshot: RecruitmentSnapshot,
+    pub previous: Option<RecruitmentSnapshot>, // 最初の変更履歴はNone
+    pub created_at: DateTime<Local>,
+}
+
+impl<'r> FromRow<'r, PgRow> for RecruitmentRevision {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        let previous = match row.try_get::<Option<i64>, _>("prev_id")? {
+            Some(_) => Some(RecruitmentSnapshot::from_row_with_prefix(row, "prev_")?),
+            None => None,
+        };
+        Ok(Self {
+            id: row.try_get("id")?,
+            recruitment_id: row.try_get("recruitment_id")?,
+            user_id: row.try_get("user_id")?,
+            snapshot: RecruitmentSnapshot::from_row_with_prefix(row, "")?,
+            previous,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+/// 募集の変更された項目
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum RecruitmentRevisionField {
+    /// タイトル
+    Title,
+    /// 募集のカテゴリ
+    Category,
+    /// 開催場所
+    Venue,
+    /// 開催場所の緯度と経度
+    VenueLocation,
+    /// 開催日時
+    StartAt,
+    /// 掲載期限
+    ClosingAt,
+    /// 詳細
+    Detail,
+    /// スポーツ
+    Sport,
+    /// 都道府県
+    Prefecture,
+    /// 定員
+    Capacity,
+    /// 定員に達したら自動で締め切るか
+    CloseWhenFull,
+    /// タグ
+    Tags,
+}
+
+/// 項目の変更前と変更後の値
+#[derive(SimpleObject, Debug)]
+pub struct RecruitmentFieldChange {
+    pub field: RecruitmentRevisionField,
+    /// 変更前の値 未設定ならnull
+    pub before: Option<String>,
+    /// 変更後の値 未設定ならnull
+    pub after: Option<String>,
+}
+
+#[Object]
+/// 募集の変更履歴 募集が更新されるたびに更新後の内容が保存される
+impl RecruitmentRevision {
+    pub async fn id(&self) -> ID {
+        id_encode(NodeType::RecruitmentRevision, self.id).into()
+    }
+    /// 募集を更新したユーザー 退会した場合はnull
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<User>> {
+        let user_id = match self.user_id {
+            Some(user_id) => user_id,
+            None => return Ok(None),
+        };
+        let loaders = get_loaders(ctx).await;
+        let user = loaders.user_loader.load_one(user_id).await?;
+        Ok(user)
+    }
+    /// 最初に更新される前の内容か
+    async fn is_original(&self) -> bool {
+        self.previous.is_none()
+    }
+    /// 1つ前の変更履歴から変わった項目 最初に更新される前の内容では空
+    async fn changes(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<RecruitmentFieldChange>> {
+        let previous = match self.previous {
+            Some(ref previous) => previous,
+            None => return Ok(Vec::new()),
+        };
+        let current = &self.snapshot;
+        let loaders = get_loaders(ctx).await;
+
+        let mut changes = Vec::new();
+        let mut push_change =
+            |field: RecruitmentRevisionField, before: Option<String>, after: Option<String>| {
+                if before != after {
+                    changes.push(RecruitmentFieldChange {
+                        field,
+                        before,
+                        after,
+                    });
+                }
+            };
+
+        push_change(
+            RecruitmentRevisionField::Title,
+            Some(previous.title.clone()),
+            Some(current.title.clone()),
+        );
+        push_change(
+            RecruitmentRevisionField::Category,
+            Some(previous.category.to_value().to_string()),
+            Some(current.category.to_value().to_string()),
+        );
+        push_change(
+            RecruitmentRevisionField::Venue,
+            previous.venue.clone(),
+            current.venue.clone(),
+        );
+        let location =
+            |snapshot: &RecruitmentSnapshot| match (snapshot.venue_lat, snapshot.venue_lng) {
+                (Some(lat), Some(lng)) => Some(format!("{},{}", lat, lng)),
+                _ => None,
+            };
+        push_change(
+            RecruitmentRevisionField::VenueLocation,
+            location(previous),
+            location(current),
+        );
+        push_change(
+            RecruitmentRevisionField::StartAt,
+            previous.start_at.map(|start_at| start_at.to_rfc3339()),
+            current.start_at.map(|start_at| start_at.to_rfc3339()),
+        );
+        push_change(
+            RecruitmentRevisionField::ClosingAt,
+            previous
+                .closing_at
+                .map(|closing_at| closing_at.to_rfc3339()),
+            current.closing_at.map(|closing_at| closing_at.to_rfc3339()),
+        );
+        push_change(
+            RecruitmentRevisionField::Detail,
+            previous.detail.clone(),
+            current.detail.clone(),
+        );
+        // スポーツ、都道府県、タグは名前で返す
+        if previous.sport_id != current.sport_id {
+            let sports = loaders
+                .sport_loader
+                .load_many([previous.sport_id, current.sport_id])
+                .await?;
+            push_change(
+                RecruitmentRevisionField::Sport,
+                sports
+                    .get(&previous.sport_id)
+                    .map(|sport| sport.name.clone()),
+                sports
+                    .get(&current.sport_id)
+                    .map(|sport| sport.name.clone()),
+            );
+        }
+        if previous.prefecture_id != current.prefecture_id {
+            let prefectures = loaders
+                .prefecture_loader
+                .load_many([previous.prefecture_id, current.prefecture_id])
+                .await?;
+            push_change(
+                RecruitmentRevisionField::Prefecture,
+                prefectures
+                    .get(&previous.prefecture_id)
+                    .map(|prefecture| prefecture.name.clone()),
+                prefectures
+                    .get(&current.prefecture_id)
+                    .map(|prefecture| prefecture.name.clone()),
+            );
+        }
+        push_change(
+            RecruitmentRevisionField::Capacity,
+            previous.capacity.map(|capacity| capacity.to_string()),
+            current.capacity.map(|capacity| capacity.to_string()),
+        );
+        push_change(
+            RecruitmentRevisionField::CloseWhenFull,
+            Some(previous.close_when_full.to_string()),
+            Some(current.close_when_full.to_string()),
+        );
+        if previous.tag_ids != current.tag_ids {
+            let tags = loaders
+                .tag_id_loader
+                .load_many(
+                    previous
+                        .tag_ids
+                        .iter()
+                        .chain(current.tag_ids.iter())
+                        .copied(),
+                )
+                .await?;
+            let tag_names = |tag_ids: &[i64]| {
+                tag_ids
+                    .iter()
+                    .filter_map(|tag_id| tags.get(tag_id).map(|tag| tag.name.clone()))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            };
+            push_change(
+                RecruitmentRevisionField::Tags,
+                Some(tag_names(&previous.tag_ids)),
+                Some(tag_names(&current.tag_ids)),
+            );
+        }
+
+        Ok(changes)
+    }
+    /// 募集が更新された日時
+    async fn created_at(&self) -> DateTime<Local> {
+        self.created_at
+    }
+}
+
+// 変更履歴がない募集は更新前の内容を最初の変更履歴として保存する
+// 募集を更新する前に呼ぶ
+#[tracing::instrument]
+pub async fn add_original_revisions_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_ids: &[i64],
+) -> Result<()> {
+    let sql = format!(
+        r#"
+        INSERT INTO recruitment_revisions
+            (recruitment_id, user_id, {columns}, tag_ids, created_at)
+        SELECT r.id, r.user_id, {columns}, {tag_ids}, r.updated_at
+        FROM recruitments as r
+        WHERE r.id = ANY($1)
+        AND NOT EXISTS (
+            SELECT 1
+            FROM recruitment_revisions as rv
+            WHERE rv.recruitment_id = r.id
+        )
+        "#,
+        columns = REVISION_COLUMNS,
+        tag_ids = TAG_IDS_COLUMN,
+    );
+
+    let result = sqlx::query(&sql).bind(recruitment_ids).execute(tx).await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("add original recruitment revisions successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("add original recruitment revisions failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 更新後の募集の内容を変更履歴に保存する 直前の変更履歴から変わっていない募集は保存しない
+// 開催日時か開催場所が変わった募集のIDを返す
+#[tracing::instrument]
+pub async fn add_recruitment_revisions_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_ids: &[i64],
+    user_id: i64,
+) -> Result<Vec<i64>> {
+    let sql = format!(
+        r#"
+        WITH latest AS (
+            SELECT DISTINCT ON (recruitment_id) *
+            FROM recruitment_revisions
+            WHERE recruitment_id = ANY($1)
+            ORDER BY recruitment_id, id DESC
+        ),
+        current AS (
+            SELECT r.id as recruitment_id, {columns}, {tag_ids} as tag_ids
+            FROM recruitments as r
+            WHERE r.id = ANY($1)
+        ),
+        inserted AS (
+            INSERT INTO recruitment_revisions
+                (recruitment_id, user_id, {columns}, tag_ids, created_at)
+            SELECT c.recruitment_id, $2, {current_columns}, c.tag_ids, $3
+            FROM current as c
+            INNER JOIN latest as l
+                ON l.recruitment_id = c.recruitment_id
+            WHERE ({current_columns}, c.tag_ids) IS DISTINCT FROM ({latest_columns}, l.tag_ids)
+            RETURNING recruitment_id, start_at, venue
+        )
+        SELECT i.recruitment_id
+        FROM inserted as i
+        INNER JOIN latest as l
+            ON l.recruitment_id = i.recruitment_id
+        WHERE i.start_at IS DISTINCT FROM l.start_at
+        OR i.venue IS DISTINCT FROM l.venue
+        "#,
+        columns = REVISION_COLUMNS,
+        tag_ids = TAG_IDS_COLUMN,
+        current_columns = prefixed_columns("c"),
+        latest_columns = prefixed_columns("l"),
+    );
+
+    let rows = sqlx::query(&sql)
+        .bind(recruitment_ids)
+        .bind(user_id)
+        .bind(Local::now())
+        .map(|row: PgRow| row.get::<i64, _>("recruitment_id"))
+        .fetch_all(tx)
+        .await;
+
+    match rows {
+        Ok(schedule_changed_ids) => {
+            tracing::info!("add recruitment revisions successed!!");
+            Ok(schedule_changed_ids)
+        }
+        Err(e) => {
+            tracing::error!("add recruitment revisions failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// REVISION_COLUMNSの各カラムにテーブルの別名を付ける
+fn prefixed_columns(alias: &str) -> String {
+    REVISION_COLUMNS
+        .split(',')
+        .map(|column| format!("{}.{}", alias, column.trim()))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[tracing::instrument]
+pub async fn get_revision(pool: &PgPool, id: i64) -> Result<Option<RecruitmentRevision>> {
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM recruitment_revisions as rv
+        {}
+        WHERE rv.id = $1
+        "#,
+        REVISION_WITH_PREVIOUS_COLUMNS, PREVIOUS_REVISION_JOIN
+    );
+
+    let row = sqlx::query_as::<_, RecruitmentRevision>(&sql)
+        .bind(id)
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(revision) => {
+            tracing::info!("get recruitment revision successed!!");
+            Ok(revision)
+        }
+        Err(e) => {
+            tracing::error!("get recruitment revision failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 募集の変更履歴を新しい順に取得する
+#[tracing::instrument]
+pub async fn get_recruitment_revisions(
+    pool: &PgPool,
+    recruitment_id: i64,
+    params: SearchParams,
+) -> Result<Vec<RecruitmentRevision>> {
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM recruitment_revisions as rv
+        {}
+        WHERE rv.recruitment_id = $1
+        AND ($2 OR rv.id < $3)
+        ORDER BY rv.id DESC
+        LIMIT $4
+        "#,
+        REVISION_WITH_PREVIOUS_COLUMNS, PREVIOUS_REVISION_JOIN
+    );
+
+    let rows = sqlx::query_as::<_, RecruitmentRevision>(&sql)
+        .bind(recruitment_id)
+        .bind(!params.use_after)
+        .bind(params.after)
+        .bind(params.num_rows)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(revisions) => {
+            tracing::info!("get recruitment revisions successed!!");
+            Ok(revisions)
+        }
+        Err(e) => {
+            tracing::error!("get recruitment revisions failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn is_next_recruitment_revision(
+    pool: &PgPool,
+    recruitment_id: i64,
+    id: i64,
+) -> Result<bool> {
+    let sql = r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM recruitment_revisions
+            WHERE recruitment_id = $1
+            AND id < $2
+        )
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(recruitment_id)
+        .bind(id)
+        .map(|row: PgRow| row.get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_next) => {
+            tracing::info!("is next recruitment revision successed!!");
+            Ok(is_next)
+        }
+        Err(e) => {
+            tracing::error!("is next recruitment revision failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -0,0 +1,457 @@
+use anyhow::{bail, Result};
+use async_graphql::{Context, Enum, Object, ID};
+use chrono::{DateTime, Duration, Local, Months};
+use sqlx::{postgres::PgRow, PgPool, Postgres, Row, Transaction};
+
+use crate::{
+    database::get_db_pool,
+    graphql::{
+        auth::get_viewer,
+        id_encode,
+        mutations::recruitment_mutation::{RecruitmentInput, RecruitmentInputIds},
+        NodeType,
+    },
+};
+
+use super::{
+    application::sync_waitlist_tx,
+    recruitment::Recruitment,
+    recruitment_revision::{add_original_revisions_tx, add_recruitment_revisions_tx},
+};
+
+// 1つの繰り返しで作成できる募集の最大数 毎週で約1年分
+pub const MAX_SERIES_OCCURRENCES: usize = 53;
+
+/// 募集を繰り返す頻度
+#[derive(Enum, Clone, Copy, Eq, PartialEq, Debug, sqlx::Type)]
+#[sqlx(type_name = "recurrence_frequency")]
+#[sqlx(rename_all = "lowercase")]
+pub enum RecurrenceFrequency {
+    /// 毎週
+    Weekly,
+    /// 隔週
+    Biweekly,
+    /// 毎月 同じ日がない月は月末
+    Monthly,
+}
+
+impl RecurrenceFrequency {
+    // 初回からn回後の開催日時
+    fn nth(&self, first: DateTime<Local>, n: u32) -> Option<DateTime<Local>> {
+        match self {
+            Self::Weekly => first.checked_add_signed(Duration::weeks(n.into())),
+            Self::Biweekly => first.checked_add_signed(Duration::weeks(2 * i64::from(n))),
+            Self::Monthly => first.checked_add_months(Months::new(n)),
+        }
+    }
+
+    // 初回からuntilまでの開催日時 最大数を超える場合はNone
+    pub fn occurrences(
+        &self,
+        first: DateTime<Local>,
+        until: DateTime<Local>,
+    ) -> Option<Vec<DateTime<Local>>> {
+        let mut occurrences = Vec::new();
+        for n in 0.. {
+            match self.nth(first, n) {
+                Some(start_at) if start_at <= until => {
+                    if occurrences.len() == MAX_SERIES_OCCURRENCES {
+                        return None;
+                    }
+                    occurrences.push(start_at);
+                }
+                _ => break,
+            }
+        }
+        Some(occurrences)
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct RecruitmentSeries {
+    pub id: i64,
+    pub user_id: i64,
+    pub frequency: RecurrenceFrequency,
+    pub until: DateTime<Local>,
+    pub created_at: DateTime<Local>,
+}
+
+#[Object]
+/// 繰り返しの募集
+impl RecruitmentSeries {
+    async fn id(&self) -> ID {
+        id_encode(NodeType::RecruitmentSeries, self.id).into()
+    }
+    /// 繰り返す頻度
+    async fn frequency(&self) -> RecurrenceFrequency {
+        self.frequency
+    }
+    /// 繰り返しの終了日時
+    async fn until(&self) -> DateTime<Local> {
+        self.until
+    }
+    /// 繰り返しで作成された募集 開催順 下書きは作成したユーザーのみ取得できる
+    async fn recruitments(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Recruitment>> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = get_viewer(ctx).await;
+        let recruitments = get_series_recruitments(pool, self.id)
+            .await?
+            .into_iter()
+            .filter(|recruitment| recruitment.is_visible_to(viewer.as_ref()))
+            .collect();
+        Ok(recruitments)
+    }
+    async fn created_at(&self) -> DateTime<Local> {
+        self.created_at
+    }
+}
+
+// 繰り返しを作成して開催日時ごとの募集を下書きとしてまとめて作成する
+// 掲載期限は初回の開催日時との差を保って各回に設定する
+#[tracing::instrument]
+pub async fn create_series(
+    pool: &PgPool,
+    input: &RecruitmentInput,
+    ids: &RecruitmentInputIds,
+    frequency: RecurrenceFrequency,
+    until: DateTime<Local>,
+    user_id: i64,
+) -> Result<(RecruitmentSeries, Vec<Recruitment>)> {
+    let first = match input.start_at {
+        Some(start_at) => start_at,
+        None => bail!("start_at is required for recruitment series"),
+    };
+    let occurrences = match frequency.occurrences(first, until) {
+        Some(occurrences) => occurrences,
+        None => bail!("too many occurrences"),
+    };
+    let lead = input.closing_at.map(|closing_at| first - closing_at);
+
+    let series_sql = r#"
+        INSERT INTO recruitment_series
+            (user_id, frequency, until, created_at, updated_at)
+        VALUES
+            ($1, $2, $3, $4, $4)
+        RETURNING *
+    "#;
+    let recruitment_sql = r#"
+        INSERT INTO recruitments
+            (title, category, venue, venue_lat, venue_lng, start_at, closing_at, detail,
+                sport_id, prefecture_id, capacity, close_when_full, series_id, series_index,
+                user_id, created_at, updated_at)
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $16)
+        RETURNING *
+    "#;
+
+    let now = Local::now();
+
+    let mut tx = pool.begin().await?;
+
+    let series = match sqlx::query_as::<_, RecruitmentSeries>(series_sql)
+        .bind(user_id)
+        .bind(frequency)
+        .bind(until)
+        .bind(now)
+        .fetch_one(&mut tx)
+        .await
+    {
+        Ok(series) => series,
+        Err(e) => {
+            tracing::error!("create recruitment series failed rollback: {:?}", e);
+            tx.rollback().await?;
+            return Err(e.into());
+        }
+    };
+
+    let mut recruitments = Vec::with_capacity(occurrences.len());
+    for (index, start_at) in occurrences.into_iter().enumerate() {
+        let recruitment = match sqlx::query_as::<_, Recruitment>(recruitment_sql)
+            .bind(&input.title)
+            .bind(input.category)
+            .bind(&input.venue)
+            .bind(input.venue_lat)
+            .bind(input.venue_lng)
+            .bind(start_at)
+            .bind(lead.map(|lead| start_at - lead))
+            .bind(&input.detail)
+            .bind(ids.sport_id)
+            .bind(ids.prefecture_id)
+            .bind(input.capacity)
+            .bind(input.close_when_full)
+            .bind(series.id)
+            .bind(index as i32 + 1)
+            .bind(user_id)
+            .bind(now)
+            .fetch_one(&mut tx)
+            .await
+        {
+            Ok(recruitment) => recruitment,
+            Err(e) => {
+                tracing::error!("create series recruitment failed rollback: {:?}", e);
+                tx.rollback().await?;
+                return Err(e.into());
+            }
+        };
+        recruitments.push(recruitment);
+    }
+
+    if let Err(e) = replace_series_recruitment_tags_tx(&mut tx, &recruitments, &ids.tag_ids).await {
+        tracing::error!("add series recruitment tags failed rollback...");
+        tx.rollback().await?;
+        return Err(e);
+    }
+
+    tx.commit().await?;
+    tracing::info!("create recruitment series successed!!");
+    Ok((series, recruitments))
+}
+
+// 同じ繰り返しで指定した募集より後の締め切っていない募集に変更を反映する
+// 開催日時は変更前との差だけずらし、掲載期限は開催日時との差を保つ
+// 更新した内容は変更履歴に保存し、開催日時か開催場所が変わった募集のIDも返す
+// 指定した募集の更新と同じトランザクションで実行する
+#[tracing::instrument]
+pub async fn update_following_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    input: &RecruitmentInput,
+    ids: &RecruitmentInputIds,
+    recruitment: &Recruitment,
+    user_id: i64,
+    now: DateTime<Local>,
+) -> Result<(Vec<Recruitment>, Vec<i64>)> {
+    let (series_id, series_index) = match (recruitment.series_id, recruitment.series_index) {
+        (Some(series_id), Some(series_index)) => (series_id, series_index),
+        _ => return Ok((Vec::new(), Vec::new())),
+    };
+
+    let following_sql = r#"
+        SELECT id
+        FROM recruitments
+        WHERE series_id = $1
+        AND series_index > $2
+        AND status <> 'closed'
+        FOR UPDATE
+    "#;
+
+    let sql = r#"
+        UPDATE recruitments
+        SET title = $1, category = $2, venue = $3, venue_lat = $4, venue_lng = $5,
+            start_at = start_at + COALESCE($6, INTERVAL '0'),
+            closing_at = CASE
+                             WHEN $7 THEN NULL
+                             WHEN $8::INTERVAL IS NULL THEN closing_at
+                             ELSE start_at + COALESCE($6, INTERVAL '0') - $8
+                         END,
+            detail = $9, sport_id = $10, prefecture_id = $11, capacity = $12,
+            close_when_full = $13, updated_at = $14
+        WHERE series_id = $15
+        AND series_index > $16
+        AND status <> 'closed'
+        RETURNING *
+    "#;
+
+    let shift = match (recruitment.start_at, input.start_at) {
+        (Some(before), Some(after)) => Some(after - before),
+        _ => None,
+    };
+    let lead = match (input.start_at, input.closing_at) {
+        (Some(start_at), Some(closing_at)) => Some(start_at - closing_at),
+        _ => None,
+    };
+    // 更新前の内容を変更履歴に残すために先に対象の募集を取得する
+    let following_ids = match sqlx::query(following_sql)
+        .bind(series_id)
+        .bind(series_index)
+        .map(|row: PgRow| row.get::<i64, _>("id"))
+        .fetch_all(&mut *tx)
+        .await
+    {
+        Ok(following_ids) => following_ids,
+        Err(e) => {
+            tracing::error!("get following recruitments failed: {:?}", e);
+            return Err(e.into());
+        }
+    };
+    if let Err(e) = add_original_revisions_tx(tx, &following_ids).await {
+        tracing::error!("add original revisions of following failed...");
+        return Err(e);
+    }
+
+    let recruitments = match sqlx::query_as::<_, Recruitment>(sql)
+        .bind(&input.title)
+        .bind(input.category)
+        .bind(&input.venue)
+        .bind(input.venue_lat)
+        .bind(input.venue_lng)
+        .bind(shift)
+        .bind(input.closing_at.is_none())
+        .bind(lead)
+        .bind(&input.detail)
+        .bind(ids.sport_id)
+        .bind(ids.prefecture_id)
+        .bind(input.capacity)
+        .bind(input.close_when_full)
+        .bind(now)
+        .bind(series_id)
+        .bind(series_index)
+        .fetch_all(&mut *tx)
+        .await
+    {
+        Ok(recruitments) => recruitments,
+        Err(e) => {
+            tracing::error!("update following recruitments failed: {:?}", e);
+            return Err(e.into());
+        }
+    };
+
+    if let Err(e) = replace_series_recruitment_tags_tx(tx, &recruitments, &ids.tag_ids).await {
+        tracing::error!("replace following recruitment tags failed...");
+        return Err(e);
+    }
+
+    let schedule_changed_ids = match add_recruitment_revisions_tx(tx, &following_ids, user_id).await
+    {
+        Ok(schedule_changed_ids) => schedule_changed_ids,
+        Err(e) => {
+            tracing::error!("add revisions of following failed...");
+            return Err(e);
+        }
+    };
+
+    // 定員が変わったらキャンセル待ちを整える
+    for recruitment in recruitments.iter() {
+        if let Err(e) = sync_waitlist_tx(tx, recruitment.id, now).await {
+            tracing::error!("sync_waitlist_tx of following failed...");
+            return Err(e);
+        }
+    }
+
+    tracing::info!("update following recruitments successed!!");
+    Ok((recruitments, schedule_changed_ids))
+}
+
+// 繰り返しの募集のタグをまとめて付け替える
+#[tracing::instrument]
+async fn replace_series_recruitment_tags_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitments: &[Recruitment],
+    tag_ids: &[i64],
+) -> Result<()> {
+    let delete_sql = "DELETE FROM recruitment_tags WHERE recruitment_id = ANY($1)";
+    let insert_sql = r#"
+        INSERT INTO recruitment_tags
+            (recruitment_id, tag_id, created_at, updated_at)
+        SELECT r.id, t.id, $3, $3
+        FROM unnest($1::BIGINT[]) as r(id)
+        CROSS JOIN unnest($2::BIGINT[]) as t(id)
+    "#;
+
+    let recruitment_ids: Vec<i64> = recruitments.iter().map(|r| r.id).collect();
+
+    if let Err(e) = sqlx::query(delete_sql)
+        .bind(&recruitment_ids)
+        .execute(&mut *tx)
+        .await
+    {
+        tracing::error!("remove series recruitment tags failed: {:?}", e);
+        return Err(e.into());
+    }
+
+    let result = sqlx::query(insert_sql)
+        .bind(&recruitment_ids)
+        .bind(tag_ids)
+        .bind(Local::now())
+        .execute(&mut *tx)
+        .await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("replace series recruitment tags successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("replace series recruitment tags failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_series(pool: &PgPool, id: i64) -> Result<Option<RecruitmentSeries>> {
+    let sql = "SELECT * FROM recruitment_series WHERE id = $1";
+    let row = sqlx::query_as::<_, RecruitmentSeries>(sql)
+        .bind(id)
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(series) => {
+            tracing::info!("get recruitment series successed!!");
+            Ok(series)
+        }
+        Err(e) => {
+            tracing::error!("get recruitment series failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_series_recruitments(pool: &PgPool, series_id: i64) -> Result<Vec<Recruitment>> {
+    let sql = r#"
+        SELECT *
+        FROM recruitments
+        WHERE series_id = $1
+        ORDER BY series_index
+    "#;
+    let rows = sqlx::query_as::<_, Recruitment>(sql)
+        .bind(series_id)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(recruitments) => {
+            tracing::info!("get series recruitments successed!!");
+            Ok(recruitments)
+        }
+        Err(e) => {
+            tracing::error!("get series recruitments failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 繰り返しで作成された募集が1つでも見えるユーザーには繰り返しも見せる
+#[tracing::instrument]
+pub async fn is_series_visible_to(
+    pool: &PgPool,
+    series_id: i64,
+    viewer_id: Option<i64>,
+) -> Result<bool> {
+    let sql = r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM recruitments
+            WHERE series_id = $1
+            AND (status <> 'draft' OR user_id = $2)
+        )
+    "#;
+    let row = sqlx::query(sql)
+        .bind(series_id)
+        .bind(viewer_id)
+        .map(|row: PgRow| row.get::<bool, _>(0))
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(is_visible) => {
+            tracing::info!("is series visible to successed!!");
+            Ok(is_visible)
+        }
+        Err(e) => {
+            tracing::error!("is series visible to failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
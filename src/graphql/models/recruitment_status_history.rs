@@ -0,0 +1,139 @@
+use anyhow::Result;
+use async_graphql::{Context, Object};
+use chrono::{DateTime, Local};
+use sqlx::{postgres::PgRow, PgPool, Postgres, Row, Transaction};
+
+use crate::graphql::loader::get_loaders;
+
+use super::{recruitment::RecruitmentStatus, user::User};
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct RecruitmentStatusHistory {
+    pub id: i64,
+    pub recruitment_id: i64,
+    pub from_status: RecruitmentStatus,
+    pub to_status: RecruitmentStatus,
+    pub user_id: Option<i64>,
+    pub created_at: DateTime<Local>,
+}
+
+#[Object]
+/// 募集のステータスの変更履歴
+impl RecruitmentStatusHistory {
+    /// 変更前のステータス
+    async fn from_status(&self) -> RecruitmentStatus {
+        self.from_status
+    }
+    /// 変更後のステータス
+    async fn to_status(&self) -> RecruitmentStatus {
+        self.to_status
+    }
+    /// ステータスを変更したユーザー 自動で締め切られた場合はnull
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<User>> {
+        let user_id = match self.user_id {
+            Some(user_id) => user_id,
+            None => return Ok(None),
+        };
+        let loaders = get_loaders(ctx).await;
+        let user = loaders.user_loader.load_one(user_id).await?;
+        Ok(user)
+    }
+    /// ステータスを変更した日時
+    async fn created_at(&self) -> DateTime<Local> {
+        self.created_at
+    }
+}
+
+#[tracing::instrument]
+pub async fn add_recruitment_status_history_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_id: i64,
+    from_status: RecruitmentStatus,
+    to_status: RecruitmentStatus,
+    user_id: Option<i64>,
+) -> Result<()> {
+    let sql = r#"
+        INSERT INTO recruitment_status_histories
+            (recruitment_id, from_status, to_status, user_id, created_at)
+        VALUES
+            ($1, $2, $3, $4, $5)
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(recruitment_id)
+        .bind(from_status)
+        .bind(to_status)
+        .bind(user_id)
+        .bind(Local::now())
+        .execute(tx)
+        .await;
+
+    match row {
+        Ok(_) => {
+            tracing::info!("add recruitment status history successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("add recruitment status history failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_recruitment_status_histories(
+    pool: &PgPool,
+    recruitment_id: i64,
+) -> Result<Vec<RecruitmentStatusHistory>> {
+    let sql = r#"
+        SELECT *
+        FROM recruitment_status_histories
+        WHERE recruitment_id = $1
+        ORDER BY id DESC
+    "#;
+
+    let rows = sqlx::query_as::<_, RecruitmentStatusHistory>(sql)
+        .bind(recruitment_id)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(histories) => {
+            tracing::info!("get recruitment status histories successed!!");
+            Ok(histories)
+        }
+        Err(e) => {
+            tracing::error!("get recruitment status histories failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 最後に募集のステータスが変わった日時 フィードのLast-Modifiedに使う
+// 締め切りや非公開でフィードから消えた募集は載っている募集の日時に現れないのでここから求める
+// idは作成順に増えるので主キーのインデックスで最新の1件を取り出す
+#[tracing::instrument]
+pub async fn get_last_status_changed_at(pool: &PgPool) -> Result<Option<DateTime<Local>>> {
+    let sql = r#"
+        SELECT created_at
+        FROM recruitment_status_histories
+        ORDER BY id DESC
+        LIMIT 1
+    "#;
+
+    let row = sqlx::query(sql)
+        .map(|row: PgRow| row.get::<DateTime<Local>, _>(0))
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(changed_at) => {
+            tracing::info!("get last status changed at successed!!");
+            Ok(changed_at)
+        }
+        Err(e) => {
+            tracing::error!("get last status changed at failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::Result;
+use async_graphql::{Context, SimpleObject};
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::{Duration, Local, NaiveDate, Offset};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::{postgres::PgRow, PgPool, Row};
+
+use crate::config::get_config;
+
+// 未ログインの閲覧者を区別するための接続元の情報
+// そのままは保存せず日ごとに変わるハッシュにして保存する
+#[derive(Debug, Clone)]
+pub struct ViewFingerprint(String);
+
+impl ViewFingerprint {
+    pub fn new(ip: &str, user_agent: &str) -> Self {
+        Self(format!("{}\n{}", ip, user_agent))
+    }
+    // 日付と接続元のHMAC-SHA256 鍵がなければハッシュから接続元を総当たりで求められない
+    fn anonymous_key(&self, date: NaiveDate) -> String {
+        let secret = get_config().view.fingerprint_secret.as_bytes();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+        mac.update(format!("{}\n{}", date, self.0).as_bytes());
+        format!(
+            "anon:{}",
+            encode_config(mac.finalize().into_bytes(), URL_SAFE_NO_PAD)
+        )
+    }
+}
+
+pub async fn get_view_fingerprint<'ctx>(ctx: &Context<'ctx>) -> Option<&'ctx ViewFingerprint> {
+    ctx.data_opt::<ViewFingerprint>()
+}
+
+// 閲覧数を集計し直す日数 日付が変わる前後の閲覧を取りこぼさないように前日から集計する
+const ROLLUP_DAYS: i64 = 1;
+// 集計済みの閲覧の記録を残す日数
+const VIEW_RETENTION_DAYS: i64 = 30;
+
+/// 募集の日ごとの数値
+#[derive(SimpleObject, Debug, sqlx::FromRow)]
+pub struct RecruitmentDailyStat {
+    pub date: NaiveDate,
+    /// 閲覧数
+    pub view_count: i64,
+    /// ストックされた数
+    pub stock_count: i64,
+    /// 応募された数
+    pub application_count: i64,
+}
+
+/// 募集の閲覧数、ストック数、応募数
+#[derive(SimpleObject, Debug)]
+pub struct RecruitmentAnalytics {
+    /// 閲覧数の合計 集計のため数分遅れる
+    pub view_count: i64,
+    /// 現在ストックされている数
+    pub stock_count: i64,
+    /// 応募された数の合計
+    pub application_count: i64,
+    /// 日ごとの推移 古い順
+    pub daily: Vec<RecruitmentDailyStat>,
+}
+
+// 募集の詳細の閲覧を記録する 同じ閲覧者の同じ日の閲覧は数えない
+// 数えた場合はtrueを返す
+#[tracing::instrument]
+pub async fn record_view(
+    pool: &PgPool,
+    recruitment_id: i64,
+    viewer_id: Option<i64>,
+    fingerprint: Option<&ViewFingerprint>,
+) -> Result<bool> {
+    if viewer_id.is_none() && fingerprint.is_none() {
+        return Ok(false);
+    }
+
+    let sql = r#"
+        INSERT INTO recruitment_views
+            (recruitment_id, viewer_key, viewed_on, created_at)
+        VALUES
+            ($1, COALESCE('user:' || $2::BIGINT, $4), $3, $5)
+        ON CONFLICT DO NOTHING
+    "#;
+
+    let now = Local::now();
+    let today = now.date_naive();
+    let result = sqlx::query(sql)
+        .bind(recruitment_id)
+        .bind(viewer_id)
+        .bind(today)
+        .bind(fingerprint.map(|fingerprint| fingerprint.anonymous_key(today)))
+        .bind(now)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(result) => {
+            tracing::info!("record recruitment view successed!!");
+            Ok(result.rows_affected() > 0)
+        }
+        Err(e) => {
+            tracing::error!("record recruitment view failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 直近の閲覧を日ごとの閲覧数に集計し、集計済みの古い閲覧の記録を削除する
+#[tracing::instrument]
+pub async fn rollup_views(pool: &PgPool) -> Result<()> {
+    let rollup_sql = r#"
+        INSERT INTO recruitment_daily_views
+            (recruitment_id, viewed_on, view_count, updated_at)
+        SELECT recruitment_id, viewed_on, COUNT(*), $2
+        FROM recruitment_views
+        WHERE viewed_on >= $1
+        GROUP BY recruitment_id, viewed_on
+        ON CONFLICT (recruitment_id, viewed_on) DO UPDATE
+        SET view_count = EXCLUDED.view_count, updated_at = EXCLUDED.updated_at
+    "#;
+    let prune_sql = "DELETE FROM recruitment_views WHERE viewed_on < $1";
+
+    let now = Local::now();
+    let today = now.date_naive();
+    let result = sqlx::query(rollup_sql)
+        .bind(today - Duration::days(ROLLUP_DAYS))
+        .bind(now)
+        .execute(pool)
+        .await;
+    if let Err(e) = result {
+        tracing::error!("rollup recruitment views failed: {:?}", e);
+        return Err(e.into());
+    }
+
+    let result = sqlx::query(prune_sql)
+        .bind(today - Duration::days(VIEW_RETENTION_DAYS))
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("rollup recruitment views successed!!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("prune recruitment views failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 閲覧数と応募数の合計を取得する
+#[tracing::instrument]
+pub async fn get_recruitment_totals(pool: &PgPool, recruitment_id: i64) -> Result<(i64, i64)> {
+    let sql = r#"
+        SELECT
+            (
+                SELECT COALESCE(SUM(view_count), 0)::BIGINT
+                FROM recruitment_daily_views
+                WHERE recruitment_id = $1
+            ) as view_count,
+            (
+                SELECT COUNT(*)
+                FROM applications
+                WHERE recruitment_id = $1
+            ) as application_count
+    "#;
+
+    let row = sqlx::query(sql)
+        .bind(recruitment_id)
+        .try_map(|row: PgRow| {
+            let view_count = row.try_get::<i64, _>("view_count")?;
+            let application_count = row.try_get::<i64, _>("application_count")?;
+            Ok((view_count, application_count))
+        })
+        .fetch_one(pool)
+        .await;
+
+    match row {
+        Ok(totals) => {
+            tracing::info!("get recruitment totals successed!!");
+            Ok(totals)
+        }
+        Err(e) => {
+            tracing::error!("get recruitment totals failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+// 今日までのdays日分の日ごとの数値を取得する 数値がない日は0にする
+#[tracing::instrument]
+pub async fn get_recruitment_daily_stats(
+    pool: &PgPool,
+    recruitment_id: i64,
+    days: i64,
+) -> Result<Vec<RecruitmentDailyStat>> {
+    // ストックと応募の日時をサーバーのタイムゾーンの日付にする
+    let sql = r#"
+        SELECT
+            d.date::DATE as date,
+            COALESCE(v.view_count, 0) as view_count,
+            (
+                SELECT COUNT(*)
+                FROM stocks as s
+                WHERE s.recruitment_id = $1
+                AND (s.created_at AT TIME ZONE 'UTC' + $4)::DATE = d.date::DATE
+            ) as stock_count,
+            (
+                SELECT COUNT(*)
+                FROM applications as a
+                WHERE a.recruitment_id = $1
+                AND (a.created_at AT TIME ZONE 'UTC' + $4)::DATE = d.date::DATE
+            ) as application_count
+        FROM generate_series($2::DATE, $3::DATE, INTERVAL '1 day') as d(date)
+        LEFT JOIN recruitment_daily_views as v
+            ON v.recruitment_id = $1 AND v.viewed_on = d.date::DATE
+        ORDER BY d.date
+    "#;
+
+    let now = Local::now();
+    let today = now.date_naive();
+    let utc_offset = Duration::seconds(now.offset().fix().local_minus_utc().into());
+    let rows = sqlx::query_as::<_, RecruitmentDailyStat>(sql)
+        .bind(recruitment_id)
+        .bind(today - Duration::days(days - 1))
+        .bind(today)
+        .bind(utc_offset)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(stats) => {
+            tracing::info!("get recruitment daily stats successed!!");
+            Ok(stats)
+        }
+        Err(e) => {
+            tracing::error!("get recruitment daily stats failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use async_graphql::*;
-use base64::{encode_config, URL_SAFE};
 use sqlx::PgPool;
 
+use crate::graphql::{id_encode, NodeType};
+
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Sport {
     pub id: i64,
@@ -11,7 +12,7 @@ pub struct Sport {
 #[Object]
 impl Sport {
     pub async fn id(&self) -> ID {
-        encode_config(format!("Sport:{}", self.id), URL_SAFE).into()
+        id_encode(NodeType::Sport, self.id).into()
     }
     async fn name(&self) -> &str {
         &self.name
@@ -36,3 +37,23 @@ pub async fn get_sports(pool: &PgPool) -> anyhow::Result<Vec<Sport>> {
         }
     }
 }
+
+#[tracing::instrument]
+pub async fn get_sport(pool: &PgPool, id: i64) -> anyhow::Result<Option<Sport>> {
+    let sport = sqlx::query_as::<_, Sport>(
+        r#"
+        SELECT * FROM sports WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await;
+
+    match sport {
+        Ok(sport) => Ok(sport),
+        Err(e) => {
+            tracing::error!("sport fetch_optional error: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use anyhow::Result;
 use async_graphql::{Object, ID};
-use base64::{encode_config, URL_SAFE};
 use chrono::Local;
 use sqlx::{postgres::PgRow, PgPool, Postgres, QueryBuilder, Row, Transaction};
 
+use crate::graphql::{id_encode, NodeType};
+
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Tag {
     pub id: i64,
@@ -13,7 +14,7 @@ pub struct Tag {
 #[Object]
 impl Tag {
     pub async fn id(&self) -> ID {
-        encode_config(format!("Tag:{}", self.id), URL_SAFE).into()
+        id_encode(NodeType::Tag, self.id).into()
     }
     async fn name(&self) -> &str {
         &self.name
@@ -127,6 +128,27 @@ pub async fn remove_recruitment_tags_tx(
     }
 }
 
+#[tracing::instrument]
+pub async fn remove_all_recruitment_tags_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    recruitment_id: i64,
+) -> Result<()> {
+    let sql = "DELETE FROM recruitment_tags WHERE recruitment_id = $1";
+
+    let result = sqlx::query(sql).bind(recruitment_id).execute(tx).await;
+
+    match result {
+        Ok(_) => {
+            tracing::info!("remove all recruitment tags successed!");
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("remove all recruitment tags failed: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
 #[tracing::instrument]
 pub async fn create(pool: &PgPool, name: &str) -> Result<Tag> {
     let sql = r#"
@@ -19,17 +19,27 @@ use crate::{
         loader::get_loaders,
         mutations::user_mutation::RegisterUserInput,
         resolvers::{
+            notification_resolver::{NotificationConnection, NotificationEdge},
             recruitment_resolver::{RecruitmentConnection, RecruitmentEdge},
             user_resolver::{FollowingConnection, UserEdge},
         },
-        utils::pagination::{PageInfo, RecruitmentSearchParams, SearchParams},
-        FieldGuard,
+        utils::pagination::{
+            sort_cursor_encode, PageInfo, RecruitmentSearchParams, SearchParams, SortedSearchParams,
+        },
+        FieldGuard, IdDecodeError, NodeType,
     },
 };
 
-use super::recruitment::{
-    get_stocked_recruitments, get_user_recruitments, is_next_stocked_recruitment,
-    is_next_user_recruitment, RecruitmentStatus,
+use super::{
+    calendar::{calendar_feed_url, get_calendar_token},
+    message::count_user_unread_messages,
+    notification::{count_unread_notifications, get_user_notifications, is_next_user_notification},
+    notification_setting::{get_notification_settings, NotificationSettings},
+    recruitment::{
+        get_feed_recruitments, get_stocked_recruitments, get_user_recruitments,
+        is_next_feed_recruitment, is_next_stocked_recruitment, is_next_user_recruitment,
+        Recruitment, RecruitmentStatus,
+    },
 };
 
 /// 権限
@@ -70,7 +80,7 @@ pub struct User {
 /// ユーザー
 impl User {
     pub async fn id(&self) -> ID {
-        id_encode("User", self.id).into()
+        id_encode(NodeType::User, self.id).into()
     }
     /// ユーザーの表示名
     async fn name(&self) -> &str {
@@ -104,6 +114,84 @@ impl User {
     async fn email_verification_status(&self) -> EmailVerificationStatus {
         self.email_verification_status
     }
+    /// 読んでいないメッセージの数
+    #[graphql(guard = "FieldGuard::new(self.id)")]
+    async fn unread_message_count(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<i64>> {
+        let pool = get_db_pool(ctx).await?;
+        let count = count_user_unread_messages(pool, self.id).await?;
+        Ok(Some(count))
+    }
+    /// 通知のリスト 最後にまとめられた順
+    #[graphql(guard = "FieldGuard::new(self.id)")]
+    async fn notifications(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<ID>,
+        first: Option<i32>,
+    ) -> async_graphql::Result<Option<NotificationConnection>> {
+        let pool = get_db_pool(ctx).await?;
+        let params = SortedSearchParams::<i64>::new(after, first, NodeType::Notification)?;
+        let notifications = get_user_notifications(pool, self.id, &params).await?;
+
+        let edges: Vec<Option<NotificationEdge>> = notifications
+            .iter()
+            .map(|notification| {
+                NotificationEdge {
+                    node: notification.to_owned(),
+                }
+                .into()
+            })
+            .collect();
+
+        let page_info = match notifications.last() {
+            Some(notification) => {
+                let has_next_page = is_next_user_notification(pool, self.id, notification).await?;
+                let end_cursor = Some(sort_cursor_encode(
+                    NodeType::Notification,
+                    notification.id,
+                    notification.sort_key,
+                ));
+                PageInfo {
+                    has_next_page,
+                    end_cursor,
+                    ..Default::default()
+                }
+            }
+            None => Default::default(),
+        };
+
+        Ok(Some(NotificationConnection {
+            edges: edges.into(),
+            page_info,
+        }))
+    }
+    /// 読んでいない通知の数
+    #[graphql(guard = "FieldGuard::new(self.id)")]
+    async fn unread_notification_count(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<i64>> {
+        let pool = get_db_pool(ctx).await?;
+        let count = count_unread_notifications(pool, self.id).await?;
+        Ok(Some(count))
+    }
+    /// 通知の設定
+    #[graphql(guard = "FieldGuard::new(self.id)")]
+    async fn notification_settings(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<NotificationSettings>> {
+        let pool = get_db_pool(ctx).await?;
+        let settings = get_notification_settings(pool, self.id).await?;
+        Ok(Some(settings))
+    }
+    /// カレンダーアプリから購読するURL 発行していなければnull
+    #[graphql(guard = "FieldGuard::new(self.id)")]
+    async fn calendar_feed_url(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<String>> {
+        let pool = get_db_pool(ctx).await?;
+        let token = get_calendar_token(pool, self.id).await?;
+        Ok(token.map(|token| calendar_feed_url(&token)))
+    }
     /// このユーザーがログインユーザー(Viewer)をフォローしているか
     async fn is_following_viewer(&self, ctx: &Context<'_>) -> FieldResult<bool> {
         let loaders = get_loaders(ctx).await;
@@ -152,19 +240,14 @@ impl User {
         let recruitments = get_user_recruitments(pool, &params, self.id).await?;
         let edges: Vec<Option<RecruitmentEdge>> = recruitments
             .iter()
-            .map(|recruitment| {
-                RecruitmentEdge {
-                    node: recruitment.to_owned(),
-                }
-                .into()
-            })
+            .map(|recruitment| RecruitmentEdge::from(recruitment.to_owned()).into())
             .collect();
 
         let page_info = match recruitments.last() {
             Some(recruitment) => {
                 let has_next_page =
                     is_next_user_recruitment(pool, recruitment.id, self.id, &params).await?;
-                let end_cursor = Some(id_encode("Recruitment", recruitment.id));
+                let end_cursor = Some(id_encode(NodeType::Recruitment, recruitment.id));
                 PageInfo {
                     has_next_page,
                     end_cursor,
@@ -186,24 +269,19 @@ impl User {
         first: Option<i32>,
     ) -> Result<RecruitmentConnection> {
         let pool = get_db_pool(ctx).await?;
-        let params = SearchParams::new(after, first)?;
+        let params = SearchParams::new(after, first, NodeType::Recruitment)?;
         let recruitments = get_stocked_recruitments(pool, self.id, params).await?;
 
         let edges: Vec<Option<RecruitmentEdge>> = recruitments
             .iter()
-            .map(|recruitment| {
-                RecruitmentEdge {
-                    node: recruitment.to_owned(),
-                }
-                .into()
-            })
+            .map(|recruitment| RecruitmentEdge::from(recruitment.to_owned()).into())
             .collect();
 
         let page_info = match recruitments.last() {
             Some(recruitment) => {
                 let has_next_page =
                     is_next_stocked_recruitment(pool, recruitment.id, self.id).await?;
-                let end_cursor = Some(id_encode("Recruitment", recruitment.id));
+                let end_cursor = Some(id_encode(NodeType::Recruitment, recruitment.id));
                 PageInfo {
                     has_next_page,
                     end_cursor,
@@ -218,6 +296,67 @@ impl User {
             page_info,
         })
     }
+    /// フォローしているユーザーの公開中の募集のリスト 公開日時の新しい順
+    #[graphql(guard = "FieldGuard::new(self.id)")]
+    async fn feed(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<ID>,
+        first: Option<i32>,
+    ) -> async_graphql::Result<Option<RecruitmentConnection>> {
+        let pool = get_db_pool(ctx).await?;
+        let params = SortedSearchParams::<i64>::new(after, first, NodeType::Recruitment)?;
+        // カーソルのマイクロ秒を公開日時に戻す 細工されたカーソルで範囲外ならエラーにする
+        let after = params
+            .after
+            .map(|(sort_key, id)| {
+                Recruitment::feed_cursor_published_at(sort_key)
+                    .map(|published_at| (published_at, id))
+                    .ok_or(IdDecodeError::Malformed)
+            })
+            .transpose()
+            .map_err(|e| e.extend())?;
+        let params = SortedSearchParams {
+            after,
+            num_rows: params.num_rows,
+        };
+        let recruitments = get_feed_recruitments(pool, self.id, &params).await?;
+
+        let edges: Vec<Option<RecruitmentEdge>> = recruitments
+            .iter()
+            .map(|recruitment| {
+                let mut edge = RecruitmentEdge::from(recruitment.to_owned());
+                edge.cursor = Some(sort_cursor_encode(
+                    NodeType::Recruitment,
+                    recruitment.id,
+                    recruitment.feed_sort_key(),
+                ));
+                edge.into()
+            })
+            .collect();
+
+        let page_info = match recruitments.last() {
+            Some(recruitment) => {
+                let has_next_page = is_next_feed_recruitment(pool, self.id, recruitment).await?;
+                let end_cursor = Some(sort_cursor_encode(
+                    NodeType::Recruitment,
+                    recruitment.id,
+                    recruitment.feed_sort_key(),
+                ));
+                PageInfo {
+                    has_next_page,
+                    end_cursor,
+                    ..Default::default()
+                }
+            }
+            None => Default::default(),
+        };
+
+        Ok(Some(RecruitmentConnection {
+            edges: edges.into(),
+            page_info,
+        }))
+    }
     /// ユーザーがフォローしているユーザーのリスト
     async fn following(
         &self,
@@ -226,7 +365,7 @@ impl User {
         first: Option<i32>,
     ) -> async_graphql::Result<FollowingConnection> {
         let pool = get_db_pool(ctx).await?;
-        let params = SearchParams::new(after, first)?;
+        let params = SearchParams::new(after, first, NodeType::User)?;
 
         let following = get_following(pool, self.id, params).await?;
 
@@ -243,7 +382,7 @@ impl User {
         let page_info = match following.last() {
             Some(user) => {
                 let has_next_page = is_next_following_edge(pool, self.id, user.id).await?;
-                let end_cursor = Some(id_encode("User", user.id));
+                let end_cursor = Some(id_encode(NodeType::User, user.id));
                 PageInfo {
                     has_next_page,
                     end_cursor,
@@ -1,8 +1,18 @@
 use async_graphql::Interface;
 
+use crate::graphql::resolvers::recruitment_resolver::RecruitmentNotFoundError;
+
 use self::{
+    application_mutation::{
+        ApplicationNotFoundError, ApplyToRecruitmentInvalidInputError,
+        ApplyToRecruitmentNotAcceptingError, ChangeApplicationStatusCapacityFullError,
+        ChangeApplicationStatusInvalidTransitionError,
+    },
+    message_mutation::{MessageThreadNotFoundError, SendMessageInvalidInputError},
     recruitment_mutation::{
-        CreateRecruitmentInvalidInputError, UpdateRecruitmentInvalidInputError,
+        ChangeRecruitmentStatusInvalidInputError, ChangeRecruitmentStatusInvalidTransitionError,
+        CreateRecruitmentInvalidInputError, CreateRecruitmentSeriesInvalidInputError,
+        UpdateRecruitmentInvalidInputError,
     },
     stock_mutation::AddStockAlreadyStockedError,
     tag_mutation::CreateTagAlreadyExistsNameError,
@@ -12,6 +22,9 @@ use self::{
     },
 };
 
+pub mod application_mutation;
+pub mod message_mutation;
+pub mod notification_mutation;
 pub mod recruitment_mutation;
 pub mod stock_mutation;
 pub mod tag_mutation;
@@ -31,4 +44,15 @@ pub enum Error {
     CreateTagAlreadyExistsNameError(CreateTagAlreadyExistsNameError),
     AddStockAlreadyStockedError(AddStockAlreadyStockedError),
     FollowUserAlreadyFollowingError(FollowUserAlreadyFollowingError),
+    RecruitmentNotFoundError(RecruitmentNotFoundError),
+    ChangeRecruitmentStatusInvalidInputError(ChangeRecruitmentStatusInvalidInputError),
+    ChangeRecruitmentStatusInvalidTransitionError(ChangeRecruitmentStatusInvalidTransitionError),
+    ApplyToRecruitmentInvalidInputError(ApplyToRecruitmentInvalidInputError),
+    ApplyToRecruitmentNotAcceptingError(ApplyToRecruitmentNotAcceptingError),
+    ChangeApplicationStatusInvalidTransitionError(ChangeApplicationStatusInvalidTransitionError),
+    ChangeApplicationStatusCapacityFullError(ChangeApplicationStatusCapacityFullError),
+    ApplicationNotFoundError(ApplicationNotFoundError),
+    SendMessageInvalidInputError(SendMessageInvalidInputError),
+    MessageThreadNotFoundError(MessageThreadNotFoundError),
+    CreateRecruitmentSeriesInvalidInputError(CreateRecruitmentSeriesInvalidInputError),
 }
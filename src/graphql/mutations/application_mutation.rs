@@ -0,0 +1,119 @@
+use async_graphql::{Enum, InputObject, SimpleObject, Union, ID};
+use validator::Validate;
+
+use crate::graphql::{
+    models::application::{Application, ApplicationStatus},
+    resolvers::recruitment_resolver::RecruitmentNotFoundError,
+};
+
+//* ApplyToRecruitment */
+#[derive(InputObject, Debug, Validate)]
+pub struct ApplyToRecruitmentInput {
+    pub recruitment_id: ID,
+    #[validate(length(
+        min = 1,
+        max = 1000,
+        message = "メッセージは1文字以上1000文字以内で入力してください"
+    ))]
+    pub message: String,
+}
+
+impl ApplyToRecruitmentInput {
+    pub fn apply_to_recruitment_validate(&self) -> Option<ApplyToRecruitmentInvalidInputErrors> {
+        match self.validate() {
+            Ok(_) => None,
+            Err(e) => {
+                let errors: Vec<ApplyToRecruitmentInvalidInputError> = e
+                    .field_errors()
+                    .iter()
+                    .filter_map(|(key, val)| {
+                        // 入力にないフィールドのエラーは返さない
+                        let field = match *key {
+                            "message" => ApplyToRecruitmentInvalidInputField::Message,
+                            &_ => return None,
+                        };
+                        let error = &val[0]; // fieldに対して複数エラーがあっても最初の一つだけ
+                        Some(ApplyToRecruitmentInvalidInputError {
+                            message: match error.message {
+                                Some(ref message) => message.to_string(),
+                                None => String::from(""),
+                            },
+                            field,
+                        })
+                    })
+                    .collect();
+                Some(ApplyToRecruitmentInvalidInputErrors { errors })
+            }
+        }
+    }
+}
+
+#[derive(Union)]
+#[allow(clippy::enum_variant_names)]
+pub enum ApplyToRecruitmentResult {
+    ApplyToRecruitmentSuccess(ApplyToRecruitmentSuccess),
+    ApplyToRecruitmentInvalidInputErrors(ApplyToRecruitmentInvalidInputErrors),
+    ApplyToRecruitmentNotAcceptingError(ApplyToRecruitmentNotAcceptingError),
+    RecruitmentNotFoundError(RecruitmentNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ApplyToRecruitmentSuccess {
+    pub application: Application,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ApplyToRecruitmentInvalidInputErrors {
+    pub errors: Vec<ApplyToRecruitmentInvalidInputError>,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ApplyToRecruitmentInvalidInputError {
+    pub message: String,
+    pub field: ApplyToRecruitmentInvalidInputField,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ApplyToRecruitmentInvalidInputField {
+    Message,
+}
+
+/// 締め切られた募集や自分の募集、既に応募している募集には応募できない
+#[derive(SimpleObject, Debug)]
+pub struct ApplyToRecruitmentNotAcceptingError {
+    pub message: String,
+}
+
+//* Accept, Reject, Withdraw Application */
+#[derive(Union)]
+#[allow(clippy::enum_variant_names)]
+pub enum ChangeApplicationStatusResult {
+    ChangeApplicationStatusSuccess(ChangeApplicationStatusSuccess),
+    ChangeApplicationStatusInvalidTransitionError(ChangeApplicationStatusInvalidTransitionError),
+    ChangeApplicationStatusCapacityFullError(ChangeApplicationStatusCapacityFullError),
+    ApplicationNotFoundError(ApplicationNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ChangeApplicationStatusSuccess {
+    pub application: Application,
+}
+
+/// 現在のステータスからは遷移できない
+#[derive(SimpleObject, Debug)]
+pub struct ChangeApplicationStatusInvalidTransitionError {
+    pub message: String,
+    /// 応募の現在のステータス
+    pub status: ApplicationStatus,
+}
+
+/// 募集の定員に達しているため承諾できない
+#[derive(SimpleObject, Debug)]
+pub struct ChangeApplicationStatusCapacityFullError {
+    pub message: String,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ApplicationNotFoundError {
+    pub message: String,
+}
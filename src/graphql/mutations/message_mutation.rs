@@ -0,0 +1,97 @@
+use async_graphql::{Enum, InputObject, SimpleObject, Union, ID};
+use validator::Validate;
+
+use crate::graphql::{
+    models::message::MessageThread, mutations::application_mutation::ApplicationNotFoundError,
+    resolvers::message_resolver::MessageEdge,
+};
+
+//* SendMessage */
+#[derive(InputObject, Debug, Validate)]
+pub struct SendMessageInput {
+    /// メッセージをやり取りする応募のID
+    pub application_id: ID,
+    #[validate(length(
+        min = 1,
+        max = 2000,
+        message = "メッセージは1文字以上2000文字以内で入力してください"
+    ))]
+    pub body: String,
+}
+
+impl SendMessageInput {
+    pub fn send_message_validate(&self) -> Option<SendMessageInvalidInputErrors> {
+        match self.validate() {
+            Ok(_) => None,
+            Err(e) => {
+                let errors: Vec<SendMessageInvalidInputError> = e
+                    .field_errors()
+                    .iter()
+                    .filter_map(|(key, val)| {
+                        // 入力にないフィールドのエラーは返さない
+                        let field = match *key {
+                            "body" => SendMessageInvalidInputField::Body,
+                            &_ => return None,
+                        };
+                        let error = &val[0]; // fieldに対して複数エラーがあっても最初の一つだけ
+                        Some(SendMessageInvalidInputError {
+                            message: match error.message {
+                                Some(ref message) => message.to_string(),
+                                None => String::from(""),
+                            },
+                            field,
+                        })
+                    })
+                    .collect();
+                Some(SendMessageInvalidInputErrors { errors })
+            }
+        }
+    }
+}
+
+#[derive(Union)]
+#[allow(clippy::enum_variant_names)]
+pub enum SendMessageResult {
+    SendMessageSuccess(SendMessageSuccess),
+    SendMessageInvalidInputErrors(SendMessageInvalidInputErrors),
+    ApplicationNotFoundError(ApplicationNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct SendMessageSuccess {
+    pub message_thread: MessageThread,
+    pub message_edge: MessageEdge,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct SendMessageInvalidInputErrors {
+    pub errors: Vec<SendMessageInvalidInputError>,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct SendMessageInvalidInputError {
+    pub message: String,
+    pub field: SendMessageInvalidInputField,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SendMessageInvalidInputField {
+    Body,
+}
+
+//* MarkMessageThreadRead */
+#[derive(Union)]
+pub enum MarkMessageThreadReadResult {
+    MarkMessageThreadReadSuccess(MarkMessageThreadReadSuccess),
+    MessageThreadNotFoundError(MessageThreadNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct MarkMessageThreadReadSuccess {
+    pub message_thread: MessageThread,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct MessageThreadNotFoundError {
+    pub message: String,
+}
@@ -0,0 +1,40 @@
+use async_graphql::{InputObject, SimpleObject, ID};
+
+use crate::graphql::models::{
+    notification::Notification,
+    notification_setting::{DigestFrequency, NotificationEvent, NotificationSettings},
+};
+
+//* MarkNotificationsRead */
+#[derive(InputObject)]
+pub struct MarkNotificationsReadInput {
+    /// 既読にする通知のID 指定しなければ全ての通知を既読にする
+    pub ids: Option<Vec<ID>>,
+}
+
+#[derive(SimpleObject)]
+pub struct MarkNotificationsReadResult {
+    /// 既読にした通知
+    pub notifications: Vec<Notification>,
+    pub unread_notification_count: i64,
+}
+
+//* UpdateNotificationSettings */
+#[derive(InputObject)]
+pub struct NotificationSettingInput {
+    pub event: NotificationEvent,
+    /// 指定しなければ変更しない
+    pub in_app: Option<bool>,
+    /// 指定しなければ変更しない
+    pub email_frequency: Option<DigestFrequency>,
+}
+
+#[derive(InputObject)]
+pub struct UpdateNotificationSettingsInput {
+    pub settings: Vec<NotificationSettingInput>,
+}
+
+#[derive(SimpleObject)]
+pub struct UpdateNotificationSettingsResult {
+    pub notification_settings: NotificationSettings,
+}
@@ -1,10 +1,19 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
 use async_graphql::{Enum, InputObject, SimpleObject, Union, ID};
 use chrono::{DateTime, Local};
 use validator::Validate;
 
 use crate::graphql::{
-    models::recruitment::{RecruitmentCategory, RecruitmentStatus},
-    resolvers::recruitment_resolver::RecruitmentEdge,
+    id_decode,
+    loader::Loaders,
+    models::{
+        recruitment::{Recruitment, RecruitmentCategory, RecruitmentStatus},
+        recruitment_series::{RecruitmentSeries, RecurrenceFrequency, MAX_SERIES_OCCURRENCES},
+    },
+    resolvers::recruitment_resolver::{RecruitmentEdge, RecruitmentNotFoundError},
+    IdDecodeError, NodeType,
 };
 
 #[derive(InputObject, Debug, Validate)]
@@ -21,10 +30,320 @@ pub struct RecruitmentInput {
     pub venue_lng: Option<f64>,
     pub start_at: Option<DateTime<Local>>,
     pub closing_at: Option<DateTime<Local>>,
-    pub status: RecruitmentStatus,
+    /// 参加人数の定員 個人参加の募集のみ指定できる
+    pub capacity: Option<i32>,
+    /// 定員に達したら自動で締め切るか
+    #[graphql(default)]
+    pub close_when_full: bool,
     pub tag_ids: Vec<ID>,
 }
 
+// デコードした募集の入力のID
+#[derive(Debug)]
+pub struct RecruitmentInputIds {
+    pub sport_id: i64,
+    pub prefecture_id: i64,
+    pub tag_ids: Vec<i64>,
+}
+
+impl RecruitmentInput {
+    // 保存する前に呼び出す 形式の正しくないIDはBAD_USER_INPUTで返せるようにIdDecodeErrorのまま返す
+    pub fn decode_ids(&self) -> Result<RecruitmentInputIds, IdDecodeError> {
+        let sport_id = id_decode(&self.sport_id, NodeType::Sport)?;
+        let prefecture_id = id_decode(&self.prefecture_id, NodeType::Prefecture)?;
+        let tag_ids = self
+            .tag_ids
+            .iter()
+            .map(|tag_id| id_decode(tag_id, NodeType::Tag))
+            .collect::<Result<Vec<i64>, _>>()?;
+        Ok(RecruitmentInputIds {
+            sport_id,
+            prefecture_id,
+            tag_ids,
+        })
+    }
+    pub async fn create_recruitment_validate(
+        &self,
+        loaders: &Loaders,
+    ) -> Result<Option<CreateRecruitmentInvalidInputErrors>> {
+        let errors: Vec<CreateRecruitmentInvalidInputError> = self
+            .invalid_input_errors(loaders, None)
+            .await?
+            .into_iter()
+            .map(|(field, message)| CreateRecruitmentInvalidInputError { message, field })
+            .collect();
+        if errors.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(CreateRecruitmentInvalidInputErrors { errors }))
+    }
+    pub async fn update_recruitment_validate(
+        &self,
+        loaders: &Loaders,
+        recruitment: &Recruitment,
+    ) -> Result<Option<UpdateRecruitmentInvalidInputErrors>> {
+        let errors: Vec<UpdateRecruitmentInvalidInputError> = self
+            .invalid_input_errors(loaders, Some(recruitment))
+            .await?
+            .into_iter()
+            .map(|(field, message)| UpdateRecruitmentInvalidInputError { message, field })
+            .collect();
+        if errors.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(UpdateRecruitmentInvalidInputErrors { errors }))
+    }
+    // 入力値を検証してフィールドごとに最初のエラーを返す
+    // 更新の場合は変更していない掲載期限が過ぎていてもエラーにしない
+    async fn invalid_input_errors(
+        &self,
+        loaders: &Loaders,
+        recruitment: Option<&Recruitment>,
+    ) -> Result<Vec<(RecruitmentInvalidInputField, String)>> {
+        let mut errors: Vec<(RecruitmentInvalidInputField, String)> = Vec::new();
+        let mut push_error = |field: RecruitmentInvalidInputField, message: &str| {
+            if !errors.iter().any(|(f, _)| *f == field) {
+                errors.push((field, message.to_string()));
+            }
+        };
+
+        if let Err(e) = self.validate() {
+            for (key, val) in e.field_errors().iter() {
+                let field = match *key {
+                    "title" => RecruitmentInvalidInputField::Title,
+                    "detail" => RecruitmentInvalidInputField::Detail,
+                    &_ => continue,
+                };
+                let message = match val[0].message {
+                    Some(ref message) => message.to_string(),
+                    None => String::from(""),
+                };
+                push_error(field, &message);
+            }
+        }
+        if self.title.trim().is_empty() {
+            push_error(
+                RecruitmentInvalidInputField::Title,
+                "タイトルを入力してください",
+            );
+        }
+
+        // 対戦相手と個人参加の募集は開催場所と日時がないと応募できない
+        if let RecruitmentCategory::Opponent | RecruitmentCategory::Personal = self.category {
+            let has_venue = match self.venue {
+                Some(ref venue) => !venue.trim().is_empty(),
+                None => false,
+            };
+            if !has_venue {
+                push_error(
+                    RecruitmentInvalidInputField::Venue,
+                    "開催場所を入力してください",
+                );
+            }
+            if self.start_at.is_none() {
+                push_error(
+                    RecruitmentInvalidInputField::StartAt,
+                    "開催日時を入力してください",
+                );
+            }
+        }
+
+        if let Some(closing_at) = self.closing_at {
+            let is_changed = match recruitment {
+                Some(recruitment) => recruitment.closing_at != Some(closing_at),
+                None => true,
+            };
+            if is_changed && closing_at <= Local::now() {
+                push_error(
+                    RecruitmentInvalidInputField::ClosingAt,
+                    "掲載期限は現在より後の日時にしてください",
+                );
+            }
+            if let Some(start_at) = self.start_at {
+                if closing_at >= start_at {
+                    push_error(
+                        RecruitmentInvalidInputField::ClosingAt,
+                        "掲載期限は開催日時より前にしてください",
+                    );
+                }
+            }
+        }
+
+        if let Some(capacity) = self.capacity {
+            if self.category != RecruitmentCategory::Personal {
+                push_error(
+                    RecruitmentInvalidInputField::Capacity,
+                    "定員は個人参加の募集のみ指定できます",
+                );
+            }
+            if capacity < 1 {
+                push_error(
+                    RecruitmentInvalidInputField::Capacity,
+                    "定員は1人以上にしてください",
+                );
+            }
+            // 承諾済みの応募を取り消すことになるので承諾済みの人数より少なくはできない
+            if let Some(recruitment) = recruitment {
+                let accepted_count = loaders
+                    .accepted_count_loader
+                    .load_one(recruitment.id)
+                    .await?
+                    .unwrap_or_default();
+                if (capacity as i64) < accepted_count {
+                    push_error(
+                        RecruitmentInvalidInputField::Capacity,
+                        &format!("定員は承諾済みの{}人以上にしてください", accepted_count),
+                    );
+                }
+            }
+        }
+
+        // 緯度と経度はどちらか片方だけでは使えない
+        match (self.venue_lat, self.venue_lng) {
+            (Some(lat), Some(lng)) => {
+                if !(-90.0..=90.0).contains(&lat) {
+                    push_error(
+                        RecruitmentInvalidInputField::VenueLat,
+                        "緯度は-90から90の範囲で入力してください",
+                    );
+                }
+                if !(-180.0..=180.0).contains(&lng) {
+                    push_error(
+                        RecruitmentInvalidInputField::VenueLng,
+                        "経度は-180から180の範囲で入力してください",
+                    );
+                }
+            }
+            (Some(_), None) => {
+                push_error(
+                    RecruitmentInvalidInputField::VenueLng,
+                    "経度を入力してください",
+                );
+            }
+            (None, Some(_)) => {
+                push_error(
+                    RecruitmentInvalidInputField::VenueLat,
+                    "緯度を入力してください",
+                );
+            }
+            (None, None) => {}
+        }
+
+        let sport = match id_decode(&self.sport_id, NodeType::Sport) {
+            Ok(sport_id) => loaders.sport_loader.load_one(sport_id).await?,
+            Err(_) => None,
+        };
+        if sport.is_none() {
+            push_error(
+                RecruitmentInvalidInputField::SportId,
+                "スポーツが見つかりませんでした",
+            );
+        }
+
+        let prefecture = match id_decode(&self.prefecture_id, NodeType::Prefecture) {
+            Ok(prefecture_id) => loaders.prefecture_loader.load_one(prefecture_id).await?,
+            Err(_) => None,
+        };
+        if prefecture.is_none() {
+            push_error(
+                RecruitmentInvalidInputField::PrefectureId,
+                "都道府県が見つかりませんでした",
+            );
+        }
+
+        let tag_ids = self
+            .tag_ids
+            .iter()
+            .map(|tag_id| id_decode(tag_id, NodeType::Tag))
+            .collect::<Result<HashSet<i64>, _>>();
+        let is_all_tags_exist = match tag_ids {
+            Ok(tag_ids) => {
+                let tags = loaders
+                    .tag_id_loader
+                    .load_many(tag_ids.iter().copied())
+                    .await?;
+                tags.len() == tag_ids.len()
+            }
+            Err(_) => false,
+        };
+        if !is_all_tags_exist {
+            push_error(
+                RecruitmentInvalidInputField::TagIds,
+                "タグが見つかりませんでした",
+            );
+        }
+
+        Ok(errors)
+    }
+}
+
+/// 開催日時を初回として繰り返す募集
+#[derive(InputObject, Debug)]
+pub struct CreateRecruitmentSeriesInput {
+    pub recruitment: RecruitmentInput,
+    pub frequency: RecurrenceFrequency,
+    /// この日時までの開催を作成する
+    pub until: DateTime<Local>,
+}
+
+impl CreateRecruitmentSeriesInput {
+    pub async fn create_recruitment_series_validate(
+        &self,
+        loaders: &Loaders,
+    ) -> Result<Option<CreateRecruitmentSeriesInvalidInputErrors>> {
+        let mut errors: Vec<CreateRecruitmentSeriesInvalidInputError> = self
+            .recruitment
+            .invalid_input_errors(loaders, None)
+            .await?
+            .into_iter()
+            .map(|(field, message)| CreateRecruitmentSeriesInvalidInputError { message, field })
+            .collect();
+        let mut push_error = |field: RecruitmentInvalidInputField, message: String| {
+            if !errors.iter().any(|error| error.field == field) {
+                errors.push(CreateRecruitmentSeriesInvalidInputError { message, field });
+            }
+        };
+
+        match self.recruitment.start_at {
+            Some(start_at) if self.until < start_at => {
+                push_error(
+                    RecruitmentInvalidInputField::Until,
+                    String::from("繰り返しの終了日時は開催日時より後にしてください"),
+                );
+            }
+            Some(start_at) => {
+                if self.frequency.occurrences(start_at, self.until).is_none() {
+                    push_error(
+                        RecruitmentInvalidInputField::Until,
+                        format!("繰り返しは{}回までにしてください", MAX_SERIES_OCCURRENCES),
+                    );
+                }
+            }
+            None => {
+                push_error(
+                    RecruitmentInvalidInputField::StartAt,
+                    String::from("繰り返す募集は開催日時を入力してください"),
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(CreateRecruitmentSeriesInvalidInputErrors { errors }))
+    }
+}
+
+/// 繰り返しの募集を編集する範囲
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum RecruitmentEditScope {
+    /// この募集のみ
+    #[default]
+    ThisOccurrence,
+    /// この募集と同じ繰り返しの以降の募集
+    ThisAndFollowing,
+}
+
 #[derive(Union)]
 #[allow(clippy::enum_variant_names, clippy::large_enum_variant)]
 pub enum CreateRecruitmentResult {
@@ -58,6 +377,8 @@ pub enum UpdateRecruitmentResult {
 #[derive(SimpleObject, Debug)]
 pub struct UpdateRecruitmentSuccess {
     pub recruitment_edge: RecruitmentEdge,
+    /// 一緒に更新した同じ繰り返しの以降の募集
+    pub following_recruitments: Vec<Recruitment>,
 }
 
 #[derive(SimpleObject, Debug)]
@@ -71,6 +392,109 @@ pub struct UpdateRecruitmentInvalidInputError {
     pub field: RecruitmentInvalidInputField,
 }
 
+#[derive(Union)]
+#[allow(clippy::enum_variant_names, clippy::large_enum_variant)]
+pub enum CreateRecruitmentSeriesResult {
+    CreateRecruitmentSeriesSuccess(CreateRecruitmentSeriesSuccess),
+    CreateRecruitmentSeriesInvalidInputErrors(CreateRecruitmentSeriesInvalidInputErrors),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct CreateRecruitmentSeriesSuccess {
+    pub series: RecruitmentSeries,
+    /// 作成した募集 開催順
+    pub recruitment_edges: Vec<RecruitmentEdge>,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct CreateRecruitmentSeriesInvalidInputErrors {
+    pub errors: Vec<CreateRecruitmentSeriesInvalidInputError>,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct CreateRecruitmentSeriesInvalidInputError {
+    pub message: String,
+    pub field: RecruitmentInvalidInputField,
+}
+
+#[derive(Union)]
+pub enum RecordRecruitmentViewResult {
+    RecordRecruitmentViewSuccess(RecordRecruitmentViewSuccess),
+    RecruitmentNotFoundError(RecruitmentNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct RecordRecruitmentViewSuccess {
+    /// 閲覧数に数えられたか 同じ日に閲覧済みの場合はfalse
+    pub counted: bool,
+}
+
+#[derive(InputObject, Debug)]
+pub struct DuplicateRecruitmentInput {
+    /// 複製する募集のID
+    pub id: ID,
+    /// 開催日時と掲載期限をずらす日数 指定しなければ日時を空にする
+    pub date_shift_days: Option<i32>,
+}
+
+#[derive(Union)]
+#[allow(clippy::large_enum_variant)]
+pub enum DuplicateRecruitmentResult {
+    DuplicateRecruitmentSuccess(DuplicateRecruitmentSuccess),
+    RecruitmentNotFoundError(RecruitmentNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct DuplicateRecruitmentSuccess {
+    pub recruitment_edge: RecruitmentEdge,
+}
+
+#[derive(Union)]
+pub enum DeleteRecruitmentResult {
+    DeleteRecruitmentSuccess(DeleteRecruitmentSuccess),
+    RecruitmentNotFoundError(RecruitmentNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct DeleteRecruitmentSuccess {
+    /// 削除した募集のID
+    pub deleted_recruitment_id: ID,
+}
+
+#[derive(Union)]
+#[allow(clippy::enum_variant_names, clippy::large_enum_variant)]
+pub enum ChangeRecruitmentStatusResult {
+    ChangeRecruitmentStatusSuccess(ChangeRecruitmentStatusSuccess),
+    ChangeRecruitmentStatusInvalidInputErrors(ChangeRecruitmentStatusInvalidInputErrors),
+    ChangeRecruitmentStatusInvalidTransitionError(ChangeRecruitmentStatusInvalidTransitionError),
+    RecruitmentNotFoundError(RecruitmentNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ChangeRecruitmentStatusSuccess {
+    pub recruitment_edge: RecruitmentEdge,
+}
+
+/// 公開に必要な項目が足りない
+#[derive(SimpleObject, Debug)]
+pub struct ChangeRecruitmentStatusInvalidInputErrors {
+    pub errors: Vec<ChangeRecruitmentStatusInvalidInputError>,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct ChangeRecruitmentStatusInvalidInputError {
+    pub message: String,
+    pub field: RecruitmentInvalidInputField,
+}
+
+/// 現在のステータスからは遷移できない
+#[derive(SimpleObject, Debug)]
+pub struct ChangeRecruitmentStatusInvalidTransitionError {
+    pub message: String,
+    /// 募集の現在のステータス
+    pub status: RecruitmentStatus,
+}
+
 #[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
 pub enum RecruitmentInvalidInputField {
     Title,
@@ -80,7 +504,10 @@ pub enum RecruitmentInvalidInputField {
     PrefectureId,
     Venue,
     VenueLat,
-    VenutLng,
+    VenueLng,
     StartAt,
     ClosingAt,
+    TagIds,
+    Capacity,
+    Until,
 }
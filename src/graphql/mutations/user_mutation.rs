@@ -8,6 +8,7 @@ use validator::{Validate, ValidationError};
 use crate::graphql::{
     id_decode,
     models::user::{is_already_exists_email, is_already_following, User},
+    NodeType,
 };
 
 static PASSWORD_FORMAT: Lazy<Regex> =
@@ -227,7 +228,7 @@ impl FollowUserInput {
         pool: &PgPool,
         viewer_id: i64,
     ) -> Result<Option<FollowUserAlreadyFollowingError>> {
-        let user_id = id_decode(&self.user_id)?;
+        let user_id = id_decode(&self.user_id, NodeType::User)?;
         if is_already_following(pool, viewer_id, user_id).await? {
             tracing::error!("This user is already following");
             let error = FollowUserAlreadyFollowingError {
@@ -266,3 +267,10 @@ pub struct UnfollowUserInput {
 pub struct UnfollowUserResult {
     pub user: User,
 }
+
+//* ResetCalendarFeedUrl */
+#[derive(SimpleObject, Debug)]
+pub struct ResetCalendarFeedUrlResult {
+    /// カレンダーアプリから購読するURL
+    pub calendar_feed_url: String,
+}
@@ -1,5 +1,8 @@
 pub use async_graphql::*;
 
+pub mod application_resolver;
+pub mod message_resolver;
+pub mod notification_resolver;
 pub mod prefecture_resolver;
 pub mod recruitment_resolver;
 pub mod sport_resolver;
@@ -7,8 +10,28 @@ pub mod stock_resolver;
 pub mod tag_resolver;
 pub mod user_resolver;
 
-use crate::graphql::models::{
-    prefecture::Prefecture, recruitment::Recruitment, sport::Sport, tag::Tag, user::User,
+use futures::future::try_join_all;
+
+use crate::{
+    database::get_db_pool,
+    graphql::{
+        auth::get_viewer,
+        global_id_decode,
+        loader::get_loaders,
+        models::{
+            application::Application,
+            message::{get_message, get_message_thread, Message, MessageThread},
+            notification::{get_notification, Notification},
+            prefecture::Prefecture,
+            recruitment::Recruitment,
+            recruitment_revision::{get_revision, RecruitmentRevision},
+            recruitment_series::{get_series, is_series_visible_to, RecruitmentSeries},
+            sport::Sport,
+            tag::Tag,
+            user::User,
+        },
+        NodeType,
+    },
 };
 
 //* Node interface */
@@ -21,6 +44,12 @@ pub enum Node {
     Tag(Tag),
     User(User),
     Recruitment(Recruitment),
+    Application(Application),
+    MessageThread(MessageThread),
+    Message(Message),
+    Notification(Notification),
+    RecruitmentSeries(RecruitmentSeries),
+    RecruitmentRevision(RecruitmentRevision),
 }
 
 #[derive(Default)]
@@ -29,11 +58,106 @@ pub struct RootQuery;
 #[Object]
 impl RootQuery {
     /// 指定されたIDでNodeを取得する
-    async fn node(&self, _ctx: &Context<'_>, _id: ID) -> Option<Node> {
-        let tag = Tag {
-            id: 9,
-            name: String::from("tag"),
-        };
-        Some(Node::Tag(tag))
+    async fn node(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Node>> {
+        load_node(ctx, &id).await
+    }
+    /// 指定されたIDのリストでNodeを取得する
+    async fn nodes(&self, ctx: &Context<'_>, ids: Vec<ID>) -> Result<Vec<Option<Node>>> {
+        // 同時にload_oneを呼ぶことでDataLoaderがタイプごとにまとめて取得する
+        try_join_all(ids.iter().map(|id| load_node(ctx, id))).await
     }
 }
+
+async fn load_node(ctx: &Context<'_>, id: &ID) -> Result<Option<Node>> {
+    let (node_type, id) = global_id_decode(id).map_err(|e| {
+        tracing::error!("node id decode failed: {:?}", e);
+        e.extend()
+    })?;
+    let loaders = get_loaders(ctx).await;
+
+    let node = match node_type {
+        NodeType::Prefecture => loaders
+            .prefecture_loader
+            .load_one(id)
+            .await?
+            .map(Node::Prefecture),
+        NodeType::Sport => loaders.sport_loader.load_one(id).await?.map(Node::Sport),
+        NodeType::Tag => loaders.tag_id_loader.load_one(id).await?.map(Node::Tag),
+        NodeType::User => loaders.user_loader.load_one(id).await?.map(Node::User),
+        NodeType::Recruitment => {
+            let viewer = get_viewer(ctx).await;
+            loaders
+                .recruitment_loader
+                .load_one(id)
+                .await?
+                .filter(|recruitment| recruitment.is_visible_to(viewer.as_ref()))
+                .map(Node::Recruitment)
+        }
+        NodeType::Application => {
+            let viewer = get_viewer(ctx).await;
+            let application = match loaders.application_loader.load_one(id).await? {
+                Some(application) => application,
+                None => return Ok(None),
+            };
+            loaders
+                .recruitment_loader
+                .load_one(application.recruitment_id)
+                .await?
+                .filter(|recruitment| application.is_visible_to(viewer.as_ref(), recruitment))
+                .map(|_| Node::Application(application))
+        }
+        NodeType::MessageThread => {
+            let pool = get_db_pool(ctx).await?;
+            let viewer = get_viewer(ctx).await;
+            get_message_thread(pool, id)
+                .await?
+                .filter(|thread| thread.is_visible_to(viewer.as_ref()))
+                .map(Node::MessageThread)
+        }
+        NodeType::Message => {
+            let pool = get_db_pool(ctx).await?;
+            let viewer = get_viewer(ctx).await;
+            let message = match get_message(pool, id).await? {
+                Some(message) => message,
+                None => return Ok(None),
+            };
+            get_message_thread(pool, message.thread_id)
+                .await?
+                .filter(|thread| thread.is_visible_to(viewer.as_ref()))
+                .map(|_| Node::Message(message))
+        }
+        NodeType::Notification => {
+            let pool = get_db_pool(ctx).await?;
+            let viewer = get_viewer(ctx).await;
+            get_notification(pool, id)
+                .await?
+                .filter(|notification| notification.is_visible_to(viewer.as_ref()))
+                .map(Node::Notification)
+        }
+        NodeType::RecruitmentSeries => {
+            let pool = get_db_pool(ctx).await?;
+            let viewer_id = get_viewer(ctx).await.as_ref().map(|viewer| viewer.id);
+            match get_series(pool, id).await? {
+                Some(series) if is_series_visible_to(pool, series.id, viewer_id).await? => {
+                    Some(Node::RecruitmentSeries(series))
+                }
+                _ => None,
+            }
+        }
+        NodeType::RecruitmentRevision => {
+            let pool = get_db_pool(ctx).await?;
+            let viewer = get_viewer(ctx).await;
+            let revision = match get_revision(pool, id).await? {
+                Some(revision) => revision,
+                None => return Ok(None),
+            };
+            loaders
+                .recruitment_loader
+                .load_one(revision.recruitment_id)
+                .await?
+                .filter(|recruitment| recruitment.is_visible_to(viewer.as_ref()))
+                .map(|_| Node::RecruitmentRevision(revision))
+        }
+    };
+    Ok(node)
+}
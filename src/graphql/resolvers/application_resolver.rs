@@ -0,0 +1,310 @@
+use async_graphql::{Context, ErrorExtensions, Object, Result, SimpleObject, Subscription, ID};
+use chrono::Local;
+use futures::{Stream, StreamExt};
+
+use crate::{
+    database::get_db_pool,
+    graphql::{
+        auth::get_viewer,
+        broker::{get_broker, Event},
+        id_decode, id_encode,
+        models::{
+            application::{
+                self, get_application, Application, ApplicationStatusChange,
+                ApplicationStatusTransition,
+            },
+            notification::{notify, NotificationKind},
+            recruitment::{get_recruitment, RecruitmentStatus},
+        },
+        mutations::application_mutation::{
+            ApplicationNotFoundError, ApplyToRecruitmentInput, ApplyToRecruitmentNotAcceptingError,
+            ApplyToRecruitmentResult, ApplyToRecruitmentSuccess,
+            ChangeApplicationStatusCapacityFullError,
+            ChangeApplicationStatusInvalidTransitionError, ChangeApplicationStatusResult,
+            ChangeApplicationStatusSuccess,
+        },
+        utils::pagination::PageInfo,
+        NodeType,
+    },
+};
+
+use super::recruitment_resolver::RecruitmentNotFoundError;
+
+#[derive(SimpleObject, Debug)]
+pub struct ApplicationConnection {
+    pub edges: Option<Vec<Option<ApplicationEdge>>>,
+    pub page_info: PageInfo,
+}
+
+#[derive(Debug)]
+pub struct ApplicationEdge {
+    pub node: Application,
+}
+
+#[Object]
+impl ApplicationEdge {
+    async fn cursor(&self) -> ID {
+        id_encode(NodeType::Application, self.node.id).into()
+    }
+    async fn node(&self) -> Option<Application> {
+        self.node.clone().into()
+    }
+}
+
+#[derive(Default)]
+pub struct ApplicationMutation;
+
+#[Object]
+impl ApplicationMutation {
+    /// 募集に応募する
+    async fn apply_to_recruitment(
+        &self,
+        ctx: &Context<'_>,
+        input: ApplyToRecruitmentInput,
+    ) -> Result<ApplyToRecruitmentResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        if let Some(errors) = input.apply_to_recruitment_validate() {
+            return Ok(errors.into());
+        }
+
+        let recruitment_id =
+            id_decode(&input.recruitment_id, NodeType::Recruitment).map_err(|e| e.extend())?;
+        let recruitment = match get_recruitment(pool, recruitment_id).await? {
+            Some(recruitment) if recruitment.is_visible_to(Some(viewer)) => recruitment,
+            _ => {
+                tracing::error!("recruitment not found...");
+                let error = RecruitmentNotFoundError {
+                    message: String::from("募集が見つかりませんでした"),
+                };
+                return Ok(error.into());
+            }
+        };
+
+        if recruitment.user_id == viewer.id {
+            tracing::error!("cannot apply to own recruitment");
+            let error = ApplyToRecruitmentNotAcceptingError {
+                message: String::from("自分の募集には応募できません"),
+            };
+            return Ok(error.into());
+        }
+
+        // 自動で締め切られる前でも掲載期限を過ぎていれば応募できない
+        let is_expired = match recruitment.closing_at {
+            Some(closing_at) => closing_at <= Local::now(),
+            None => false,
+        };
+        if recruitment.status != RecruitmentStatus::Published || is_expired {
+            tracing::error!("recruitment is not accepting applications");
+            let error = ApplyToRecruitmentNotAcceptingError {
+                message: String::from("この募集は締め切られています"),
+            };
+            return Ok(error.into());
+        }
+
+        match application::apply(pool, recruitment.id, viewer.id, &input.message).await? {
+            Some(application) => {
+                // 通知に失敗しても応募は成功させる
+                let _ = notify(
+                    pool,
+                    recruitment.user_id,
+                    NotificationKind::Applied,
+                    viewer.id,
+                    Some(recruitment.id),
+                )
+                .await;
+                get_broker(ctx)
+                    .await
+                    .publish(Event::ApplicationStatusChanged {
+                        application: application.clone(),
+                        owner_id: recruitment.user_id,
+                    });
+                Ok(ApplyToRecruitmentSuccess { application }.into())
+            }
+            None => {
+                tracing::error!("recruitment is already applied");
+                let error = ApplyToRecruitmentNotAcceptingError {
+                    message: String::from("この募集には既に応募しています"),
+                };
+                Ok(error.into())
+            }
+        }
+    }
+    /// 応募を承諾する 募集を作成したユーザーのみ承諾できる
+    async fn accept_application(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<ChangeApplicationStatusResult> {
+        change_application_status(ctx, id, ApplicationStatusTransition::Accept).await
+    }
+    /// 応募を断る 募集を作成したユーザーのみ断れる
+    async fn reject_application(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<ChangeApplicationStatusResult> {
+        change_application_status(ctx, id, ApplicationStatusTransition::Reject).await
+    }
+    /// 応募を取り下げる 応募したユーザーのみ取り下げられる
+    async fn withdraw_application(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<ChangeApplicationStatusResult> {
+        change_application_status(ctx, id, ApplicationStatusTransition::Withdraw).await
+    }
+}
+
+async fn change_application_status(
+    ctx: &Context<'_>,
+    id: ID,
+    transition: ApplicationStatusTransition,
+) -> Result<ChangeApplicationStatusResult> {
+    let pool = get_db_pool(ctx).await?;
+    let viewer = match get_viewer(ctx).await {
+        Some(viewer) => viewer,
+        None => return Err(async_graphql::Error::new("Please login")),
+    };
+
+    let id = id_decode(&id, NodeType::Application).map_err(|e| e.extend())?;
+    let application = match get_application(pool, id).await? {
+        Some(application) => application,
+        None => return Ok(application_not_found()),
+    };
+    let recruitment = match get_recruitment(pool, application.recruitment_id).await? {
+        Some(recruitment) if application.is_visible_to(Some(viewer), &recruitment) => recruitment,
+        _ => return Ok(application_not_found()),
+    };
+
+    // 承諾と拒否は募集者、取り下げは応募者のみ
+    let can_change = match transition {
+        ApplicationStatusTransition::Accept | ApplicationStatusTransition::Reject => {
+            recruitment.user_id == viewer.id
+        }
+        ApplicationStatusTransition::Withdraw => application.user_id == viewer.id,
+    };
+    if !can_change {
+        tracing::error!("This application status cannot be changed");
+        return Err(async_graphql::Error::new(
+            "This application status cannot be changed",
+        ));
+    }
+
+    if !transition.from_statuses().contains(&application.status) {
+        tracing::error!(
+            "invalid status transition: {:?} {:?}",
+            application.status,
+            transition
+        );
+        let error = ChangeApplicationStatusInvalidTransitionError {
+            message: String::from("現在のステータスからは変更できません"),
+            status: application.status,
+        };
+        return Ok(error.into());
+    }
+
+    match application::change_status(pool, id, recruitment.id, transition).await? {
+        ApplicationStatusChange::Changed {
+            application,
+            waitlist_changed,
+        } => {
+            let kind = match transition {
+                ApplicationStatusTransition::Accept => Some(NotificationKind::ApplicationAccepted),
+                ApplicationStatusTransition::Reject => Some(NotificationKind::ApplicationRejected),
+                ApplicationStatusTransition::Withdraw => None,
+            };
+            if let Some(kind) = kind {
+                // 通知に失敗してもステータスの変更は成功させる
+                let _ = notify(
+                    pool,
+                    application.user_id,
+                    kind,
+                    viewer.id,
+                    Some(recruitment.id),
+                )
+                .await;
+            }
+            let broker = get_broker(ctx).await;
+            for changed in std::iter::once(application.clone()).chain(waitlist_changed) {
+                broker.publish(Event::ApplicationStatusChanged {
+                    application: changed,
+                    owner_id: recruitment.user_id,
+                });
+            }
+            Ok(ChangeApplicationStatusSuccess { application }.into())
+        }
+        ApplicationStatusChange::CapacityFull => {
+            let error = ChangeApplicationStatusCapacityFullError {
+                message: String::from("定員に達しているため承諾できません"),
+            };
+            Ok(error.into())
+        }
+        // 確認してから更新するまでの間に他でステータスが変更された
+        ApplicationStatusChange::Conflict => {
+            let status = match get_application(pool, id).await? {
+                Some(application) => application.status,
+                None => return Ok(application_not_found()),
+            };
+            let error = ChangeApplicationStatusInvalidTransitionError {
+                message: String::from("現在のステータスからは変更できません"),
+                status,
+            };
+            Ok(error.into())
+        }
+    }
+}
+
+fn application_not_found() -> ChangeApplicationStatusResult {
+    tracing::error!("application not found...");
+    let error = ApplicationNotFoundError {
+        message: String::from("応募が見つかりませんでした"),
+    };
+    error.into()
+}
+
+#[derive(Default)]
+pub struct ApplicationSubscription;
+
+#[Subscription]
+impl ApplicationSubscription {
+    /// ログインユーザー(Viewer)の応募かViewerの募集への応募のステータスが変わったら受け取る
+    /// recruitmentIdを指定するとその募集への応募だけを受け取る
+    async fn application_status_changed(
+        &self,
+        ctx: &Context<'_>,
+        recruitment_id: Option<ID>,
+    ) -> Result<impl Stream<Item = Application>> {
+        let viewer_id = match get_viewer(ctx).await {
+            Some(viewer) => viewer.id,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+        let recruitment_id = match recruitment_id {
+            Some(ref id) => Some(id_decode(id, NodeType::Recruitment).map_err(|e| e.extend())?),
+            None => None,
+        };
+
+        let stream = get_broker(ctx)
+            .await
+            .subscribe()
+            .filter_map(move |event| async move {
+                match event {
+                    Event::ApplicationStatusChanged {
+                        application,
+                        owner_id,
+                    } if (application.user_id == viewer_id || owner_id == viewer_id)
+                        && (recruitment_id.is_none()
+                            || recruitment_id == Some(application.recruitment_id)) =>
+                    {
+                        Some(application)
+                    }
+                    _ => None,
+                }
+            });
+        Ok(stream)
+    }
+}
@@ -0,0 +1,204 @@
+use async_graphql::{Context, ErrorExtensions, Object, Result, SimpleObject, Subscription, ID};
+use futures::{Stream, StreamExt};
+
+use crate::{
+    database::get_db_pool,
+    graphql::{
+        auth::get_viewer,
+        broker::{get_broker, Event},
+        id_decode, id_encode,
+        models::{
+            application::get_application,
+            message::{
+                get_message_thread, mark_message_thread_read, send_message, Message, MessageThread,
+            },
+            recruitment::get_recruitment,
+        },
+        mutations::{
+            application_mutation::ApplicationNotFoundError,
+            message_mutation::{
+                MarkMessageThreadReadResult, MarkMessageThreadReadSuccess,
+                MessageThreadNotFoundError, SendMessageInput, SendMessageResult,
+                SendMessageSuccess,
+            },
+        },
+        utils::pagination::{sort_cursor_encode, PageInfo},
+        NodeType,
+    },
+};
+
+#[derive(SimpleObject, Debug)]
+pub struct MessageThreadConnection {
+    pub edges: Option<Vec<Option<MessageThreadEdge>>>,
+    pub page_info: PageInfo,
+}
+
+#[derive(Debug)]
+pub struct MessageThreadEdge {
+    pub node: MessageThread,
+}
+
+#[Object]
+impl MessageThreadEdge {
+    // 最後のメッセージのIDで並べるのでカーソルに含める
+    async fn cursor(&self) -> ID {
+        sort_cursor_encode(
+            NodeType::MessageThread,
+            self.node.id,
+            self.node.last_message_id,
+        )
+        .into()
+    }
+    async fn node(&self) -> Option<MessageThread> {
+        self.node.clone().into()
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct MessageConnection {
+    pub edges: Option<Vec<Option<MessageEdge>>>,
+    pub page_info: PageInfo,
+}
+
+#[derive(Debug)]
+pub struct MessageEdge {
+    pub node: Message,
+}
+
+#[Object]
+impl MessageEdge {
+    async fn cursor(&self) -> ID {
+        id_encode(NodeType::Message, self.node.id).into()
+    }
+    async fn node(&self) -> Option<Message> {
+        self.node.clone().into()
+    }
+}
+
+#[derive(Default)]
+pub struct MessageMutation;
+
+#[Object]
+impl MessageMutation {
+    /// 応募についてメッセージを送る 募集者と応募者のみ送れる
+    async fn send_message(
+        &self,
+        ctx: &Context<'_>,
+        input: SendMessageInput,
+    ) -> Result<SendMessageResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        if let Some(errors) = input.send_message_validate() {
+            return Ok(errors.into());
+        }
+
+        let application_id =
+            id_decode(&input.application_id, NodeType::Application).map_err(|e| e.extend())?;
+        let application = get_application(pool, application_id).await?;
+        let recruitment = match application {
+            Some(ref application) => get_recruitment(pool, application.recruitment_id).await?,
+            None => None,
+        };
+        let (application, recruitment) = match (application, recruitment) {
+            (Some(application), Some(recruitment))
+                if application.is_visible_to(Some(viewer), &recruitment) =>
+            {
+                (application, recruitment)
+            }
+            _ => {
+                tracing::error!("application not found...");
+                let error = ApplicationNotFoundError {
+                    message: String::from("応募が見つかりませんでした"),
+                };
+                return Ok(error.into());
+            }
+        };
+
+        let (message_thread, message) = send_message(
+            pool,
+            &recruitment,
+            application.user_id,
+            viewer.id,
+            &input.body,
+        )
+        .await?;
+        get_broker(ctx)
+            .await
+            .publish(Event::MessageSent(message.clone()));
+        let success = SendMessageSuccess {
+            message_thread,
+            message_edge: MessageEdge { node: message },
+        };
+        Ok(success.into())
+    }
+    /// やり取りのメッセージを全て既読にする
+    async fn mark_message_thread_read(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<MarkMessageThreadReadResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        let id = id_decode(&id, NodeType::MessageThread).map_err(|e| e.extend())?;
+        match get_message_thread(pool, id).await? {
+            Some(thread) if thread.is_visible_to(Some(viewer)) => {
+                let message_thread = mark_message_thread_read(pool, thread.id, viewer.id).await?;
+                Ok(MarkMessageThreadReadSuccess { message_thread }.into())
+            }
+            _ => {
+                tracing::error!("message thread not found...");
+                let error = MessageThreadNotFoundError {
+                    message: String::from("メッセージのやり取りが見つかりませんでした"),
+                };
+                Ok(error.into())
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MessageSubscription;
+
+#[Subscription]
+impl MessageSubscription {
+    /// やり取りにメッセージが送られたら受け取る 募集者と応募者のみ購読できる
+    async fn message_sent(
+        &self,
+        ctx: &Context<'_>,
+        thread_id: ID,
+    ) -> Result<impl Stream<Item = Message>> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        let thread_id = id_decode(&thread_id, NodeType::MessageThread).map_err(|e| e.extend())?;
+        match get_message_thread(pool, thread_id).await? {
+            Some(thread) if thread.is_visible_to(Some(viewer)) => (),
+            _ => {
+                tracing::error!("message thread not found...");
+                return Err(async_graphql::Error::new("Message thread not found"));
+            }
+        };
+
+        let stream = get_broker(ctx)
+            .await
+            .subscribe()
+            .filter_map(move |event| async move {
+                match event {
+                    Event::MessageSent(message) if message.thread_id == thread_id => Some(message),
+                    _ => None,
+                }
+            });
+        Ok(stream)
+    }
+}
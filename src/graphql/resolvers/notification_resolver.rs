@@ -0,0 +1,105 @@
+use async_graphql::{Context, ErrorExtensions, Object, Result, SimpleObject, ID};
+
+use crate::{
+    database::get_db_pool,
+    graphql::{
+        auth::get_viewer,
+        id_decode,
+        models::{
+            notification::{count_unread_notifications, mark_notifications_read, Notification},
+            notification_setting::{get_notification_settings, update_notification_setting},
+        },
+        mutations::notification_mutation::{
+            MarkNotificationsReadInput, MarkNotificationsReadResult,
+            UpdateNotificationSettingsInput, UpdateNotificationSettingsResult,
+        },
+        utils::pagination::{sort_cursor_encode, PageInfo},
+        IdDecodeError, NodeType,
+    },
+};
+
+#[derive(SimpleObject, Debug)]
+pub struct NotificationConnection {
+    pub edges: Option<Vec<Option<NotificationEdge>>>,
+    pub page_info: PageInfo,
+}
+
+#[derive(Debug)]
+pub struct NotificationEdge {
+    pub node: Notification,
+}
+
+#[Object]
+impl NotificationEdge {
+    // まとめられるたびに並び順が変わるのでsort_keyをカーソルに含める
+    async fn cursor(&self) -> ID {
+        sort_cursor_encode(NodeType::Notification, self.node.id, self.node.sort_key).into()
+    }
+    async fn node(&self) -> Option<Notification> {
+        self.node.clone().into()
+    }
+}
+
+#[derive(Default)]
+pub struct NotificationMutation;
+
+#[Object]
+impl NotificationMutation {
+    /// 通知を既読にする
+    async fn mark_notifications_read(
+        &self,
+        ctx: &Context<'_>,
+        input: MarkNotificationsReadInput,
+    ) -> Result<MarkNotificationsReadResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        let ids = match input.ids {
+            Some(ref ids) => Some(
+                ids.iter()
+                    .map(|id| id_decode(id, NodeType::Notification))
+                    .collect::<Result<Vec<i64>, IdDecodeError>>()
+                    .map_err(|e| e.extend())?,
+            ),
+            None => None,
+        };
+        let notifications = mark_notifications_read(pool, viewer.id, ids.as_deref()).await?;
+        let unread_notification_count = count_unread_notifications(pool, viewer.id).await?;
+
+        Ok(MarkNotificationsReadResult {
+            notifications,
+            unread_notification_count,
+        })
+    }
+    /// 通知の設定を変更する 指定したイベントの指定した項目だけ変更する
+    async fn update_notification_settings(
+        &self,
+        ctx: &Context<'_>,
+        input: UpdateNotificationSettingsInput,
+    ) -> Result<UpdateNotificationSettingsResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        for setting in input.settings {
+            update_notification_setting(
+                pool,
+                viewer.id,
+                setting.event,
+                setting.in_app,
+                setting.email_frequency,
+            )
+            .await?;
+        }
+        let notification_settings = get_notification_settings(pool, viewer.id).await?;
+
+        Ok(UpdateNotificationSettingsResult {
+            notification_settings,
+        })
+    }
+}
@@ -1,17 +1,50 @@
-use async_graphql::{Context, Object, Result, SimpleObject, ID};
-use base64::encode_config;
+use async_graphql::{
+    Context, ErrorExtensions, InputObject, Object, Result, SimpleObject, Subscription, Union, ID,
+};
+use chrono::{DateTime, Duration, Local};
+use futures::{Stream, StreamExt};
+use sqlx::PgPool;
+use std::sync::Arc;
 
 use crate::{
     database::get_db_pool,
     graphql::{
         auth::get_viewer,
+        broker::{get_broker, Event},
         id_decode, id_encode,
-        models::recruitment::{self, get_recruitments, is_next_recruitment, Recruitment},
+        loader::get_loaders,
+        mail::sender::send_recruitment_deleted_notice,
+        models::{
+            notification::{notify_followers, notify_recruitment_changed},
+            recommendation::{get_recommendations, Recommendation},
+            recruitment::{
+                self, get_recruitment, get_recruitments, get_recruitments_by_distance,
+                is_matched_recruitment, is_next_recruitment, is_next_recruitment_by_distance,
+                is_next_searched_recruitment, search_recruitments, NearFilter, Recruitment,
+                RecruitmentCategory, RecruitmentFilter, RecruitmentOrder, RecruitmentStatus,
+                RecruitmentStatusTransition, TagMatch,
+            },
+            recruitment_revision::RecruitmentRevision,
+            recruitment_series::create_series,
+            recruitment_view::{get_view_fingerprint, record_view},
+            user::UserRole,
+        },
         mutations::recruitment_mutation::{
-            CreateRecruitmentResult, CreateRecruitmentSuccess, RecruitmentInput,
-            UpdateRecruitmentResult, UpdateRecruitmentSuccess,
+            ChangeRecruitmentStatusInvalidInputErrors,
+            ChangeRecruitmentStatusInvalidTransitionError, ChangeRecruitmentStatusResult,
+            ChangeRecruitmentStatusSuccess, CreateRecruitmentResult, CreateRecruitmentSeriesInput,
+            CreateRecruitmentSeriesResult, CreateRecruitmentSeriesSuccess,
+            CreateRecruitmentSuccess, DeleteRecruitmentResult, DeleteRecruitmentSuccess,
+            DuplicateRecruitmentInput, DuplicateRecruitmentResult, DuplicateRecruitmentSuccess,
+            RecordRecruitmentViewResult, RecordRecruitmentViewSuccess, RecruitmentEditScope,
+            RecruitmentInput, UpdateRecruitmentResult, UpdateRecruitmentSuccess,
         },
-        utils::pagination::{PageInfo, SearchParams},
+        utils::{
+            geo::distance_km,
+            pagination::{sort_cursor_encode, PageInfo, SearchParams, SortedSearchParams},
+            search::highlight,
+        },
+        IdDecodeError, NodeType,
     },
 };
 
@@ -21,19 +54,232 @@ pub struct RecruitmentConnection {
     pub page_info: PageInfo,
 }
 
+// 抜粋の最大文字数
+const SNIPPET_LENGTH: usize = 120;
+
 #[derive(Debug, Clone)]
 pub struct RecruitmentEdge {
     pub node: Recruitment,
+    pub cursor: Option<String>, // 並び順の値を含むカーソル Noneなら募集のIDから作る
+    pub snippet: Option<String>,
+    pub distance_km: Option<f64>,
+}
+
+impl From<Recruitment> for RecruitmentEdge {
+    fn from(node: Recruitment) -> Self {
+        Self {
+            node,
+            cursor: None,
+            snippet: None,
+            distance_km: None,
+        }
+    }
+}
+
+impl RecruitmentEdge {
+    // キーワードに一致した箇所の抜粋を付ける 詳細に一致しなければタイトルから作る
+    fn with_snippet(mut self, keyword: Option<&str>) -> Self {
+        if let Some(keyword) = keyword {
+            self.snippet = self
+                .node
+                .detail
+                .as_deref()
+                .and_then(|detail| highlight(detail, keyword, SNIPPET_LENGTH))
+                .or_else(|| highlight(&self.node.title, keyword, SNIPPET_LENGTH));
+        }
+        self
+    }
+    // 指定した地点から開催場所までの距離を付ける
+    fn with_distance(mut self, near: Option<&NearFilter>) -> Self {
+        if let (Some(near), Some(lat), Some(lng)) = (near, self.node.venue_lat, self.node.venue_lng)
+        {
+            self.distance_km = Some(distance_km(near.lat, near.lng, lat, lng));
+        }
+        self
+    }
 }
 
 #[Object]
 impl RecruitmentEdge {
     pub async fn cursor(&self) -> ID {
-        id_encode("Recruitment", self.node.id).into()
+        match self.cursor {
+            Some(ref cursor) => cursor.as_str().into(),
+            None => id_encode(NodeType::Recruitment, self.node.id).into(),
+        }
     }
     pub async fn node(&self) -> Option<Recruitment> {
         self.node.clone().into()
     }
+    /// キーワードに一致した箇所を<mark>で囲んだ抜粋 HTMLエスケープ済み
+    pub async fn snippet(&self) -> Option<&str> {
+        self.snippet.as_deref()
+    }
+    /// nearで指定した地点から開催場所までの距離(km)
+    pub async fn distance_km(&self) -> Option<f64> {
+        self.distance_km
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct RecommendedRecruitmentConnection {
+    pub edges: Option<Vec<Option<RecommendedRecruitmentEdge>>>,
+    pub page_info: PageInfo,
+}
+
+#[derive(Debug)]
+pub struct RecommendedRecruitmentEdge {
+    pub node: Recommendation,
+}
+
+#[Object]
+impl RecommendedRecruitmentEdge {
+    async fn cursor(&self) -> ID {
+        sort_cursor_encode(
+            NodeType::Recruitment,
+            self.node.recruitment.id,
+            self.node.score,
+        )
+        .into()
+    }
+    async fn node(&self) -> Option<Recruitment> {
+        self.node.recruitment.clone().into()
+    }
+    /// おすすめ度 大きいほどおすすめ
+    async fn score(&self) -> f64 {
+        self.node.score
+    }
+    /// おすすめする理由
+    async fn reason(&self) -> String {
+        self.node.reason()
+    }
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct RecruitmentRevisionConnection {
+    pub edges: Option<Vec<Option<RecruitmentRevisionEdge>>>,
+    pub page_info: PageInfo,
+}
+
+#[derive(Debug)]
+pub struct RecruitmentRevisionEdge {
+    pub node: RecruitmentRevision,
+}
+
+#[Object]
+impl RecruitmentRevisionEdge {
+    async fn cursor(&self) -> ID {
+        id_encode(NodeType::RecruitmentRevision, self.node.id).into()
+    }
+    async fn node(&self) -> Option<RecruitmentRevision> {
+        self.node.clone().into()
+    }
+}
+
+#[derive(Union)]
+#[allow(clippy::large_enum_variant)]
+pub enum RecruitmentResult {
+    Recruitment(Recruitment),
+    RecruitmentNotFoundError(RecruitmentNotFoundError),
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct RecruitmentNotFoundError {
+    pub message: String,
+}
+
+/// 地点と半径
+#[derive(InputObject, Debug, Clone, Copy)]
+pub struct NearInput {
+    /// 緯度
+    pub lat: f64,
+    /// 経度
+    pub lng: f64,
+    /// 半径(km) 100kmまで
+    pub radius_km: f64,
+}
+
+impl NearInput {
+    fn validate(&self) -> Result<NearFilter> {
+        if !(-90.0..=90.0).contains(&self.lat) || !(-180.0..=180.0).contains(&self.lng) {
+            return Err(async_graphql::Error::new("緯度経度が正しくありません"));
+        }
+        if !(self.radius_km > 0.0 && self.radius_km <= 100.0) {
+            return Err(async_graphql::Error::new(
+                "半径は100km以内で指定してください",
+            ));
+        }
+        Ok(NearFilter {
+            lat: self.lat,
+            lng: self.lng,
+            radius_km: self.radius_km,
+        })
+    }
+}
+
+/// 募集の検索条件
+#[derive(InputObject, Debug, Default)]
+pub struct RecruitmentFilterInput {
+    pub sport_id: Option<ID>,
+    pub prefecture_id: Option<ID>,
+    pub category: Option<RecruitmentCategory>,
+    #[graphql(default)]
+    pub tag_ids: Vec<ID>,
+    /// tag_idsをいずれか含むか全て含むか
+    #[graphql(default)]
+    pub tag_match: TagMatch,
+    /// 開催日時がこの日時以降
+    pub start_at_from: Option<DateTime<Local>>,
+    /// 開催日時がこの日時より前
+    pub start_at_to: Option<DateTime<Local>>,
+    /// 締め切られた募集も含めるか
+    #[graphql(default)]
+    pub include_closed: bool,
+    /// タイトルと詳細に含まれるキーワード
+    pub keyword: Option<String>,
+    /// 開催場所がこの範囲内
+    pub near: Option<NearInput>,
+}
+
+impl RecruitmentFilterInput {
+    pub fn decode(self) -> Result<RecruitmentFilter> {
+        let sport_id = match self.sport_id {
+            Some(ref id) => Some(id_decode(id, NodeType::Sport).map_err(|e| e.extend())?),
+            None => None,
+        };
+        let prefecture_id = match self.prefecture_id {
+            Some(ref id) => Some(id_decode(id, NodeType::Prefecture).map_err(|e| e.extend())?),
+            None => None,
+        };
+        let mut tag_ids = self
+            .tag_ids
+            .iter()
+            .map(|id| id_decode(id, NodeType::Tag))
+            .collect::<Result<Vec<i64>, IdDecodeError>>()
+            .map_err(|e| e.extend())?;
+        tag_ids.sort_unstable();
+        tag_ids.dedup();
+        let keyword = self
+            .keyword
+            .map(|keyword| keyword.trim().to_string())
+            .filter(|keyword| !keyword.is_empty());
+        let near = match self.near {
+            Some(near) => Some(near.validate()?),
+            None => None,
+        };
+
+        Ok(RecruitmentFilter {
+            sport_id,
+            prefecture_id,
+            category: self.category,
+            tag_ids,
+            tag_match: self.tag_match,
+            start_at_from: self.start_at_from,
+            start_at_to: self.start_at_to,
+            include_closed: self.include_closed,
+            keyword,
+            near,
+        })
+    }
 }
 
 #[derive(Default)]
@@ -41,49 +287,241 @@ pub struct RecruitmentQuery;
 
 #[Object]
 impl RecruitmentQuery {
+    /// 指定されたIDの募集を取得する 下書きは作成したユーザーのみ取得できる
+    async fn recruitment(&self, ctx: &Context<'_>, id: ID) -> Result<RecruitmentResult> {
+        let loaders = get_loaders(ctx).await;
+        let viewer = get_viewer(ctx).await;
+        let id = id_decode(&id, NodeType::Recruitment).map_err(|e| e.extend())?;
+
+        let recruitment = loaders
+            .recruitment_loader
+            .load_one(id)
+            .await?
+            .filter(|recruitment| recruitment.is_visible_to(viewer.as_ref()));
+
+        match recruitment {
+            Some(recruitment) => Ok(recruitment.into()),
+            None => {
+                tracing::error!("recruitment not found...");
+                let error = RecruitmentNotFoundError {
+                    message: String::from("募集が見つかりませんでした"),
+                };
+                Ok(error.into())
+            }
+        }
+    }
     /// 公開中の募集のリストを取得する
     async fn recruitments(
         &self,
         ctx: &Context<'_>,
         after: Option<ID>,
         first: Option<i32>,
+        filter: Option<RecruitmentFilterInput>,
+        #[graphql(default)] order_by: RecruitmentOrder,
     ) -> Result<RecruitmentConnection> {
         let pool = get_db_pool(ctx).await?;
-        let search_params = SearchParams::new(after, first)?;
+        let filter = filter.unwrap_or_default().decode()?;
 
-        let recruitments = get_recruitments(pool, search_params).await?;
+        match order_by {
+            RecruitmentOrder::Newest => newest_recruitments(pool, after, first, &filter).await,
+            RecruitmentOrder::Relevance => relevant_recruitments(pool, after, first, &filter).await,
+            RecruitmentOrder::Distance => nearest_recruitments(pool, after, first, &filter).await,
+        }
+    }
+    /// ログインユーザー(Viewer)におすすめの公開中の募集 おすすめ度の高い順
+    /// ストックした募集やフォローしているユーザーから求める 未ログインの場合はストックされた数の多い順
+    async fn recommended_recruitments(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<ID>,
+        first: Option<i32>,
+    ) -> Result<RecommendedRecruitmentConnection> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer_id = get_viewer(ctx).await.as_ref().map(|viewer| viewer.id);
+        let params = SortedSearchParams::new(after, first, NodeType::Recruitment)?;
+        let (recommendations, has_next_page) =
+            get_recommendations(pool, viewer_id, &params).await?;
 
-        let edges: Vec<Option<RecruitmentEdge>> = recruitments
-            .iter()
-            .map(|recruitment| {
-                RecruitmentEdge {
-                    node: recruitment.to_owned(),
+        let page_info = match recommendations.last() {
+            Some(recommendation) => PageInfo {
+                has_next_page,
+                end_cursor: Some(sort_cursor_encode(
+                    NodeType::Recruitment,
+                    recommendation.recruitment.id,
+                    recommendation.score,
+                )),
+                ..Default::default()
+            },
+            None => Default::default(),
+        };
+        let edges: Vec<Option<RecommendedRecruitmentEdge>> = recommendations
+            .into_iter()
+            .map(|recommendation| {
+                RecommendedRecruitmentEdge {
+                    node: recommendation,
                 }
                 .into()
             })
             .collect();
 
-        let page_info = match recruitments.last() {
-            Some(recruitment) => {
-                let is_next = is_next_recruitment(pool, recruitment.id).await?;
-                let encoded_id =
-                    encode_config(format!("Recruitment:{}", recruitment.id), base64::URL_SAFE);
-                PageInfo {
-                    has_next_page: is_next,
-                    end_cursor: Some(encoded_id),
-                    ..Default::default()
-                }
-            }
-            None => Default::default(),
-        };
-
-        Ok(RecruitmentConnection {
-            page_info,
+        Ok(RecommendedRecruitmentConnection {
             edges: edges.into(),
+            page_info,
         })
     }
 }
 
+async fn newest_recruitments(
+    pool: &PgPool,
+    after: Option<ID>,
+    first: Option<i32>,
+    filter: &RecruitmentFilter,
+) -> Result<RecruitmentConnection> {
+    let search_params = SearchParams::new(after, first, NodeType::Recruitment)?;
+    let recruitments = get_recruitments(pool, search_params, filter).await?;
+
+    let edges: Vec<Option<RecruitmentEdge>> = recruitments
+        .iter()
+        .map(|recruitment| {
+            RecruitmentEdge::from(recruitment.to_owned())
+                .with_snippet(filter.keyword.as_deref())
+                .with_distance(filter.near.as_ref())
+                .into()
+        })
+        .collect();
+
+    let page_info = match recruitments.last() {
+        Some(recruitment) => {
+            let is_next = is_next_recruitment(pool, recruitment.id, filter).await?;
+            let encoded_id = id_encode(NodeType::Recruitment, recruitment.id);
+            PageInfo {
+                has_next_page: is_next,
+                end_cursor: Some(encoded_id),
+                ..Default::default()
+            }
+        }
+        None => Default::default(),
+    };
+
+    Ok(RecruitmentConnection {
+        page_info,
+        edges: edges.into(),
+    })
+}
+
+async fn relevant_recruitments(
+    pool: &PgPool,
+    after: Option<ID>,
+    first: Option<i32>,
+    filter: &RecruitmentFilter,
+) -> Result<RecruitmentConnection> {
+    let keyword = match filter.keyword.as_deref() {
+        Some(keyword) => keyword,
+        None => {
+            tracing::error!("keyword is required to order by relevance");
+            return Err(async_graphql::Error::new(
+                "関連度順で並べるにはキーワードを指定してください",
+            ));
+        }
+    };
+    let params = SortedSearchParams::new(after, first, NodeType::Recruitment)?;
+    let rows = search_recruitments(pool, &params, filter, keyword).await?;
+
+    let edges: Vec<Option<RecruitmentEdge>> = rows
+        .iter()
+        .map(|(recruitment, score)| {
+            let mut edge = RecruitmentEdge::from(recruitment.to_owned())
+                .with_snippet(Some(keyword))
+                .with_distance(filter.near.as_ref());
+            edge.cursor = Some(sort_cursor_encode(
+                NodeType::Recruitment,
+                recruitment.id,
+                *score,
+            ));
+            edge.into()
+        })
+        .collect();
+
+    let page_info = match rows.last() {
+        Some((recruitment, score)) => {
+            let has_next_page =
+                is_next_searched_recruitment(pool, *score, recruitment.id, filter, keyword).await?;
+            PageInfo {
+                has_next_page,
+                end_cursor: Some(sort_cursor_encode(
+                    NodeType::Recruitment,
+                    recruitment.id,
+                    *score,
+                )),
+                ..Default::default()
+            }
+        }
+        None => Default::default(),
+    };
+
+    Ok(RecruitmentConnection {
+        page_info,
+        edges: edges.into(),
+    })
+}
+
+async fn nearest_recruitments(
+    pool: &PgPool,
+    after: Option<ID>,
+    first: Option<i32>,
+    filter: &RecruitmentFilter,
+) -> Result<RecruitmentConnection> {
+    let near = match filter.near {
+        Some(ref near) => near,
+        None => {
+            tracing::error!("near is required to order by distance");
+            return Err(async_graphql::Error::new(
+                "距離順で並べるには地点を指定してください",
+            ));
+        }
+    };
+    let params = SortedSearchParams::new(after, first, NodeType::Recruitment)?;
+    let rows = get_recruitments_by_distance(pool, &params, filter, near).await?;
+
+    let edges: Vec<Option<RecruitmentEdge>> = rows
+        .iter()
+        .map(|(recruitment, distance)| {
+            let mut edge = RecruitmentEdge::from(recruitment.to_owned())
+                .with_snippet(filter.keyword.as_deref());
+            edge.distance_km = Some(*distance);
+            edge.cursor = Some(sort_cursor_encode(
+                NodeType::Recruitment,
+                recruitment.id,
+                *distance,
+            ));
+            edge.into()
+        })
+        .collect();
+
+    let page_info = match rows.last() {
+        Some((recruitment, distance)) => {
+            let has_next_page =
+                is_next_recruitment_by_distance(pool, *distance, recruitment.id, filter, near)
+                    .await?;
+            PageInfo {
+                has_next_page,
+                end_cursor: Some(sort_cursor_encode(
+                    NodeType::Recruitment,
+                    recruitment.id,
+                    *distance,
+                )),
+                ..Default::default()
+            }
+        }
+        None => Default::default(),
+    };
+
+    Ok(RecruitmentConnection {
+        page_info,
+        edges: edges.into(),
+    })
+}
+
 #[derive(Default)]
 pub struct RecruitmentMutation;
 
@@ -101,17 +539,61 @@ impl RecruitmentMutation {
             None => return Err(async_graphql::Error::new("Please login")),
         };
 
-        let recruitment = recruitment::create(pool, input, viewer.id).await?;
-        let recruitment_edge = RecruitmentEdge { node: recruitment };
+        let loaders = get_loaders(ctx).await;
+        if let Some(errors) = input.create_recruitment_validate(loaders).await? {
+            return Ok(errors.into());
+        }
+
+        let ids = input.decode_ids().map_err(|e| e.extend())?;
+        let recruitment = recruitment::create(pool, input, ids, viewer.id).await?;
+        let recruitment_edge = RecruitmentEdge::from(recruitment);
         let success = CreateRecruitmentSuccess { recruitment_edge };
         Ok(success.into())
     }
-    /// 募集の更新をする
+    /// 開催日時から繰り返す募集を下書きとしてまとめて作成する
+    async fn create_recruitment_series(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateRecruitmentSeriesInput,
+    ) -> Result<CreateRecruitmentSeriesResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        let loaders = get_loaders(ctx).await;
+        if let Some(errors) = input.create_recruitment_series_validate(loaders).await? {
+            return Ok(errors.into());
+        }
+
+        let ids = input.recruitment.decode_ids().map_err(|e| e.extend())?;
+        let (series, recruitments) = create_series(
+            pool,
+            &input.recruitment,
+            &ids,
+            input.frequency,
+            input.until,
+            viewer.id,
+        )
+        .await?;
+        let recruitment_edges = recruitments
+            .into_iter()
+            .map(RecruitmentEdge::from)
+            .collect();
+        let success = CreateRecruitmentSeriesSuccess {
+            series,
+            recruitment_edges,
+        };
+        Ok(success.into())
+    }
+    /// 募集の更新をする 繰り返しの募集は以降の募集もまとめて更新できる
     async fn update_recruitment(
         &self,
         ctx: &Context<'_>,
         id: ID,
         input: RecruitmentInput,
+        #[graphql(default)] scope: RecruitmentEditScope,
     ) -> Result<UpdateRecruitmentResult> {
         let pool = get_db_pool(ctx).await?;
         let viewer = match get_viewer(ctx).await {
@@ -119,9 +601,296 @@ impl RecruitmentMutation {
             None => return Err(async_graphql::Error::new("Please login")),
         };
 
-        let recruitment = recruitment::update(pool, input, id_decode(&id)?, viewer.id).await?;
-        let recruitment_edge = RecruitmentEdge { node: recruitment };
-        let success = UpdateRecruitmentSuccess { recruitment_edge };
+        let id = id_decode(&id, NodeType::Recruitment).map_err(|e| e.extend())?;
+        let recruitment = match get_recruitment(pool, id).await? {
+            Some(recruitment) if recruitment.user_id == viewer.id => recruitment,
+            _ => {
+                tracing::error!("This recruitment cannot be updated");
+                return Err(async_graphql::Error::new(
+                    "This recruitment cannot be updated",
+                ));
+            }
+        };
+
+        let loaders = get_loaders(ctx).await;
+        if let Some(errors) = input
+            .update_recruitment_validate(loaders, &recruitment)
+            .await?
+        {
+            return Ok(errors.into());
+        }
+
+        let ids = input.decode_ids().map_err(|e| e.extend())?;
+        let with_following = scope == RecruitmentEditScope::ThisAndFollowing;
+        let (recruitment, following_recruitments, schedule_changed_ids) =
+            recruitment::update(pool, input, ids, &recruitment, viewer.id, with_following).await?;
+        // 通知に失敗しても募集の更新は成功させる
+        for recruitment_id in schedule_changed_ids {
+            let _ = notify_recruitment_changed(pool, recruitment_id, viewer.id).await;
+        }
+
+        let recruitment_edge = RecruitmentEdge::from(recruitment);
+        let success = UpdateRecruitmentSuccess {
+            recruitment_edge,
+            following_recruitments,
+        };
+        Ok(success.into())
+    }
+    /// 募集を複製して自分の下書きとして作成する 閲覧できる募集のみ複製できる
+    async fn duplicate_recruitment(
+        &self,
+        ctx: &Context<'_>,
+        input: DuplicateRecruitmentInput,
+    ) -> Result<DuplicateRecruitmentResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        let id = id_decode(&input.id, NodeType::Recruitment).map_err(|e| e.extend())?;
+        let recruitment = match get_recruitment(pool, id).await? {
+            Some(recruitment) if recruitment.is_visible_to(Some(viewer)) => recruitment,
+            _ => {
+                tracing::error!("recruitment not found...");
+                let error = RecruitmentNotFoundError {
+                    message: String::from("募集が見つかりませんでした"),
+                };
+                return Ok(error.into());
+            }
+        };
+
+        let date_shift = input
+            .date_shift_days
+            .map(|days| Duration::days(days.into()));
+        let recruitment =
+            recruitment::duplicate(pool, recruitment.id, viewer.id, date_shift).await?;
+        let recruitment_edge = RecruitmentEdge::from(recruitment);
+        let success = DuplicateRecruitmentSuccess { recruitment_edge };
+        Ok(success.into())
+    }
+    /// 募集の詳細の閲覧を記録する 作成したユーザー自身の閲覧は数えない
+    async fn record_recruitment_view(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<RecordRecruitmentViewResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = get_viewer(ctx).await;
+        let id = id_decode(&id, NodeType::Recruitment).map_err(|e| e.extend())?;
+
+        let recruitment = match get_recruitment(pool, id).await? {
+            Some(recruitment) if recruitment.status != RecruitmentStatus::Draft => recruitment,
+            _ => {
+                tracing::error!("recruitment not found...");
+                let error = RecruitmentNotFoundError {
+                    message: String::from("募集が見つかりませんでした"),
+                };
+                return Ok(error.into());
+            }
+        };
+        let viewer_id = viewer.as_ref().map(|viewer| viewer.id);
+        if viewer_id == Some(recruitment.user_id) {
+            return Ok(RecordRecruitmentViewSuccess { counted: false }.into());
+        }
+
+        let fingerprint = get_view_fingerprint(ctx).await;
+        let counted = record_view(pool, recruitment.id, viewer_id, fingerprint).await?;
+        Ok(RecordRecruitmentViewSuccess { counted }.into())
+    }
+    /// 募集を削除する 作成したユーザーか管理者のみ削除できる
+    async fn delete_recruitment(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<DeleteRecruitmentResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        let decoded_id = id_decode(&id, NodeType::Recruitment).map_err(|e| e.extend())?;
+        let recruitment = match get_recruitment(pool, decoded_id).await? {
+            Some(recruitment) => recruitment,
+            None => {
+                tracing::error!("recruitment not found...");
+                let error = RecruitmentNotFoundError {
+                    message: String::from("募集が見つかりませんでした"),
+                };
+                return Ok(error.into());
+            }
+        };
+
+        if recruitment.user_id != viewer.id && viewer.role != UserRole::Admin {
+            tracing::error!("This recruitment cannot be deleted");
+            return Err(async_graphql::Error::new(
+                "This recruitment cannot be deleted",
+            ));
+        }
+
+        let stocked_users = recruitment::delete(pool, recruitment.id).await?;
+
+        // 募集をストックしていたユーザーにメールで知らせる
+        tokio::spawn(async move {
+            for user in stocked_users.iter() {
+                if let Err(e) = send_recruitment_deleted_notice(user, &recruitment.title).await {
+                    tracing::error!("send recruitment deleted notice failed: {:?}", e);
+                }
+            }
+        });
+
+        let success = DeleteRecruitmentSuccess {
+            deleted_recruitment_id: id,
+        };
         Ok(success.into())
     }
+    /// 下書きの募集を公開する
+    async fn publish_recruitment(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<ChangeRecruitmentStatusResult> {
+        change_recruitment_status(ctx, id, RecruitmentStatusTransition::Publish).await
+    }
+    /// 公開中の募集を締め切る
+    async fn close_recruitment(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<ChangeRecruitmentStatusResult> {
+        change_recruitment_status(ctx, id, RecruitmentStatusTransition::Close).await
+    }
+    /// 締め切った募集を再び公開する
+    async fn reopen_recruitment(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<ChangeRecruitmentStatusResult> {
+        change_recruitment_status(ctx, id, RecruitmentStatusTransition::Reopen).await
+    }
+    /// 公開中の募集を下書きに戻す
+    async fn unpublish_recruitment(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> Result<ChangeRecruitmentStatusResult> {
+        change_recruitment_status(ctx, id, RecruitmentStatusTransition::Unpublish).await
+    }
+}
+
+// 募集を作成したユーザーのみステータスを変更できる
+async fn change_recruitment_status(
+    ctx: &Context<'_>,
+    id: ID,
+    transition: RecruitmentStatusTransition,
+) -> Result<ChangeRecruitmentStatusResult> {
+    let pool = get_db_pool(ctx).await?;
+    let viewer = match get_viewer(ctx).await {
+        Some(viewer) => viewer,
+        None => return Err(async_graphql::Error::new("Please login")),
+    };
+
+    let id = id_decode(&id, NodeType::Recruitment).map_err(|e| e.extend())?;
+    let recruitment = match get_recruitment(pool, id).await? {
+        Some(recruitment) if recruitment.is_visible_to(Some(viewer)) => recruitment,
+        _ => {
+            tracing::error!("recruitment not found...");
+            let error = RecruitmentNotFoundError {
+                message: String::from("募集が見つかりませんでした"),
+            };
+            return Ok(error.into());
+        }
+    };
+
+    if recruitment.user_id != viewer.id {
+        tracing::error!("This recruitment status cannot be changed");
+        return Err(async_graphql::Error::new(
+            "This recruitment status cannot be changed",
+        ));
+    }
+
+    if recruitment.status != transition.from_status() {
+        tracing::error!(
+            "invalid status transition: {:?} {:?}",
+            recruitment.status,
+            transition
+        );
+        let error = ChangeRecruitmentStatusInvalidTransitionError {
+            message: String::from("現在のステータスからは変更できません"),
+            status: recruitment.status,
+        };
+        return Ok(error.into());
+    }
+
+    if transition.to_status() == RecruitmentStatus::Published {
+        let errors = recruitment.publish_errors();
+        if !errors.is_empty() {
+            return Ok(ChangeRecruitmentStatusInvalidInputErrors { errors }.into());
+        }
+    }
+
+    match recruitment::change_status(pool, id, transition, viewer.id).await? {
+        Some(recruitment) => {
+            if transition == RecruitmentStatusTransition::Publish {
+                get_broker(ctx)
+                    .await
+                    .publish(Event::RecruitmentPublished(recruitment.clone()));
+                // 通知に失敗しても公開は成功させる
+                let _ = notify_followers(pool, &recruitment).await;
+            }
+            let recruitment_edge = RecruitmentEdge::from(recruitment);
+            let success = ChangeRecruitmentStatusSuccess { recruitment_edge };
+            Ok(success.into())
+        }
+        // 確認してから更新するまでの間に他でステータスが変更された
+        None => {
+            let status = match get_recruitment(pool, id).await? {
+                Some(recruitment) => recruitment.status,
+                None => recruitment.status,
+            };
+            let error = ChangeRecruitmentStatusInvalidTransitionError {
+                message: String::from("現在のステータスからは変更できません"),
+                status,
+            };
+            Ok(error.into())
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RecruitmentSubscription;
+
+#[Subscription]
+impl RecruitmentSubscription {
+    /// 検索条件に一致する募集が公開されたら受け取る
+    async fn recruitment_published(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<RecruitmentFilterInput>,
+    ) -> Result<impl Stream<Item = Recruitment>> {
+        let pool = Arc::clone(get_db_pool(ctx).await?);
+        let filter = Arc::new(filter.unwrap_or_default().decode()?);
+
+        // 検索と条件がずれないように全ての条件を検索と同じくDBで判定する
+        let stream = get_broker(ctx).await.subscribe().filter_map(move |event| {
+            let pool = Arc::clone(&pool);
+            let filter = Arc::clone(&filter);
+            async move {
+                let recruitment = match event {
+                    Event::RecruitmentPublished(recruitment) => recruitment,
+                    _ => return None,
+                };
+                match is_matched_recruitment(&pool, recruitment.id, &filter).await {
+                    Ok(true) => Some(recruitment),
+                    Ok(false) => None,
+                    Err(e) => {
+                        tracing::error!("recruitment published filter failed: {:?}", e);
+                        None
+                    }
+                }
+            }
+        });
+        Ok(stream)
+    }
 }
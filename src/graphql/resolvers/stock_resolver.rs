@@ -1,4 +1,4 @@
-use async_graphql::{Context, Object, Result};
+use async_graphql::{Context, ErrorExtensions, Object, Result};
 
 use crate::{
     database::get_db_pool,
@@ -6,6 +6,7 @@ use crate::{
         auth::get_viewer,
         id_decode,
         models::{
+            notification::{notify, NotificationKind},
             recruitment::get_recruitment,
             stock::{add_stock, is_already_stocked, remove_stock},
         },
@@ -13,6 +14,7 @@ use crate::{
             AddStockAlreadyStockedError, AddStockInput, AddStockResult, AddStockSuccess,
             RemoveStockInput, RemoveStockResult,
         },
+        NodeType,
     },
 };
 
@@ -31,7 +33,8 @@ impl StockMutation {
             None => return Err(async_graphql::Error::new("Please login")),
         };
 
-        let decoded_recruitment_id = id_decode(&input.recruitment_id)?;
+        let decoded_recruitment_id =
+            id_decode(&input.recruitment_id, NodeType::Recruitment).map_err(|e| e.extend())?;
         // 既にストックしていたらエラーを返す
         if is_already_stocked(pool, viewer.id, decoded_recruitment_id).await? {
             tracing::error!("recruitment is already stocked");
@@ -48,8 +51,17 @@ impl StockMutation {
                 return Err(async_graphql::Error::new("recruitment not found..."));
             }
         };
+        // 通知に失敗してもストックは成功させる
+        let _ = notify(
+            pool,
+            recruitment.user_id,
+            NotificationKind::Stocked,
+            viewer.id,
+            Some(recruitment.id),
+        )
+        .await;
         let success = AddStockSuccess {
-            recruitment_edge: RecruitmentEdge { node: recruitment },
+            recruitment_edge: RecruitmentEdge::from(recruitment),
         };
 
         Ok(success.into())
@@ -66,7 +78,8 @@ impl StockMutation {
             None => return Err(async_graphql::Error::new("Please login")),
         };
 
-        let decoded_recruitment_id = id_decode(&input.recruitment_id)?;
+        let decoded_recruitment_id =
+            id_decode(&input.recruitment_id, NodeType::Recruitment).map_err(|e| e.extend())?;
         remove_stock(pool, viewer.id, decoded_recruitment_id).await?;
         let recruitment = match get_recruitment(pool, decoded_recruitment_id).await? {
             Some(recruitment) => recruitment,
@@ -1,11 +1,12 @@
 use async_graphql::{Context, Object, Result};
-use base64::{encode_config, URL_SAFE};
 
 use crate::{
     database::get_db_pool,
     graphql::{
+        id_encode,
         models::tag::{self, get_tags, Tag},
         mutations::tag_mutation::{CreateTagInput, CreateTagResult, CreateTagSuccess},
+        NodeType,
     },
 };
 
@@ -18,7 +19,7 @@ pub struct TagEdge {
 #[Object]
 impl TagEdge {
     pub async fn cursor(&self) -> String {
-        encode_config(format!("Tag:{}", self.node.id), URL_SAFE)
+        id_encode(NodeType::Tag, self.node.id)
     }
     pub async fn node(&self) -> Tag {
         self.node.clone()
@@ -1,4 +1,4 @@
-use async_graphql::{Context, Object, Result, SimpleObject, ID};
+use async_graphql::{Context, ErrorExtensions, Object, Result, SimpleObject, ID};
 
 use crate::{
     database::get_db_pool,
@@ -9,16 +9,21 @@ use crate::{
         },
         id_decode, id_encode,
         mail::sender::send_email_verification_code,
-        models::user::{
-            self, authentication, follow, get_user_from_email, get_user_from_id, unfollow, User,
+        models::{
+            calendar::{calendar_feed_url, reset_calendar_token},
+            notification::{notify, NotificationKind},
+            user::{
+                self, authentication, follow, get_user_from_email, get_user_from_id, unfollow, User,
+            },
         },
         mutations::user_mutation::{
             FollowUserInput, FollowUserResult, FollowUserSuccess, LoginUserAuthenticationError,
             LoginUserInput, LoginUserNotFoundError, LoginUserResult, LoginUserSuccess,
-            RegisterUserInput, RegisterUserResult, RegisterUserSuccess, UnfollowUserInput,
-            UnfollowUserResult,
+            RegisterUserInput, RegisterUserResult, RegisterUserSuccess, ResetCalendarFeedUrlResult,
+            UnfollowUserInput, UnfollowUserResult,
         },
         utils::pagination::PageInfo,
+        NodeType,
     },
 };
 
@@ -35,7 +40,7 @@ pub struct UserEdge {
 #[Object]
 impl UserEdge {
     async fn cursor(&self) -> ID {
-        id_encode("User", self.node.id).into()
+        id_encode(NodeType::User, self.node.id).into()
     }
     async fn node(&self) -> Option<User> {
         self.node.clone().into()
@@ -158,8 +163,10 @@ impl UserMutation {
             return Ok(e.into());
         }
 
-        let user_id = id_decode(&input.user_id)?;
+        let user_id = id_decode(&input.user_id, NodeType::User).map_err(|e| e.extend())?;
         follow(pool, viewer.id, user_id).await?;
+        // 通知に失敗してもフォローは成功させる
+        let _ = notify(pool, user_id, NotificationKind::Followed, viewer.id, None).await;
         let user = match get_user_from_id(pool, user_id).await? {
             Some(user) => user,
             None => {
@@ -183,7 +190,7 @@ impl UserMutation {
             None => return Err(async_graphql::Error::new("Please login")),
         };
 
-        let user_id = id_decode(&input.user_id)?;
+        let user_id = id_decode(&input.user_id, NodeType::User).map_err(|e| e.extend())?;
         unfollow(pool, viewer.id, user_id).await?;
         let user = match get_user_from_id(pool, user_id).await? {
             Some(user) => user,
@@ -195,4 +202,20 @@ impl UserMutation {
 
         Ok(UnfollowUserResult { user })
     }
+    /// カレンダーを購読するURLを発行する 発行済みの場合は作り直して古いURLを使えなくする
+    async fn reset_calendar_feed_url(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<ResetCalendarFeedUrlResult> {
+        let pool = get_db_pool(ctx).await?;
+        let viewer = match get_viewer(ctx).await {
+            Some(viewer) => viewer,
+            None => return Err(async_graphql::Error::new("Please login")),
+        };
+
+        let token = reset_calendar_token(pool, viewer.id).await?;
+        Ok(ResetCalendarFeedUrlResult {
+            calendar_feed_url: calendar_feed_url(&token),
+        })
+    }
 }
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use async_graphql::ID;
+use axum::{
+    extract::Path,
+    http::header,
+    response::{Html, IntoResponse, Response},
+    Extension,
+};
+use hyper::StatusCode;
+use sqlx::PgPool;
+
+use crate::{
+    config::API_ORIGIN,
+    graphql::{
+        id_decode, id_encode,
+        mail::sender::recruitment_url,
+        models::{
+            prefecture::get_prefecture,
+            recruitment::{get_recruitment, RecruitmentStatus},
+            sport::get_sport,
+            user::get_user_from_id,
+        },
+        utils::{html::escape, jst::to_jst},
+        NodeType,
+    },
+};
+
+// 説明に載せる詳細の最大文字数
+const DESCRIPTION_DETAIL_LENGTH: usize = 100;
+
+// LINEやTwitterでリンクのプレビューを表示するためのOGPを付けたページ
+// ブラウザで開いた場合はフロントエンドの募集のページに移動する 下書きは404にする
+pub async fn recruitment_share_page(
+    Extension(pool): Extension<Arc<PgPool>>,
+    Path(id): Path<String>,
+) -> Response {
+    let id = match id_decode(&ID::from(&id), NodeType::Recruitment) {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::error!("share page id decode failed: {:?}", e);
+            return not_found();
+        }
+    };
+
+    let recruitment = match get_recruitment(&pool, id).await {
+        Ok(Some(recruitment)) if recruitment.status != RecruitmentStatus::Draft => recruitment,
+        Ok(_) => return not_found(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let (sport, prefecture, user) = match tokio::try_join!(
+        get_sport(&pool, recruitment.sport_id),
+        get_prefecture(&pool, recruitment.prefecture_id),
+        get_user_from_id(&pool, recruitment.user_id),
+    ) {
+        Ok(result) => result,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    // 例: 「サッカー / 東京都 / 2024/01/01 10:00 / 山田さんの募集」の後に詳細の冒頭
+    let mut items = Vec::new();
+    if let Some(sport) = sport {
+        items.push(sport.name);
+    }
+    if let Some(prefecture) = prefecture {
+        items.push(prefecture.name);
+    }
+    if let Some(start_at) = recruitment.start_at {
+        items.push(to_jst(start_at).format("%Y/%m/%d %H:%M").to_string());
+    }
+    if let Some(ref user) = user {
+        items.push(format!("{}さんの募集", user.name));
+    }
+    let mut description = items.join(" / ");
+    if let Some(ref detail) = recruitment.detail {
+        // metaタグの中なので改行は空白にまとめる
+        let detail = detail.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let mut chars = detail.chars();
+        let head: String = chars.by_ref().take(DESCRIPTION_DETAIL_LENGTH).collect();
+        description.push(' ');
+        description.push_str(&head);
+        if chars.next().is_some() {
+            description.push('…');
+        }
+    }
+
+    let id = id_encode(NodeType::Recruitment, recruitment.id);
+    let values = [
+        ("title", escape(&recruitment.title)),
+        ("description", escape(&description)),
+        ("url", escape(&format!("{}/r/{}", API_ORIGIN, id))),
+        (
+            "image",
+            escape(
+                user.as_ref()
+                    .map(|user| user.avatar.as_str())
+                    .unwrap_or_default(),
+            ),
+        ),
+        ("app_url", escape(&recruitment_url(recruitment.id))),
+    ];
+    let html = render(include_str!("./template/recruitment.html"), &values);
+
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Html(html),
+    )
+        .into_response()
+}
+
+fn not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Html(String::from(
+            "<!DOCTYPE html><title>募集が見つかりませんでした</title>",
+        )),
+    )
+        .into_response()
+}
+
+// テンプレートの{name}を値に置き換える
+// 値に{name}が含まれていても置き換えないように1回の走査で置き換える
+fn render(template: &str, values: &[(&str, String)]) -> String {
+    let mut html = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        html.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                html.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                html.push('{');
+                rest = after;
+            }
+        }
+    }
+    html.push_str(rest);
+    html
+}
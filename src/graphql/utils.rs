@@ -1 +1,5 @@
+pub mod geo;
+pub mod html;
+pub mod jst;
 pub mod pagination;
+pub mod search;
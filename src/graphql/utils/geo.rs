@@ -0,0 +1,11 @@
+// earthdistanceのearth()と同じ地球の半径(m)
+const EARTH_RADIUS_M: f64 = 6378168.0;
+
+// 2点間の大円距離(km)を返す DBのearth_distanceと同じ値になる
+pub fn distance_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = (lng2 - lng1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin() / 1000.0
+}
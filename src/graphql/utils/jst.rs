@@ -0,0 +1,9 @@
+use chrono::{DateTime, FixedOffset, Local};
+
+const JST_OFFSET_SECONDS: i32 = 9 * 60 * 60;
+
+// サーバーのタイムゾーンに関係なく日本時間の日時にする
+pub fn to_jst(datetime: DateTime<Local>) -> DateTime<FixedOffset> {
+    let jst = FixedOffset::east_opt(JST_OFFSET_SECONDS).expect("JST offset is valid");
+    datetime.with_timezone(&jst)
+}
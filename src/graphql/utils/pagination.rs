@@ -1,7 +1,10 @@
+use std::{fmt::Display, str::FromStr};
+
 use anyhow::Result;
 use async_graphql::{Object, ID};
+use base64::{decode_config, encode_config, URL_SAFE};
 
-use crate::graphql::{id_decode, models::recruitment::RecruitmentStatus};
+use crate::graphql::{id_decode, models::recruitment::RecruitmentStatus, IdDecodeError, NodeType};
 
 //* SearchParams */
 #[derive(Debug)]
@@ -21,7 +24,8 @@ pub struct RecruitmentSearchParams {
 }
 
 impl SearchParams {
-    pub fn new(after: Option<ID>, first: Option<i32>) -> Result<Self> {
+    // node_typeはカーソルに含まれるタイプ
+    pub fn new(after: Option<ID>, first: Option<i32>, node_type: NodeType) -> Result<Self> {
         if let (Some(first), None) = (first, after.as_ref()) {
             let search_params = SearchParams {
                 use_after: false,
@@ -35,7 +39,7 @@ impl SearchParams {
             }
             let search_params = SearchParams {
                 use_after: true,
-                after: id_decode(after)? as i32,
+                after: id_decode(after, node_type)? as i32,
                 num_rows: first,
             };
             Ok(search_params)
@@ -67,7 +71,7 @@ impl RecruitmentSearchParams {
             }
             let search_params = RecruitmentSearchParams {
                 use_after: true,
-                after: id_decode(after)? as i32,
+                after: id_decode(after, NodeType::Recruitment)? as i32,
                 num_rows: first,
                 ..Default::default()
             };
@@ -88,6 +92,72 @@ impl RecruitmentSearchParams {
     }
 }
 
+//* SortedSearchParams */
+// 並び順の値とIDを組み合わせたカーソルでページングする
+// 並び順の値はスコアや距離ならf64、BIGINTの値ならi64にして精度を落とさないようにする
+#[derive(Debug, Default)]
+pub struct SortedSearchParams<K = f64> {
+    pub after: Option<(K, i64)>, // decodeしたカーソルの(並び順の値, id)を保持
+    pub num_rows: i32,           // 何件取得するかを保持
+}
+
+impl<K: FromStr> SortedSearchParams<K> {
+    pub fn new(after: Option<ID>, first: Option<i32>, node_type: NodeType) -> Result<Self> {
+        match (first, after) {
+            (Some(first), None) => Ok(SortedSearchParams {
+                after: None,
+                num_rows: first,
+            }),
+            (Some(first), Some(after)) => Ok(SortedSearchParams {
+                after: Some(sort_cursor_decode(&after, node_type)?),
+                num_rows: first,
+            }),
+            _ => {
+                tracing::error!("search params validation error");
+                Err(anyhow::anyhow!(
+                    "[first], [first, after] のいずれかの組み合わせで指定してください"
+                ))
+            }
+        }
+    }
+}
+
+// "Type:id:並び順の値"の形のカーソルを作る
+pub fn sort_cursor_encode(node_type: NodeType, id: i64, sort_key: impl Display) -> String {
+    encode_config(
+        format!("{}:{}:{}", node_type.as_str(), id, sort_key),
+        URL_SAFE,
+    )
+}
+
+pub fn sort_cursor_decode<K: FromStr>(
+    cursor: &ID,
+    node_type: NodeType,
+) -> Result<(K, i64), IdDecodeError> {
+    let bytes = decode_config(cursor.as_bytes(), URL_SAFE).map_err(|_| IdDecodeError::Malformed)?;
+    let s = String::from_utf8(bytes).map_err(|_| IdDecodeError::Malformed)?;
+    let mut split_cursor = s.splitn(3, ':');
+    match (
+        split_cursor.next(),
+        split_cursor.next(),
+        split_cursor.next(),
+    ) {
+        (Some(type_name), Some(id), Some(sort_key)) => {
+            if type_name != node_type.as_str() {
+                return Err(IdDecodeError::TypeMismatch {
+                    expected: node_type.as_str(),
+                });
+            }
+            let id = id.parse::<i64>().map_err(|_| IdDecodeError::Malformed)?;
+            let sort_key = sort_key
+                .parse::<K>()
+                .map_err(|_| IdDecodeError::Malformed)?;
+            Ok((sort_key, id))
+        }
+        _ => Err(IdDecodeError::Malformed),
+    }
+}
+
 //* PageInfo */
 #[derive(Debug, Clone, Default)]
 pub struct PageInfo {
@@ -112,3 +182,79 @@ impl PageInfo {
         self.has_previous_page
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(s: &str) -> ID {
+        ID::from(encode_config(s, URL_SAFE))
+    }
+
+    #[test]
+    fn sort_cursor_round_trip() {
+        let encoded = sort_cursor_encode(NodeType::Recruitment, 12, 1.5);
+        let decoded = sort_cursor_decode::<f64>(&ID::from(encoded), NodeType::Recruitment);
+        assert_eq!(decoded.unwrap(), (1.5, 12));
+    }
+
+    #[test]
+    fn sort_cursor_round_trip_with_integer_key() {
+        let encoded = sort_cursor_encode(NodeType::Notification, 3, i64::MAX);
+        let decoded = sort_cursor_decode::<i64>(&ID::from(encoded), NodeType::Notification);
+        assert_eq!(decoded.unwrap(), (i64::MAX, 3));
+    }
+
+    #[test]
+    fn sort_cursor_decode_invalid_base64() {
+        let decoded = sort_cursor_decode::<f64>(&ID::from("!!!"), NodeType::Recruitment);
+        assert!(matches!(decoded, Err(IdDecodeError::Malformed)));
+    }
+
+    #[test]
+    fn sort_cursor_decode_invalid_utf8() {
+        let decoded = sort_cursor_decode::<f64>(
+            &ID::from(encode_config([0xff, 0xfe], URL_SAFE)),
+            NodeType::Recruitment,
+        );
+        assert!(matches!(decoded, Err(IdDecodeError::Malformed)));
+    }
+
+    #[test]
+    fn sort_cursor_decode_missing_sort_key() {
+        let decoded = sort_cursor_decode::<f64>(&cursor("Recruitment:12"), NodeType::Recruitment);
+        assert!(matches!(decoded, Err(IdDecodeError::Malformed)));
+    }
+
+    #[test]
+    fn sort_cursor_decode_type_mismatch() {
+        let decoded = sort_cursor_decode::<f64>(&cursor("User:12:1.5"), NodeType::Recruitment);
+        assert!(matches!(
+            decoded,
+            Err(IdDecodeError::TypeMismatch {
+                expected: "Recruitment"
+            })
+        ));
+    }
+
+    #[test]
+    fn sort_cursor_decode_non_numeric_id() {
+        let decoded =
+            sort_cursor_decode::<f64>(&cursor("Recruitment:abc:1.5"), NodeType::Recruitment);
+        assert!(matches!(decoded, Err(IdDecodeError::Malformed)));
+    }
+
+    #[test]
+    fn sort_cursor_decode_non_numeric_sort_key() {
+        let decoded =
+            sort_cursor_decode::<f64>(&cursor("Recruitment:12:abc"), NodeType::Recruitment);
+        assert!(matches!(decoded, Err(IdDecodeError::Malformed)));
+    }
+
+    #[test]
+    fn sort_cursor_decode_fractional_integer_key() {
+        let decoded =
+            sort_cursor_decode::<i64>(&cursor("Notification:12:1.5"), NodeType::Notification);
+        assert!(matches!(decoded, Err(IdDecodeError::Malformed)));
+    }
+}
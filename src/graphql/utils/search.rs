@@ -0,0 +1,211 @@
+use super::html::escape;
+
+// 半角カタカナ(U+FF61〜U+FF9F)に対応する全角の文字
+const HALFWIDTH_KANA: [char; 63] = [
+    '。', '「', '」', '、', '・', 'ヲ', 'ァ', 'ィ', 'ゥ', 'ェ', 'ォ', 'ャ', 'ュ', 'ョ', 'ッ', 'ー',
+    'ア', 'イ', 'ウ', 'エ', 'オ', 'カ', 'キ', 'ク', 'ケ', 'コ', 'サ', 'シ', 'ス', 'セ', 'ソ', 'タ',
+    'チ', 'ツ', 'テ', 'ト', 'ナ', 'ニ', 'ヌ', 'ネ', 'ノ', 'ハ', 'ヒ', 'フ', 'ヘ', 'ホ', 'マ', 'ミ',
+    'ム', 'メ', 'モ', 'ヤ', 'ユ', 'ヨ', 'ラ', 'リ', 'ル', 'レ', 'ロ', 'ワ', 'ン', '゛', '゜',
+];
+
+// 濁点、半濁点を付けた文字を返す 付けられなければNone
+fn compose_voiced_mark(c: char, mark: char) -> Option<char> {
+    let code = c as u32;
+    match mark {
+        'ﾞ' => match c {
+            'ウ' => Some('ヴ'),
+            // カ〜ト、ハ〜ホは次のコードポイントが濁音
+            'カ'..='チ' if (code - 'カ' as u32) % 2 == 0 => char::from_u32(code + 1),
+            'ツ' | 'テ' | 'ト' => char::from_u32(code + 1),
+            'ハ'..='ホ' if (code - 'ハ' as u32) % 3 == 0 => char::from_u32(code + 1),
+            _ => None,
+        },
+        'ﾟ' => match c {
+            'ハ'..='ホ' if (code - 'ハ' as u32) % 3 == 0 => char::from_u32(code + 2),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn normalize_char(c: char) -> char {
+    let c = match c {
+        '\u{3000}' => ' ',
+        // 全角英数字、記号を半角にする
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        '\u{FF61}'..='\u{FF9F}' => HALFWIDTH_KANA[(c as u32 - 0xFF61) as usize],
+        _ => c,
+    };
+    match c {
+        // カタカナをひらがなにする
+        'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c.to_lowercase().next().unwrap_or(c),
+    }
+}
+
+// DBのsearch_normalizeと同じように正規化する
+// 正規化後の文字と元の文字列での文字の位置を返す
+pub fn normalize_with_offsets(s: &str) -> Vec<(char, usize)> {
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut normalized = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        // 半角カタカナの濁点、半濁点は前の文字と合成する
+        if let Some(&mark) = chars.get(i + 1) {
+            if let Some(composed) = HALFWIDTH_KANA
+                .get((c as u32).wrapping_sub(0xFF61) as usize)
+                .and_then(|&kana| compose_voiced_mark(kana, mark))
+            {
+                normalized.push((normalize_char(composed), i));
+                i += 2;
+                continue;
+            }
+        }
+        normalized.push((normalize_char(c), i));
+        i += 1;
+    }
+    normalized
+}
+
+pub fn normalize(s: &str) -> String {
+    normalize_with_offsets(s).iter().map(|(c, _)| c).collect()
+}
+
+// キーワードに一致した箇所を<mark>で囲んだ抜粋を返す 一致しなければNone
+// 抜粋以外の部分はHTMLエスケープする
+pub fn highlight(text: &str, keyword: &str, max_chars: usize) -> Option<String> {
+    let chars = text.chars().collect::<Vec<char>>();
+    let normalized = normalize_with_offsets(text);
+    let words = keyword
+        .split_whitespace()
+        .map(|word| normalize(word).chars().collect::<Vec<char>>())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<Vec<char>>>();
+
+    // 元の文字列での一致範囲 [start, end)
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < normalized.len() {
+        let matched = words.iter().find(|word| {
+            normalized.len() - i >= word.len()
+                && word.iter().zip(&normalized[i..]).all(|(w, (c, _))| w == c)
+        });
+        match matched {
+            Some(word) => {
+                let start = normalized[i].1;
+                let end = match normalized.get(i + word.len()) {
+                    Some((_, offset)) => *offset,
+                    None => chars.len(),
+                };
+                ranges.push((start, end));
+                i += word.len();
+            }
+            None => i += 1,
+        }
+    }
+    let first = ranges.first()?;
+
+    // 最初に一致した箇所が抜粋の先頭付近にくるようにする
+    let start = first.0.saturating_sub(max_chars / 4);
+    let end = usize::min(start + max_chars, chars.len());
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut cursor = start;
+    for &(match_start, match_end) in ranges.iter() {
+        if match_start < cursor || match_end > end {
+            continue;
+        }
+        snippet.push_str(&escape(
+            &chars[cursor..match_start].iter().collect::<String>(),
+        ));
+        snippet.push_str("<mark>");
+        snippet.push_str(&escape(
+            &chars[match_start..match_end].iter().collect::<String>(),
+        ));
+        snippet.push_str("</mark>");
+        cursor = match_end;
+    }
+    snippet.push_str(&escape(&chars[cursor..end].iter().collect::<String>()));
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_fullwidth_ascii() {
+        assert_eq!(normalize("ＦＣ　１２３"), "fc 123");
+    }
+
+    #[test]
+    fn normalize_katakana_to_hiragana() {
+        assert_eq!(normalize("サッカー"), "さっかー");
+    }
+
+    #[test]
+    fn normalize_halfwidth_kana() {
+        assert_eq!(normalize("ｻｯｶｰ"), "さっかー");
+    }
+
+    #[test]
+    fn normalize_halfwidth_kana_with_voiced_marks() {
+        assert_eq!(normalize("ﾊﾞｽｹ ﾊﾟｽ"), "ばすけ ぱす");
+    }
+
+    #[test]
+    fn normalize_with_offsets_points_to_original_chars() {
+        assert_eq!(normalize_with_offsets("ﾊﾞｽ"), vec![('ば', 0), ('す', 2)]);
+    }
+
+    #[test]
+    fn highlight_katakana_with_hiragana_keyword() {
+        assert_eq!(
+            highlight("サッカーをします", "さっかー", 100).as_deref(),
+            Some("<mark>サッカー</mark>をします")
+        );
+    }
+
+    #[test]
+    fn highlight_fullwidth_text() {
+        assert_eq!(
+            highlight("ＦＣ東京の試合", "fc", 100).as_deref(),
+            Some("<mark>ＦＣ</mark>東京の試合")
+        );
+    }
+
+    #[test]
+    fn highlight_halfwidth_kana_text() {
+        assert_eq!(
+            highlight("ﾊﾞｽｹ募集", "バスケ", 100).as_deref(),
+            Some("<mark>ﾊﾞｽｹ</mark>募集")
+        );
+    }
+
+    #[test]
+    fn highlight_escapes_html() {
+        assert_eq!(
+            highlight("<b>サッカー</b>", "サッカー", 100).as_deref(),
+            Some("&lt;b&gt;<mark>サッカー</mark>&lt;/b&gt;")
+        );
+    }
+
+    #[test]
+    fn highlight_trims_long_text() {
+        assert_eq!(
+            highlight("あいうえおかきくけこサッカー", "サッカー", 8).as_deref(),
+            Some("…けこ<mark>サッカー</mark>")
+        );
+    }
+
+    #[test]
+    fn highlight_without_match() {
+        assert_eq!(highlight("バスケ募集", "サッカー", 100), None);
+    }
+}
@@ -1,20 +1,26 @@
-use async_graphql::{EmptySubscription, Schema};
-use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use async_graphql::{http::ALL_WEBSOCKET_PROTOCOLS, Data, Schema};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
 use axum::{
+    extract::{ConnectInfo, WebSocketUpgrade},
     http::{
         header::{self, HeaderMap},
         HeaderValue, Method,
     },
+    response::Response,
     routing::{get, post},
     Extension, Router, Server,
 };
 use sqlx::PgPool;
-use std::{sync::Arc, *};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    *,
+};
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::fmt::format::FmtSpan;
 
 use self::graphql::loader::Loaders;
-use self::graphql::{GraphqlSchema, Mutation, Query};
+use self::graphql::{broker::Broker, GraphqlSchema, Mutation, Query, Subscription};
 use crate::graphql::auth::{
     cookie::get_value_from_cookie,
     external::{
@@ -23,11 +29,19 @@ use crate::graphql::auth::{
     },
     jwt::get_user_from_token,
 };
+use crate::graphql::feed::{
+    ical::{recruitment_ical, user_calendar_ical},
+    syndication::{atom_feed, rss_feed},
+};
+use crate::graphql::mail::unsubscribe::{unsubscribe, unsubscribe_page};
+use crate::graphql::models::recruitment_view::ViewFingerprint;
+use crate::graphql::share::recruitment::recruitment_share_page;
 pub mod config;
 mod database;
 mod graphql;
+mod scheduler;
 
-use config::get_config;
+use config::{get_config, FRONTEND_ORIGIN};
 use database::pool;
 
 async fn graphql_handler(
@@ -35,6 +49,7 @@ async fn graphql_handler(
     req: GraphQLRequest,
     headers: HeaderMap,
     Extension(pool): Extension<Arc<PgPool>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
 ) -> GraphQLResponse {
     let mut req = req.into_inner();
     if let Some(token) = get_value_from_cookie(&headers, "token") {
@@ -43,9 +58,85 @@ async fn graphql_handler(
         req = req.data(user);
     }
 
+    let ip = client_ip(&headers, addr.ip(), &get_config().proxy.trusted_ips);
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    req = req.data(ViewFingerprint::new(&ip.to_string(), user_agent));
+
     schema.execute(req).await.into()
 }
 
+// 接続元のIPアドレス X-Forwarded-Forはクライアントが自由に付けられるので信用するプロキシからの接続のみ使う
+// プロキシは受け取った接続元を末尾に追加するので、末尾から信用するプロキシを飛ばした最初のアドレスを使う
+fn client_ip(headers: &HeaderMap, peer: IpAddr, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|ip| ip.trim().parse::<IpAddr>())
+        .collect::<Vec<_>>();
+    let mut client = peer;
+    for ip in forwarded.into_iter().rev() {
+        match ip {
+            Ok(ip) if trusted_proxies.contains(&client) => client = ip,
+            // 形式が正しくないアドレスより前はクライアントが付けた値なので使わない
+            _ => break,
+        }
+    }
+    client
+}
+
+// 購読者に配信しきれていないイベントを保持する数
+const BROKER_CAPACITY: usize = 1024;
+
+async fn graphql_ws_handler(
+    Extension(schema): Extension<GraphqlSchema>,
+    Extension(pool): Extension<Arc<PgPool>>,
+    protocol: GraphQLProtocol,
+    websocket: WebSocketUpgrade,
+    headers: HeaderMap,
+) -> Response {
+    // 他のサイトからcookieを使って接続されないようにフロントエンドからの接続のみcookieを使う
+    let cookie_token = match headers.get(header::ORIGIN) {
+        Some(origin) if origin == FRONTEND_ORIGIN => get_value_from_cookie(&headers, "token"),
+        _ => None,
+    };
+
+    websocket
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .on_connection_init(move |payload| on_connection_init(pool, cookie_token, payload))
+                .serve()
+        })
+}
+
+// connection_initのpayloadの{"token": "..."}を優先してcookieのトークンでログインユーザーを取得する
+async fn on_connection_init(
+    pool: Arc<PgPool>,
+    cookie_token: Option<String>,
+    payload: serde_json::Value,
+) -> async_graphql::Result<Data> {
+    let payload_token = payload
+        .get("token")
+        .and_then(|token| token.as_str())
+        .map(|token| token.to_string());
+
+    let mut data = Data::default();
+    if let Some(token) = payload_token.or(cookie_token) {
+        let user = get_user_from_token(&pool, token).await;
+        // ctx.data::<Option<User>>でログインユーザにアクセスできる
+        data.insert(user);
+    }
+    Ok(data)
+}
+
 // todo unwrap使わない
 #[tokio::main]
 async fn main() {
@@ -60,22 +151,37 @@ async fn main() {
         let pool = Arc::new(pool);
         let google_auth_client = Arc::new(new_google_auth_client(&config.google).await.unwrap());
         let line_auth_client = Arc::new(new_line_auth_client(&config.line).await.unwrap());
+        scheduler::spawn(Arc::clone(&pool));
         let loaders = Loaders::new(&pool);
-        let schema = Schema::build(Query::default(), Mutation::default(), EmptySubscription)
-            .data(Arc::clone(&pool))
-            .data(loaders)
-            .data(config)
-            .finish();
+        let broker = Broker::new(BROKER_CAPACITY);
+        let schema = Schema::build(
+            Query::default(),
+            Mutation::default(),
+            Subscription::default(),
+        )
+        .data(Arc::clone(&pool))
+        .data(loaders)
+        .data(broker)
+        .data(config)
+        .finish();
 
         let app = Router::new()
             .route("/graphql", post(graphql_handler))
+            .route("/graphql/ws", get(graphql_ws_handler))
             .route("/oauth/google", get(auth_google_redirect))
             .route("/oauth/google/callback", get(auth_google_callback))
             .route("/oauth/line", get(auth_line_redirect))
             .route("/oauth/line/callback", get(auth_line_callback))
+            .route("/unsubscribe", get(unsubscribe_page).post(unsubscribe))
+            // axumのパスは拡張子だけを固定できないので.icsはハンドラで取り除く
+            .route("/ical/recruitments/:file", get(recruitment_ical))
+            .route("/ical/users/:file", get(user_calendar_ical))
+            .route("/feeds/recruitments.atom", get(atom_feed))
+            .route("/feeds/recruitments.rss", get(rss_feed))
+            .route("/r/:id", get(recruitment_share_page))
             .layer(
                 CorsLayer::new()
-                    .allow_origin("http://localhost:5173".parse::<HeaderValue>().unwrap())
+                    .allow_origin(FRONTEND_ORIGIN.parse::<HeaderValue>().unwrap())
                     .allow_headers(vec![
                         header::ACCEPT,
                         header::ACCEPT_LANGUAGE,
@@ -93,7 +199,7 @@ async fn main() {
             .layer(Extension(pool));
 
         Server::bind(&"0.0.0.0:8080".parse().unwrap())
-            .serve(app.into_make_service())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .await
             .unwrap();
     };
@@ -0,0 +1,156 @@
+use chrono::Local;
+use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
+
+use crate::graphql::{
+    mail::sender::send_digest,
+    models::{
+        digest::{claim_due_digest_users, get_digest, release_digest_claim},
+        notification::notify_closing_stocks,
+        notification_setting::DigestFrequency,
+        recruitment::close_expired,
+        recruitment_view::rollup_views,
+    },
+};
+
+// 掲載期限切れの募集を締め切る間隔
+const CLOSE_EXPIRED_INTERVAL: Duration = Duration::from_secs(60);
+// 1回のクエリで締め切る募集の最大数 ロックを長く持たないように小分けにする
+const CLOSE_EXPIRED_BATCH_SIZE: i64 = 100;
+// まとめメールを送るユーザーを確認する間隔
+const DIGEST_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// 1回のクエリで取得するまとめメールを送るユーザーの最大数
+const DIGEST_BATCH_SIZE: i64 = 100;
+// 閲覧数を集計する間隔
+const VIEW_ROLLUP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// サーバーと同じプロセスで定期実行する処理を起動する
+pub fn spawn(pool: Arc<PgPool>) {
+    let digest_pool = Arc::clone(&pool);
+    let rollup_pool = Arc::clone(&pool);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLOSE_EXPIRED_INTERVAL);
+        // 処理が遅れても溜まった分をまとめて実行しない
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            close_expired_recruitments(&pool).await;
+            notify_closing_stocks_in_app(&pool).await;
+        }
+    });
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DIGEST_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            for frequency in [DigestFrequency::Daily, DigestFrequency::Weekly] {
+                send_digests(&digest_pool, frequency).await;
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(VIEW_ROLLUP_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = rollup_views(&rollup_pool).await {
+                tracing::error!("rollup views failed: {:?}", e);
+            }
+        }
+    });
+}
+
+// 締め切る募集がなくなるまでバッチごとに締め切る
+async fn close_expired_recruitments(pool: &PgPool) {
+    let mut closed_count = 0;
+    loop {
+        match close_expired(pool, CLOSE_EXPIRED_BATCH_SIZE).await {
+            Ok(recruitments) => {
+                closed_count += recruitments.len();
+                if (recruitments.len() as i64) < CLOSE_EXPIRED_BATCH_SIZE {
+                    break;
+                }
+            }
+            Err(e) => {
+                tracing::error!("close expired recruitments failed: {:?}", e);
+                break;
+            }
+        }
+    }
+    if closed_count > 0 {
+        tracing::info!("closed {} expired recruitments", closed_count);
+    }
+}
+
+// 締め切りが近くなったストックした募集をアプリ内で通知する
+async fn notify_closing_stocks_in_app(pool: &PgPool) {
+    match notify_closing_stocks(pool).await {
+        Ok(count) if count > 0 => {
+            tracing::info!("notified {} closing stocks", count);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!("notify closing stocks failed: {:?}", e);
+        }
+    }
+}
+
+// 前回から頻度の期間が過ぎたユーザーにまとめメールを送る
+// 他のサーバーと二重に送らないように送る前にユーザーを確保する
+// 送る内容がなくても送ったことにして次の期間まで確認しない
+async fn send_digests(pool: &PgPool, frequency: DigestFrequency) {
+    let period = match frequency.period() {
+        Some(period) => period,
+        None => return,
+    };
+    let now = Local::now();
+    let mut sent_count = 0;
+    loop {
+        let users =
+            match claim_due_digest_users(pool, frequency, now - period, now, DIGEST_BATCH_SIZE)
+                .await
+            {
+                Ok(users) => users,
+                Err(e) => {
+                    tracing::error!("claim due digest users failed: {:?}", e);
+                    break;
+                }
+            };
+
+        let mut delivered_count = 0;
+        for (user, last_delivered_at) in users.iter() {
+            let since = last_delivered_at.unwrap_or(now - period);
+            let result = match get_digest(pool, user.id, frequency, since, now).await {
+                Ok(digest) if digest.is_empty() => Ok(false),
+                Ok(digest) => send_digest(user, frequency, &digest).await.map(|_| true),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(sent) => {
+                    delivered_count += 1;
+                    if sent {
+                        sent_count += 1;
+                    }
+                }
+                // 送れなかった場合は確保を取り消して次の確認で送り直す
+                Err(e) => {
+                    tracing::error!("send digest failed: {:?}", e);
+                    if let Err(e) =
+                        release_digest_claim(pool, user.id, frequency, now, *last_delivered_at)
+                            .await
+                    {
+                        tracing::error!("release digest claim failed: {:?}", e);
+                    }
+                }
+            }
+        }
+
+        // 全員失敗した場合は同じユーザーを取得し続けるので次の確認まで待つ
+        if (users.len() as i64) < DIGEST_BATCH_SIZE || delivered_count == 0 {
+            break;
+        }
+    }
+    if sent_count > 0 {
+        tracing::info!("sent {} {:?} digests", sent_count, frequency);
+    }
+}